@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+
+use serenity::{
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{channel::Message, id::ChannelId},
+    prelude::*,
+    utils::Color,
+};
+
+use crate::{
+    module::Module,
+    types::{CommandGroups, StorageContainer},
+};
+
+/// Commands that can never be disabled, so the bot can always be fixed from Discord.
+const ALWAYS_ENABLED: &[&str] = &["command", "enable", "disable", "help"];
+
+#[group]
+#[commands(command)]
+struct Admin;
+
+/// Guild administration commands.
+pub struct AdminModule;
+
+impl Module for AdminModule {
+    fn name(&self) -> &'static str {
+        "admin"
+    }
+
+    fn groups(&self) -> Vec<&'static CommandGroup> {
+        vec![&ADMIN_GROUP]
+    }
+}
+
+/// Enable or disable commands and command groups in this guild.
+///
+/// Without arguments it shows what is disabled in this guild and channel.
+///
+/// A setting for a channel overrides the guild setting, so `command disable fortune`
+/// followed by `command enable fortune #memes` only allows `fortune` in `#memes`.
+#[command]
+#[only_in(guild)]
+#[required_permissions("MANAGE_GUILD")]
+#[sub_commands(enable, disable)]
+#[usage = "command <enable | disable> <COMMAND | GROUP> [#CHANNEL]"]
+async fn command(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let storage = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(s) => s.clone(),
+        None => {
+            msg.reply(&ctx, "There was a problem getting the storage")
+                .await?;
+            return Ok(());
+        },
+    };
+
+    let (guild_disabled, channel_disabled, channel_enabled) = {
+        let storage = storage.read().await;
+        match storage.guild(guild_id) {
+            Some(guild) => {
+                let (disabled, enabled) = match guild.channels.get(&msg.channel_id.0) {
+                    Some(rules) => (sorted(&rules.disabled), sorted(&rules.enabled)),
+                    None => (Vec::new(), Vec::new()),
+                };
+                (sorted(&guild.disabled), disabled, enabled)
+            },
+            None => (Vec::new(), Vec::new(), Vec::new()),
+        }
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("COMMANDS")
+                    .color(Color::BLUE)
+                    .field(
+                        "Disabled in this guild",
+                        list_or_none(&guild_disabled),
+                        false,
+                    )
+                    .field(
+                        "Disabled in this channel",
+                        list_or_none(&channel_disabled),
+                        false,
+                    )
+                    .field(
+                        "Enabled in this channel",
+                        list_or_none(&channel_enabled),
+                        false,
+                    )
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Enable a command or command group in this guild, or only in a channel.
+#[command]
+#[only_in(guild)]
+#[required_permissions("MANAGE_GUILD")]
+#[min_args(1)]
+#[max_args(2)]
+#[usage = "command enable <COMMAND | GROUP> [#CHANNEL]"]
+async fn enable(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    set_enabled(ctx, msg, args, true).await
+}
+
+/// Disable a command or command group in this guild, or only in a channel.
+#[command]
+#[only_in(guild)]
+#[required_permissions("MANAGE_GUILD")]
+#[min_args(1)]
+#[max_args(2)]
+#[usage = "command disable <COMMAND | GROUP> [#CHANNEL]"]
+async fn disable(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    set_enabled(ctx, msg, args, false).await
+}
+
+async fn set_enabled(ctx: &Context, msg: &Message, mut args: Args, enabled: bool) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let name = args.single::<String>().unwrap_or_default().to_lowercase();
+    let channel_id = match args.single::<ChannelId>() {
+        Ok(id) => Some(id),
+        Err(_) if args.is_empty() => None,
+        Err(_) => {
+            reply_embed(ctx, msg, Color::RED, "Invalid channel.").await?;
+            return Ok(());
+        },
+    };
+
+    let (known, protected, storage) = {
+        let data = ctx.data.read().await;
+        let (known, protected) = match data.get::<CommandGroups>() {
+            Some(groups) => (
+                groups.contains_key(&name) || groups.values().any(|chain| chain.contains(&name)),
+                always_enabled_in(groups, &name),
+            ),
+            None => (false, None),
+        };
+        (known, protected, data.get::<StorageContainer>().cloned())
+    };
+
+    if !known {
+        reply_embed(
+            ctx,
+            msg,
+            Color::RED,
+            &format!("Unknown command or group `{}`.", name),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    if !enabled && ALWAYS_ENABLED.contains(&name.as_str()) {
+        reply_embed(
+            ctx,
+            msg,
+            Color::RED,
+            &format!("`{}` can't be disabled.", name),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    if let (false, Some(command)) = (enabled, protected) {
+        reply_embed(
+            ctx,
+            msg,
+            Color::RED,
+            &format!(
+                "`{}` can't be disabled, it has `{}` that must stay enabled.",
+                name, command
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let storage = match storage {
+        Some(s) => s,
+        None => {
+            msg.reply(&ctx, "There was a problem getting the storage")
+                .await?;
+            return Ok(());
+        },
+    };
+
+    {
+        let mut storage = storage.write().await;
+        storage
+            .guild_mut(guild_id)
+            .set_enabled(&name, channel_id, enabled);
+        storage.save()?;
+    }
+
+    let state = if enabled { "enabled" } else { "disabled" };
+    let place = match channel_id {
+        Some(id) => format!("in <#{}>", id),
+        None => "in this guild".to_string(),
+    };
+    reply_embed(
+        ctx,
+        msg,
+        Color::TEAL,
+        &format!("`{}` is now {} {}.", name, state, place),
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn reply_embed(ctx: &Context, msg: &Message, color: Color, text: &str) -> CommandResult {
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| e.title(" ").color(color).description(text))
+        })
+        .await?;
+
+    Ok(())
+}
+
+fn sorted(names: &std::collections::HashSet<String>) -> Vec<String> {
+    let mut names = names.iter().cloned().collect::<Vec<_>>();
+    names.sort_unstable();
+    names
+}
+
+fn list_or_none(names: &[String]) -> String {
+    if names.is_empty() {
+        "None".to_string()
+    } else {
+        format!("`{}`", names.join("`, `"))
+    }
+}
+
+/// The always enabled command that disabling the group or command `name` would disable.
+fn always_enabled_in(groups: &HashMap<String, Vec<String>>, name: &str) -> Option<&'static str> {
+    ALWAYS_ENABLED.iter().copied().find(|command| {
+        groups
+            .get(*command)
+            .map_or(false, |chain| chain.iter().any(|parent| parent == name))
+    })
+}
+
+/// Whether `command_name` and the groups containing it are enabled in the channel of
+/// `msg`.
+///
+/// Commands can only be disabled in guilds.
+pub async fn is_enabled(ctx: &Context, msg: &Message, command_name: &str) -> bool {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return true,
+    };
+    let data = ctx.data.read().await;
+
+    let mut names = vec![command_name.to_lowercase()];
+    if let Some(parents) = data
+        .get::<CommandGroups>()
+        .and_then(|groups| groups.get(&names[0]))
+    {
+        names.extend(parents.iter().cloned());
+    }
+    let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+
+    match data.get::<StorageContainer>() {
+        Some(storage) => match storage.read().await.guild(guild_id) {
+            Some(guild) => guild.is_enabled(msg.channel_id, &names),
+            None => true,
+        },
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::id::GuildId;
+
+    use crate::testing::{message, MockDiscord};
+
+    use super::*;
+
+    const GUILD: u64 = 30;
+    const CHANNEL: u64 = 20;
+
+    async fn mock() -> MockDiscord {
+        let mock = MockDiscord::start().await;
+        let mut groups = HashMap::new();
+        groups.insert("fortune".to_string(), vec!["meme".to_string()]);
+        groups.insert("ping".to_string(), vec!["meme".to_string()]);
+        groups.insert("command".to_string(), vec!["admin".to_string()]);
+        groups.insert("disable".to_string(), vec![
+            "command".to_string(),
+            "admin".to_string(),
+        ]);
+        mock.insert::<CommandGroups>(groups).await;
+        mock
+    }
+
+    #[tokio::test]
+    async fn disable_is_persisted() {
+        let mock = mock().await;
+        let msg = message("!command disable fortune", 10, Some(GUILD), CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        let data = mock.ctx.data.read().await;
+        let storage = data.get::<StorageContainer>().unwrap().read().await;
+        let guild = storage.guild(GuildId(GUILD)).unwrap();
+        assert!(!guild.is_enabled(ChannelId(CHANNEL), &["fortune", "meme"]));
+        assert!(guild.is_enabled(ChannelId(CHANNEL), &["ping", "meme"]));
+    }
+
+    #[tokio::test]
+    async fn disabled_commands_are_not_run() {
+        let mock = mock().await;
+        let disable = message("!command disable ping", 10, Some(GUILD), CHANNEL);
+        mock.send(&disable).await.unwrap();
+        let sent = mock.requests().len();
+
+        let refused = mock.send(&message("!ping", 10, Some(GUILD), CHANNEL)).await;
+
+        assert!(refused.unwrap_err().to_string().contains("before hook"));
+        assert_eq!(mock.requests().len(), sent);
+    }
+
+    #[tokio::test]
+    async fn unknown_commands_are_refused() {
+        let mock = mock().await;
+        let msg = message("!command disable nope", 10, Some(GUILD), CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        let embeds = mock.sent_embeds();
+        assert_eq!(embeds[0]["description"], "Unknown command or group `nope`.");
+        let data = mock.ctx.data.read().await;
+        let storage = data.get::<StorageContainer>().unwrap().read().await;
+        assert!(storage.guild(GuildId(GUILD)).is_none());
+    }
+
+    #[tokio::test]
+    async fn groups_with_always_enabled_commands_are_refused() {
+        let mock = mock().await;
+        for name in &["admin", "command"] {
+            let content = format!("!command disable {}", name);
+            let msg = message(&content, 10, Some(GUILD), CHANNEL);
+            mock.send(&msg).await.unwrap();
+        }
+
+        let embeds = mock.sent_embeds();
+        assert_eq!(
+            embeds[0]["description"],
+            "`admin` can't be disabled, it has `command` that must stay enabled."
+        );
+        assert_eq!(embeds[1]["description"], "`command` can't be disabled.");
+        let data = mock.ctx.data.read().await;
+        let storage = data.get::<StorageContainer>().unwrap().read().await;
+        assert!(storage.guild(GuildId(GUILD)).is_none());
+    }
+}
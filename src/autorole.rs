@@ -0,0 +1,721 @@
+use std::{collections::HashMap, convert::TryFrom, time::Duration};
+
+use serenity::{
+    async_trait,
+    client::bridge::gateway::GatewayIntents,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{
+        channel::Message,
+        guild::{Member, Role},
+        id::{GuildId, RoleId, UserId},
+        user::User,
+    },
+    prelude::*,
+    utils::Colour as Color,
+};
+
+use time::OffsetDateTime;
+
+use crate::{
+    config, duration, management,
+    module::Module,
+    reply::{Reply, RoleChange},
+    storage::{AutoRoles, TaskAction},
+    types::StorageContainer,
+};
+
+/// Longest wait after a member joined before giving the roles.
+const MAX_DELAY: Duration = Duration::from_secs(7 * 86_400);
+
+#[group]
+#[commands(autorole)]
+struct AutoRole;
+
+/// Roles given to members when they join, and self-assigned roles given back when they
+/// rejoin.
+///
+/// Needs the privileged server members intent to be told about joins and passed
+/// screenings.
+pub struct AutoRoleModule;
+
+#[async_trait]
+impl Module for AutoRoleModule {
+    fn name(&self) -> &'static str {
+        "autorole"
+    }
+
+    fn groups(&self) -> Vec<&'static CommandGroup> {
+        vec![&AUTOROLE_GROUP]
+    }
+
+    fn intents(&self) -> GatewayIntents {
+        GatewayIntents::GUILD_MEMBERS
+    }
+
+    async fn guild_member_addition(&self, ctx: &Context, guild_id: GuildId, member: &Member) {
+        let storage = match ctx.data.read().await.get::<StorageContainer>() {
+            Some(s) => s.clone(),
+            None => return,
+        };
+
+        if member.pending {
+            let mut storage = storage.write().await;
+            if storage
+                .guild(guild_id)
+                .map_or(false, |g| g.auto_roles.screening)
+            {
+                let user = member.user.id.0;
+                storage.guild_mut(guild_id).auto_roles.waiting.insert(user);
+                if let Err(why) = storage.save() {
+                    eprintln!("Failed to save the storage: {}", why);
+                }
+                return;
+            }
+        }
+
+        give_join_roles(ctx, guild_id, member.user.id).await;
+    }
+
+    async fn guild_member_update(&self, ctx: &Context, _old: Option<&Member>, new: &Member) {
+        if new.pending {
+            return;
+        }
+
+        let storage = match ctx.data.read().await.get::<StorageContainer>() {
+            Some(s) => s.clone(),
+            None => return,
+        };
+
+        let passed = {
+            let mut storage = storage.write().await;
+            let passed = match storage.guilds.get_mut(&new.guild_id.0) {
+                Some(guild) => guild.auto_roles.waiting.remove(&new.user.id.0),
+                None => false,
+            };
+            if passed {
+                if let Err(why) = storage.save() {
+                    eprintln!("Failed to save the storage: {}", why);
+                }
+            }
+            passed
+        };
+
+        if passed {
+            give_join_roles(ctx, new.guild_id, new.user.id).await;
+        }
+    }
+
+    async fn guild_member_removal(
+        &self, ctx: &Context, guild_id: GuildId, user: &User, _member: Option<&Member>,
+    ) {
+        let storage = match ctx.data.read().await.get::<StorageContainer>() {
+            Some(s) => s.clone(),
+            None => return,
+        };
+
+        let mut storage = storage.write().await;
+        if storage.guild(guild_id).is_none() {
+            return;
+        }
+
+        let (guild, user) = (guild_id.0, user.id.0);
+        storage.guild_mut(guild_id).auto_roles.waiting.remove(&user);
+        storage.tasks.retain(|t| match t.action {
+            TaskAction::AddRole {
+                guild: g, user: u, ..
+            } => (g, u) != (guild, user),
+            _ => true,
+        });
+        if let Err(why) = storage.save() {
+            eprintln!("Failed to save the storage: {}", why);
+        }
+    }
+}
+
+/// Show the roles given to members when they join.
+///
+/// Use the subcommands to change them.
+#[command]
+#[only_in(guild)]
+#[required_permissions("MANAGE_ROLES")]
+#[sub_commands(add, rm, delay, screening, restore)]
+#[usage = "autorole <add | rm> <ROLES ...>` or `autorole delay <DURATION | off>` or `autorole \
+           <screening | restore> <on | off>"]
+async fn autorole(ctx: &Context, msg: &Message) -> CommandResult {
+    change_settings(ctx, msg, |_| Ok(())).await
+}
+
+/// Give roles to members when they join.
+///
+/// Roles can be mentioned, or given by id or by name.
+#[command]
+#[only_in(guild)]
+#[required_permissions("MANAGE_ROLES")]
+#[min_args(1)]
+#[usage = "autorole add <ROLES ...>"]
+async fn add(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let roles = match find_roles(ctx, msg, args).await {
+        Ok(roles) => roles,
+        Err(reply) => return reply.send(ctx, msg).await,
+    };
+
+    if let Some(guild) = msg.guild(&ctx.cache).await {
+        let bot_position = management::bot_position(&guild, ctx.cache.current_user_id().await);
+        let author_position = if guild.owner_id == msg.author.id {
+            None
+        } else {
+            // A member that can't be fetched can't give any role
+            let member_roles = match guild.members.get(&msg.author.id) {
+                Some(member) => member.roles.clone(),
+                None => guild
+                    .member(ctx, msg.author.id)
+                    .await
+                    .map(|m| m.roles)
+                    .unwrap_or_default(),
+            };
+            Some(management::top_position(&guild.roles, &member_roles))
+        };
+
+        let problems = auto_role_problems(&roles, &guild.roles, bot_position, author_position);
+        if !problems.is_empty() {
+            return Reply::embed(" ", Color::RED, problems.join("\n"))
+                .send(ctx, msg)
+                .await;
+        }
+    }
+
+    change_settings(ctx, msg, |auto| {
+        for role in roles {
+            if !auto.roles.contains(&role.0) {
+                auto.roles.push(role.0);
+            }
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Stop giving roles to members when they join.
+#[command]
+#[only_in(guild)]
+#[required_permissions("MANAGE_ROLES")]
+#[aliases(remove)]
+#[min_args(1)]
+#[usage = "autorole rm <ROLES ...>"]
+async fn rm(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let roles = match find_roles(ctx, msg, args).await {
+        Ok(roles) => roles,
+        Err(reply) => return reply.send(ctx, msg).await,
+    };
+
+    change_settings(ctx, msg, |auto| {
+        auto.roles.retain(|r| !roles.contains(&RoleId(*r)));
+        Ok(())
+    })
+    .await
+}
+
+/// Wait a while, at most a week, after a member joined before giving the roles.
+#[command]
+#[only_in(guild)]
+#[required_permissions("MANAGE_ROLES")]
+#[num_args(1)]
+#[usage = "autorole delay <DURATION | off>"]
+#[example = "autorole delay 10m"]
+async fn delay(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let arg = args.rest().trim().to_string();
+
+    change_settings(ctx, msg, |auto| {
+        auto.delay = if arg == "off" {
+            None
+        } else {
+            match duration::parse(&arg) {
+                Some(duration) if duration <= MAX_DELAY => Some(duration.as_secs()),
+                Some(_) => return Err("The delay can be at most a week.".to_string()),
+                None => return Err(format!("Invalid duration: `{}`", arg)),
+            }
+        };
+        Ok(())
+    })
+    .await
+}
+
+/// Wait until members passed the membership screening before giving the roles.
+#[command]
+#[only_in(guild)]
+#[required_permissions("MANAGE_ROLES")]
+#[num_args(1)]
+#[usage = "autorole screening <on | off>"]
+async fn screening(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let enabled = on_off(args.rest());
+
+    change_settings(ctx, msg, |auto| {
+        auto.screening = enabled?;
+        if !auto.screening {
+            auto.waiting.clear();
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Give back their self-assigned roles to members who rejoin.
+///
+/// Only the roles of the catalog they gave themselves with `role add` are given back.
+#[command]
+#[only_in(guild)]
+#[required_permissions("MANAGE_ROLES")]
+#[num_args(1)]
+#[usage = "autorole restore <on | off>"]
+async fn restore(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let enabled = on_off(args.rest());
+
+    change_settings(ctx, msg, |auto| {
+        auto.restore = enabled?;
+        Ok(())
+    })
+    .await
+}
+
+/// Apply `change` to the auto roles of the guild and reply with the new settings.
+///
+/// An error from `change` is the reply instead, and nothing is saved.
+async fn change_settings(
+    ctx: &Context, msg: &Message, change: impl FnOnce(&mut AutoRoles) -> Result<(), String>,
+) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let storage = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(s) => s.clone(),
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    let result = {
+        let mut storage = storage.write().await;
+        let mut auto = storage
+            .guild(guild_id)
+            .map(|g| g.auto_roles.clone())
+            .unwrap_or_default();
+
+        match change(&mut auto) {
+            Ok(()) if storage.guild(guild_id).map(|g| &g.auto_roles) != Some(&auto) => {
+                storage.guild_mut(guild_id).auto_roles = auto.clone();
+                storage.save()?;
+                Ok(auto)
+            },
+            Ok(()) => Ok(auto),
+            Err(why) => Err(why),
+        }
+    };
+
+    match result {
+        Ok(auto) => settings_reply(&auto).send(ctx, msg).await,
+        Err(why) => Reply::embed(" ", Color::RED, why).send(ctx, msg).await,
+    }
+}
+
+/// Find the roles named by `args` in the guild of `msg`.
+async fn find_roles(ctx: &Context, msg: &Message, mut args: Args) -> Result<Vec<RoleId>, Reply> {
+    let guild = match msg.guild(&ctx.cache).await {
+        Some(guild) => guild,
+        None => return Err(Reply::embed(" ", Color::RED, "The guild is not cached.")),
+    };
+
+    let mut roles = Vec::new();
+    while let Ok(arg) = args.single_quoted::<String>() {
+        let role = arg
+            .parse::<RoleId>()
+            .ok()
+            .filter(|id| guild.roles.contains_key(id))
+            .or_else(|| guild.role_by_name(&arg).map(|r| r.id));
+        match role {
+            Some(id) => roles.push(id),
+            None => {
+                return Err(Reply::embed(
+                    " ",
+                    Color::RED,
+                    format!("There is no role `{}` in this guild.", arg),
+                ))
+            },
+        }
+    }
+
+    Ok(roles)
+}
+
+fn on_off(arg: &str) -> Result<bool, String> {
+    match arg.trim() {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        arg => Err(format!("Expected `on` or `off`, not `{}`", arg)),
+    }
+}
+
+/// Why `roles` can't be auto roles, one line per problem.
+///
+/// Besides the roles the bot can't give, like in the catalog, only roles below the
+/// highest role of the author (`author_position`) can be added, unless they own the
+/// guild.
+fn auto_role_problems(
+    roles: &[RoleId], guild_roles: &HashMap<RoleId, Role>, bot_position: Option<i64>,
+    author_position: Option<i64>,
+) -> Vec<String> {
+    let change = RoleChange {
+        add: roles.to_vec(),
+        ..RoleChange::default()
+    };
+    let mut problems = management::role_problems(&change, guild_roles, bot_position);
+
+    if let Some(top) = author_position {
+        for role in roles.iter().filter_map(|id| guild_roles.get(id)) {
+            if role.position >= top {
+                problems.push(format!(
+                    "`{}` is not below your highest role, so you can't make it an auto role.",
+                    role.name
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// Give the auto roles, and the self-assigned roles if they are given back, to a member
+/// who just joined or passed the screening.
+async fn give_join_roles(ctx: &Context, guild_id: GuildId, user_id: UserId) {
+    let storage = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(s) => s.clone(),
+        None => return,
+    };
+
+    let (auto, self_assigned) = match storage.read().await.guild(guild_id) {
+        Some(guild) if guild.auto_roles.restore => (
+            guild.auto_roles.clone(),
+            guild.self_assigned_roles(user_id.0),
+        ),
+        Some(guild) => (guild.auto_roles.clone(), Vec::new()),
+        None => return,
+    };
+
+    let mut roles = auto.roles.clone();
+    for role in restorable(ctx, guild_id, self_assigned).await {
+        if !roles.contains(&role) {
+            roles.push(role);
+        }
+    }
+
+    match auto.delay {
+        Some(delay) if !roles.is_empty() => {
+            let now = OffsetDateTime::now_utc().unix_timestamp();
+            let due = match i64::try_from(delay).ok().and_then(|d| now.checked_add(d)) {
+                Some(due) => due,
+                None => {
+                    eprintln!("The auto role delay of {} is too long", guild_id);
+                    return;
+                },
+            };
+            let mut storage = storage.write().await;
+            for role in roles {
+                storage.schedule(due, TaskAction::AddRole {
+                    guild: guild_id.0,
+                    user: user_id.0,
+                    role,
+                });
+            }
+            if let Err(why) = storage.save() {
+                eprintln!("Failed to save the storage: {}", why);
+            }
+        },
+        _ => {
+            for role in roles {
+                if let Err(why) = ctx.http.add_member_role(guild_id.0, user_id.0, role).await {
+                    eprintln!(
+                        "Failed to give the role {} to {} in {}: {}",
+                        role, user_id, guild_id, why
+                    );
+                }
+            }
+        },
+    }
+}
+
+/// The `roles` that are still in the catalog.
+///
+/// Role names are only known for cached guilds, so nothing is given back in the others.
+async fn restorable(ctx: &Context, guild_id: GuildId, roles: Vec<u64>) -> Vec<u64> {
+    if roles.is_empty() {
+        return roles;
+    }
+
+    let config = config::current(ctx).await;
+    match guild_id.to_guild_cached(&ctx.cache).await {
+        Some(guild) => roles
+            .into_iter()
+            .filter(|&id| {
+                guild
+                    .roles
+                    .get(&RoleId(id))
+                    .map_or(false, |r| config.is_self_assignable(&r.name))
+            })
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Reply describing the auto roles of a guild.
+fn settings_reply(auto: &AutoRoles) -> Reply {
+    let roles = if auto.roles.is_empty() {
+        "none".to_string()
+    } else {
+        auto.roles
+            .iter()
+            .map(|id| format!("<@&{}>", id))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let delay = auto.delay.map_or_else(
+        || "none".to_string(),
+        |secs| duration::format(std::time::Duration::from_secs(secs)),
+    );
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+
+    Reply::embed(
+        "AUTO ROLES",
+        Color::BLUE,
+        format!(
+            "Roles: {}\nDelay: {}\nWait for the membership screening: {}\nGive back self-assigned \
+             roles: {}",
+            roles,
+            delay,
+            yes_no(auto.screening),
+            yes_no(auto.restore)
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::permissions::Permissions;
+
+    use crate::{
+        storage::{GuildData, RoleChangeRecord, RoleChangeSource},
+        testing::{self, MockDiscord},
+    };
+
+    use super::*;
+
+    const GUILD: u64 = 30;
+
+    async fn set_guild(mock: &MockDiscord, change: impl FnOnce(&mut GuildData)) {
+        let data = mock.ctx.data.read().await;
+        let mut storage = data.get::<StorageContainer>().unwrap().write().await;
+        change(storage.guild_mut(GuildId(GUILD)));
+    }
+
+    fn role_grants(mock: &MockDiscord) -> Vec<String> {
+        mock.requests()
+            .into_iter()
+            .filter(|r| r.method == "PUT" && r.path.contains("/roles/"))
+            .map(|r| r.path)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn joins_get_the_auto_roles() {
+        let mock = MockDiscord::start().await;
+        set_guild(&mock, |g| g.auto_roles.roles = vec![40]).await;
+
+        AutoRoleModule
+            .guild_member_addition(&mock.ctx, GuildId(GUILD), &testing::member(GUILD, 50))
+            .await;
+
+        assert_eq!(role_grants(&mock), vec![
+            "/guilds/30/members/50/roles/40".to_string()
+        ]);
+    }
+
+    #[tokio::test]
+    async fn delayed_roles_are_scheduled() {
+        let mock = MockDiscord::start().await;
+        set_guild(&mock, |g| {
+            g.auto_roles.roles = vec![40];
+            g.auto_roles.delay = Some(600);
+        })
+        .await;
+
+        AutoRoleModule
+            .guild_member_addition(&mock.ctx, GuildId(GUILD), &testing::member(GUILD, 50))
+            .await;
+
+        assert!(role_grants(&mock).is_empty());
+        let data = mock.ctx.data.read().await;
+        let storage = data.get::<StorageContainer>().unwrap().read().await;
+        assert_eq!(storage.tasks[0].action, TaskAction::AddRole {
+            guild: GUILD,
+            user:  50,
+            role:  40,
+        });
+    }
+
+    #[tokio::test]
+    async fn delays_are_at_most_a_week() {
+        let mock = MockDiscord::start().await;
+        let msg = testing::message("!autorole delay 8d", 10, Some(GUILD), 20);
+
+        mock.send(&msg).await.unwrap();
+        set_guild(&mock, |g| {
+            g.auto_roles.roles = vec![40];
+            g.auto_roles.delay = Some(u64::MAX);
+        })
+        .await;
+        AutoRoleModule
+            .guild_member_addition(&mock.ctx, GuildId(GUILD), &testing::member(GUILD, 50))
+            .await;
+
+        assert_eq!(
+            mock.sent_embeds()[0]["description"],
+            "The delay can be at most a week."
+        );
+        assert!(role_grants(&mock).is_empty());
+        let data = mock.ctx.data.read().await;
+        let storage = data.get::<StorageContainer>().unwrap().read().await;
+        assert!(storage.tasks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn screening_waits_for_the_member() {
+        let mock = MockDiscord::start().await;
+        set_guild(&mock, |g| {
+            g.auto_roles.roles = vec![40];
+            g.auto_roles.screening = true;
+        })
+        .await;
+        let mut member = testing::member(GUILD, 50);
+        member.pending = true;
+
+        AutoRoleModule
+            .guild_member_addition(&mock.ctx, GuildId(GUILD), &member)
+            .await;
+        assert!(role_grants(&mock).is_empty());
+
+        let old = member.clone();
+        member.pending = false;
+        AutoRoleModule
+            .guild_member_update(&mock.ctx, Some(&old), &member)
+            .await;
+
+        assert_eq!(role_grants(&mock), vec![
+            "/guilds/30/members/50/roles/40".to_string()
+        ]);
+    }
+
+    #[tokio::test]
+    async fn rejoining_members_get_their_catalog_roles_back() {
+        let mock = MockDiscord::start().await;
+        mock.add_guild(GUILD, 20, &[(40, "Rust"), (41, "Moderator")])
+            .await;
+        set_guild(&mock, |g| {
+            g.auto_roles.restore = true;
+            g.role_changes.push(RoleChangeRecord {
+                user:     50,
+                added:    vec![40, 41],
+                removed:  vec![],
+                time:     0,
+                source:   RoleChangeSource::Command,
+                duration: None,
+            });
+        })
+        .await;
+
+        AutoRoleModule
+            .guild_member_addition(&mock.ctx, GuildId(GUILD), &testing::member(GUILD, 50))
+            .await;
+
+        assert_eq!(role_grants(&mock), vec![
+            "/guilds/30/members/50/roles/40".to_string()
+        ]);
+    }
+
+    #[test]
+    fn settings_are_described() {
+        let auto = AutoRoles {
+            roles: vec![40, 41],
+            delay: Some(600),
+            screening: true,
+            ..AutoRoles::default()
+        };
+
+        assert_eq!(
+            settings_reply(&auto).embed.unwrap().description,
+            "Roles: <@&40> <@&41>\nDelay: 10m\nWait for the membership screening: yes\nGive back \
+             self-assigned roles: no"
+        );
+    }
+
+    #[tokio::test]
+    async fn only_roles_below_the_author_are_added() {
+        let mock = MockDiscord::start().await;
+        mock.add_guild(GUILD, 20, &[(40, "Member"), (41, "Moderator")])
+            .await;
+        mock.grant(GUILD, GUILD, Permissions::MANAGE_ROLES).await;
+        mock.add_member(GUILD, 10, &[40]).await;
+        mock.add_member(GUILD, 11, &[41]).await;
+
+        let msg = testing::message("!autorole add Moderator", 10, Some(GUILD), 20);
+        mock.send(&msg).await.unwrap();
+        let msg = testing::message("!autorole add Member", 11, Some(GUILD), 20);
+        mock.send(&msg).await.unwrap();
+
+        assert_eq!(
+            mock.sent_embeds()[0]["description"],
+            "`Moderator` is not below your highest role, so you can't make it an auto role."
+        );
+        let data = mock.ctx.data.read().await;
+        let storage = data.get::<StorageContainer>().unwrap().read().await;
+        assert_eq!(
+            storage.guild(GuildId(GUILD)).unwrap().auto_roles.roles,
+            vec![40]
+        );
+    }
+
+    #[test]
+    fn elevated_roles_are_never_auto_roles() {
+        let role = |id: u64, permissions: &str, position: i64| {
+            let role: Role = serde_json::from_value(serde_json::json!({
+                "id": id.to_string(),
+                "guild_id": GUILD.to_string(),
+                "name": format!("role{}", id),
+                "color": 0,
+                "hoist": false,
+                "managed": false,
+                "mentionable": false,
+                "permissions": permissions,
+                "position": position,
+            }))
+            .unwrap();
+            (role.id, role)
+        };
+        // 4 is the permission to ban members
+        let roles = vec![role(40, "0", 1), role(41, "4", 2)]
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+
+        let problems = auto_role_problems(&[RoleId(40), RoleId(41)], &roles, Some(5), None);
+
+        assert_eq!(problems, vec!["`role41` has moderation or administration \
+                                   permissions, so it can't be self-assigned."
+            .to_string()]);
+        assert!(auto_role_problems(&[RoleId(40)], &roles, Some(5), Some(2)).is_empty());
+    }
+}
@@ -0,0 +1,615 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    error::Error,
+    fmt, fs, io,
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+use serenity::prelude::*;
+
+use crate::types::ConfigContainer;
+
+/// Environment variable that overrides the configuration file path.
+pub const CONFIG_PATH_VAR: &str = "OXIBOT_CONFIG";
+
+/// Configuration file used when [`CONFIG_PATH_VAR`] is not set.
+pub const DEFAULT_CONFIG_PATH: &str = "oxibot.toml";
+
+/// OxiBot runtime configuration.
+///
+/// Every field has a default, so a missing file or a partial file is valid.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Command prefixes.
+    pub prefixes: Vec<String>,
+    /// Ids of users trusted with the owner commands, besides the application owners.
+    pub owners: Vec<u64>,
+    /// Where the persistent data is saved. Only read at startup.
+    pub storage: PathBuf,
+    /// Options for the `fortune` command.
+    pub fortune: FortuneConfig,
+    /// Options for the `ip` command.
+    pub ip: IpConfig,
+    /// Options for the `exec` command.
+    pub exec: ExecConfig,
+    /// Options for the moderation commands.
+    pub moderation: ModerationConfig,
+    /// Self-assignable role categories.
+    pub catalog: Vec<Category>,
+    /// Options of the modules, by module name.
+    pub modules: BTreeMap<String, ModuleConfig>,
+}
+
+/// Options for the `fortune` command.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FortuneConfig {
+    /// Fortune files or directories passed to `fortune`. Empty means the system defaults.
+    pub files: Vec<String>,
+}
+
+/// Options for the `ip` command.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IpConfig {
+    /// Services that answer with the public ip of the caller. Empty disables the lookup.
+    pub echo_urls:    Vec<String>,
+    /// How long to wait for each service, in seconds.
+    pub timeout_secs: u64,
+}
+
+/// Options for the `exec` command.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExecConfig {
+    /// Programs that can be run. They are run directly, never through a shell.
+    pub allowed:      Vec<String>,
+    /// How long a program can run before it is killed, in seconds.
+    pub timeout_secs: u64,
+    /// Maximum number of messages used to send the output.
+    pub max_pages:    usize,
+    /// File where every invocation is appended.
+    pub audit_log:    PathBuf,
+}
+
+/// Options of a module.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModuleConfig {
+    /// Whether the module is loaded. Only read at startup.
+    pub enabled:  bool,
+    /// Every other key of the module table, checked by the module itself.
+    #[serde(flatten)]
+    pub settings: toml::value::Table,
+}
+
+/// Options for the moderation commands.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModerationConfig {
+    /// Name of the role given by `mute`. It must exist in the guild.
+    pub mute_role:  String,
+    /// What happens to a member after a number of warnings.
+    pub escalation: Vec<EscalationRule>,
+}
+
+/// A penalty given automatically when a member gets a number of warnings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EscalationRule {
+    /// Number of warnings that triggers the rule.
+    pub warnings: usize,
+    pub penalty:  Penalty,
+    /// How long a mute or a ban lasts, like `1h`. Permanent if missing.
+    #[serde(default)]
+    pub duration: Option<String>,
+}
+
+/// A penalty of an [`EscalationRule`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Penalty {
+    Mute,
+    Kick,
+    Ban,
+}
+
+/// A category of self-assignable roles.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Category {
+    /// Main name of the category.
+    pub name: String,
+    /// Other names the category answers to.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Names of the roles of the category.
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// Color of the roles created by `role sync create`, like `0xdea584`.
+    #[serde(default)]
+    pub color: Option<u32>,
+    /// Whether the created roles are shown apart in the member list.
+    #[serde(default)]
+    pub hoist: bool,
+    /// Whether everyone can mention the created roles.
+    #[serde(default)]
+    pub mentionable: bool,
+    /// Most roles of the category a member can pick in the `role menu`. Unlimited if
+    /// missing.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Errors that can happen while loading the configuration.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+}
+
+/// A snapshot of the current configuration of the bot.
+pub async fn current(ctx: &Context) -> Config {
+    match ctx.data.read().await.get::<ConfigContainer>() {
+        Some(config) => config.read().await.clone(),
+        None => Config::default(),
+    }
+}
+
+impl Config {
+    /// The configuration file path.
+    pub fn path() -> PathBuf {
+        env::var_os(CONFIG_PATH_VAR)
+            .map_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH), PathBuf::from)
+    }
+
+    /// Load the configuration from [`Config::path`].
+    ///
+    /// If the file does not exist, the default configuration is returned.
+    pub fn load() -> Result<Self, ConfigError> {
+        let path = Self::path();
+
+        let content = match fs::read_to_string(&path) {
+            Ok(c) => c,
+            Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(why) => return Err(ConfigError::Io(path, why)),
+        };
+
+        toml::from_str(&content).map_err(|why| ConfigError::Parse(path, why))
+    }
+
+    /// Find a category by its name or one of its aliases.
+    pub fn category(&self, name: &str) -> Option<&Category> {
+        self.catalog.iter().find(|c| c.matches(name))
+    }
+
+    /// Check if `role` is a role of any category.
+    pub fn is_self_assignable(&self, role: &str) -> bool {
+        self.catalog
+            .iter()
+            .any(|c| c.roles.iter().any(|r| r == role))
+    }
+
+    /// Check if the module called `name` is enabled. Modules are enabled by default.
+    pub fn module_enabled(&self, name: &str) -> bool {
+        self.modules.get(name).map_or(true, |m| m.enabled)
+    }
+
+    /// The settings of the module called `name`, if it has any.
+    pub fn module_settings(&self, name: &str) -> Option<&toml::value::Table> {
+        self.modules.get(name).map(|m| &m.settings)
+    }
+
+    /// Describe what changed from `self` to `new`, one line per change.
+    pub fn diff(&self, new: &Config) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        if self.prefixes != new.prefixes {
+            changes.push(format!(
+                "prefixes: {} -> {}",
+                code_list(&self.prefixes),
+                code_list(&new.prefixes)
+            ));
+        }
+
+        if self.owners != new.owners {
+            let ids = |owners: &[u64]| owners.iter().map(u64::to_string).collect::<Vec<_>>();
+            changes.push(format!(
+                "co-owners: {} -> {}",
+                code_list(&ids(&self.owners)),
+                code_list(&ids(&new.owners))
+            ));
+        }
+
+        if self.storage != new.storage {
+            changes.push(format!(
+                "storage: `{}` -> `{}` (applies after a restart)",
+                self.storage.display(),
+                new.storage.display()
+            ));
+        }
+
+        if self.fortune.files != new.fortune.files {
+            changes.push(format!(
+                "fortune files: {} -> {}",
+                code_list(&self.fortune.files),
+                code_list(&new.fortune.files)
+            ));
+        }
+
+        if self.ip != new.ip {
+            changes.push(format!(
+                "ip echo services: {} ({}s) -> {} ({}s)",
+                code_list(&self.ip.echo_urls),
+                self.ip.timeout_secs,
+                code_list(&new.ip.echo_urls),
+                new.ip.timeout_secs
+            ));
+        }
+
+        if self.exec.allowed != new.exec.allowed {
+            changes.push(format!(
+                "exec allowlist: {} -> {}",
+                code_list(&self.exec.allowed),
+                code_list(&new.exec.allowed)
+            ));
+        }
+
+        if self.exec.timeout_secs != new.exec.timeout_secs
+            || self.exec.max_pages != new.exec.max_pages
+            || self.exec.audit_log != new.exec.audit_log
+        {
+            changes.push(format!(
+                "exec limits: {}s, {} pages, `{}` -> {}s, {} pages, `{}`",
+                self.exec.timeout_secs,
+                self.exec.max_pages,
+                self.exec.audit_log.display(),
+                new.exec.timeout_secs,
+                new.exec.max_pages,
+                new.exec.audit_log.display()
+            ));
+        }
+
+        if self.moderation.mute_role != new.moderation.mute_role {
+            changes.push(format!(
+                "mute role: `{}` -> `{}`",
+                self.moderation.mute_role, new.moderation.mute_role
+            ));
+        }
+
+        if self.moderation.escalation != new.moderation.escalation {
+            let rules = |rules: &[EscalationRule]| {
+                rules
+                    .iter()
+                    .map(EscalationRule::to_string)
+                    .collect::<Vec<_>>()
+            };
+            changes.push(format!(
+                "escalation: {} -> {}",
+                code_list(&rules(&self.moderation.escalation)),
+                code_list(&rules(&new.moderation.escalation))
+            ));
+        }
+
+        for old in &self.catalog {
+            match new.catalog.iter().find(|c| c.name == old.name) {
+                Some(c) => {
+                    if c.aliases != old.aliases {
+                        changes.push(format!(
+                            "category `{}` aliases: {} -> {}",
+                            old.name,
+                            code_list(&old.aliases),
+                            code_list(&c.aliases)
+                        ));
+                    }
+
+                    if (c.color, c.hoist, c.mentionable, c.limit)
+                        != (old.color, old.hoist, old.mentionable, old.limit)
+                    {
+                        changes.push(format!(
+                            "category `{}` roles: {} -> {}",
+                            old.name,
+                            role_settings(old),
+                            role_settings(c)
+                        ));
+                    }
+
+                    let added: Vec<_> = c.roles.iter().filter(|r| !old.roles.contains(r)).collect();
+                    let removed: Vec<_> =
+                        old.roles.iter().filter(|r| !c.roles.contains(r)).collect();
+                    if !added.is_empty() {
+                        changes.push(format!(
+                            "category `{}` added roles: {}",
+                            old.name,
+                            code_list(&added)
+                        ));
+                    }
+                    if !removed.is_empty() {
+                        changes.push(format!(
+                            "category `{}` removed roles: {}",
+                            old.name,
+                            code_list(&removed)
+                        ));
+                    }
+                },
+                None => changes.push(format!("removed category `{}`", old.name)),
+            }
+        }
+
+        for c in &new.catalog {
+            if !self.catalog.iter().any(|old| old.name == c.name) {
+                changes.push(format!(
+                    "added category `{}` with {} roles",
+                    c.name,
+                    c.roles.len()
+                ));
+            }
+        }
+
+        let names = self
+            .modules
+            .keys()
+            .chain(new.modules.keys())
+            .collect::<BTreeSet<_>>();
+        for name in names {
+            if self.module_enabled(name) != new.module_enabled(name) {
+                changes.push(format!(
+                    "module `{}` {} (applies after a restart)",
+                    name,
+                    if new.module_enabled(name) {
+                        "enabled"
+                    } else {
+                        "disabled"
+                    }
+                ));
+            }
+            let old_settings = self.module_settings(name).filter(|s| !s.is_empty());
+            let new_settings = new.module_settings(name).filter(|s| !s.is_empty());
+            if old_settings != new_settings {
+                changes.push(format!("module `{}` settings changed", name));
+            }
+        }
+
+        changes
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            prefixes: vec!["!".to_string(), ".".to_string(), ";".to_string()],
+            owners: Vec::new(),
+            storage: PathBuf::from("oxibot.json"),
+            fortune: FortuneConfig::default(),
+            ip: IpConfig::default(),
+            exec: ExecConfig::default(),
+            moderation: ModerationConfig::default(),
+            catalog: default_catalog(),
+            modules: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for IpConfig {
+    fn default() -> Self {
+        Self {
+            echo_urls:    vec![
+                "https://api.ipify.org".to_string(),
+                "https://api6.ipify.org".to_string(),
+            ],
+            timeout_secs: 5,
+        }
+    }
+}
+
+impl Default for ExecConfig {
+    fn default() -> Self {
+        Self {
+            allowed:      Vec::new(),
+            timeout_secs: 10,
+            max_pages:    3,
+            audit_log:    PathBuf::from("oxibot-exec.log"),
+        }
+    }
+}
+
+impl Default for ModerationConfig {
+    fn default() -> Self {
+        Self {
+            mute_role:  "Muted".to_string(),
+            escalation: Vec::new(),
+        }
+    }
+}
+
+impl Default for ModuleConfig {
+    fn default() -> Self {
+        Self {
+            enabled:  true,
+            settings: toml::value::Table::new(),
+        }
+    }
+}
+
+impl Category {
+    /// Check if `name` is the name or an alias of the category.
+    pub fn matches(&self, name: &str) -> bool {
+        self.name == name || self.aliases.iter().any(|a| a == name)
+    }
+}
+
+impl fmt::Display for EscalationRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let penalty = match self.penalty {
+            Penalty::Mute => "mute",
+            Penalty::Kick => "kick",
+            Penalty::Ban => "ban",
+        };
+        write!(f, "{} warnings: {}", self.warnings, penalty)?;
+        if let Some(duration) = &self.duration {
+            write!(f, " for {}", duration)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, why) => write!(f, "failed to read {}: {}", path.display(), why),
+            ConfigError::Parse(path, why) => {
+                write!(f, "failed to parse {}: {}", path.display(), why)
+            },
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+fn code_list<T: AsRef<str>>(list: &[T]) -> String {
+    if list.is_empty() {
+        "none".to_string()
+    } else {
+        list.iter()
+            .map(|s| format!("`{}`", s.as_ref()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+fn role_settings(category: &Category) -> String {
+    let color = match category.color {
+        Some(color) => format!("`{:#08x}`", color),
+        None => "no color".to_string(),
+    };
+    let limit = match category.limit {
+        Some(limit) => format!("limit {}", limit),
+        None => "no limit".to_string(),
+    };
+    format!(
+        "{}, {}, {}, {}",
+        color,
+        if category.hoist {
+            "hoisted"
+        } else {
+            "not hoisted"
+        },
+        if category.mentionable {
+            "mentionable"
+        } else {
+            "not mentionable"
+        },
+        limit
+    )
+}
+
+fn category(name: &str, aliases: &[&str], roles: &[&str]) -> Category {
+    Category {
+        name: name.to_string(),
+        aliases: aliases.iter().map(|&s| s.to_string()).collect(),
+        roles: roles.iter().map(|&s| s.to_string()).collect(),
+        color: None,
+        hoist: false,
+        mentionable: false,
+        limit: None,
+    }
+}
+
+fn default_catalog() -> Vec<Category> {
+    vec![
+        category("especial", &[], &["Gamer", "Otaku"]),
+        category("os", &["so", "sistema-operacional"], &[
+            "DragonflyBSD",
+            "FreeBSD",
+            "OpenBSD",
+            "NetBSD",
+            "Linux",
+            "Illumos",
+            "Solaris",
+            "MacOS",
+            "Windows",
+        ]),
+        category("plataforma", &["plataforma-de-jogos"], &[
+            "EpicGames",
+            "NintendoOnline",
+            "Origin",
+            "PlaystationNetwork",
+            "Steam",
+            "XboxLive",
+        ]),
+        category("prog", &["programming", "programação"], &[
+            "Ada",
+            "Agda",
+            "Assembly",
+            "BrainFuck",
+            "C-lang",
+            "C++",
+            "C#",
+            "Carp",
+            "Clojure",
+            "CommonLisp",
+            "Coq",
+            "Crystal",
+            "CSS",
+            "D-lang",
+            "Dart",
+            "ECMAScript",
+            "Elixir",
+            "Elm",
+            "Erlang",
+            "F#",
+            "Fortran",
+            "Go",
+            "Groovy",
+            "Haskell",
+            "HTML",
+            "Idris",
+            "Janet",
+            "Java",
+            "Julia",
+            "Kotlin",
+            "Matlab",
+            "Nim",
+            "Latex",
+            "Lua",
+            "OCaml",
+            "Octave",
+            "PureScript",
+            "Python",
+            "R-lang",
+            "Racket",
+            "Ruby",
+            "Rust",
+            "Scala",
+            "Scheme",
+            "Shell",
+            "Swift",
+            "TypeScript",
+            "WebAssembly",
+            "Zig",
+        ]),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_shows_catalog_role_settings() {
+        let old = Config::default();
+        let mut new = Config::default();
+        let prog = new.catalog.iter_mut().find(|c| c.name == "prog").unwrap();
+        prog.color = Some(0xdea584);
+        prog.mentionable = true;
+        prog.limit = Some(3);
+
+        assert_eq!(old.diff(&new), vec!["category `prog` roles: no color, \
+                                         not hoisted, not mentionable, no \
+                                         limit -> `0xdea584`, not hoisted, \
+                                         mentionable, limit 3"
+            .to_string()]);
+        assert!(old.diff(&Config::default()).is_empty());
+    }
+}
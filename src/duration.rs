@@ -0,0 +1,161 @@
+//! Durations written as `30s`, `10m`, `1h30m`, `7d` or `2w`, or with words like
+//! `2 hours 30 minutes`.
+
+use std::time::Duration;
+
+/// Seconds in each unit, from the largest.
+const UNITS: &[(char, u64)] = &[
+    ('w', 604_800),
+    ('d', 86_400),
+    ('h', 3_600),
+    ('m', 60),
+    ('s', 1),
+];
+
+/// Parse a duration made of numbers followed by a unit.
+///
+/// Units are `w`, `d`, `h`, `m` and `s`, or their names in English or Portuguese, like
+/// `hours` or `minutos`. Spaces between the parts are allowed. Returns `None` if the text
+/// is not a duration or the duration is zero.
+pub fn parse(text: &str) -> Option<Duration> {
+    let mut total: u64 = 0;
+    let mut chars = text.trim().chars().peekable();
+
+    while chars.peek().is_some() {
+        let mut value: Option<u64> = None;
+        while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+            value = Some(
+                value
+                    .unwrap_or(0)
+                    .checked_mul(10)?
+                    .checked_add(u64::from(digit))?,
+            );
+            chars.next();
+        }
+        while chars.peek().map_or(false, |c| c.is_whitespace()) {
+            chars.next();
+        }
+
+        let mut unit = String::new();
+        while let Some(&c) = chars.peek().filter(|c| c.is_alphabetic()) {
+            unit.extend(c.to_lowercase());
+            chars.next();
+        }
+        while chars.peek().map_or(false, |c| c.is_whitespace()) {
+            chars.next();
+        }
+
+        total = total.checked_add(value?.checked_mul(unit_secs(&unit)?)?)?;
+    }
+
+    if total == 0 {
+        return None;
+    }
+
+    Some(Duration::from_secs(total))
+}
+
+/// Parse the longest duration at the start of `text`, made of whole words.
+///
+/// Returns the duration and the rest of the text, like `(2h30m, "take a break")` for
+/// `2h 30m take a break`.
+pub fn parse_prefix(text: &str) -> Option<(Duration, &str)> {
+    let text = text.trim_start();
+    let ends = text
+        .char_indices()
+        .filter(|&(_, c)| c.is_whitespace())
+        .map(|(i, _)| i)
+        .chain(Some(text.len()))
+        .collect::<Vec<_>>();
+
+    ends.iter()
+        .rev()
+        .find_map(|&end| parse(&text[..end]).map(|d| (d, text[end..].trim_start())))
+}
+
+/// Seconds in a unit, by its letter or its name.
+fn unit_secs(unit: &str) -> Option<u64> {
+    let secs = match unit {
+        "w" | "week" | "weeks" | "semana" | "semanas" => 604_800,
+        "d" | "day" | "days" | "dia" | "dias" => 86_400,
+        "h" | "hr" | "hrs" | "hour" | "hours" | "hora" | "horas" => 3_600,
+        "m" | "min" | "mins" | "minute" | "minutes" | "minuto" | "minutos" => 60,
+        "s" | "sec" | "secs" | "second" | "seconds" | "segundo" | "segundos" => 1,
+        _ => return None,
+    };
+    Some(secs)
+}
+
+/// Format a duration the way [`parse`] reads it, like `1d 2h 30m`.
+pub fn format(duration: Duration) -> String {
+    let mut secs = duration.as_secs();
+    if secs == 0 {
+        return "0s".to_string();
+    }
+
+    let mut parts = Vec::new();
+    for &(unit, size) in UNITS {
+        if secs >= size {
+            parts.push(format!("{}{}", secs / size, unit));
+            secs %= size;
+        }
+    }
+
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_single_and_combined_units() {
+        assert_eq!(parse("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse("7d"), Some(Duration::from_secs(7 * 86_400)));
+        assert_eq!(parse("1h30m"), Some(Duration::from_secs(5_400)));
+        assert_eq!(parse("2W"), Some(Duration::from_secs(2 * 604_800)));
+    }
+
+    #[test]
+    fn refuses_what_is_not_a_duration() {
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("7"), None);
+        assert_eq!(parse("1h30"), None);
+        assert_eq!(parse("h"), None);
+        assert_eq!(parse("0m"), None);
+        assert_eq!(parse("spam"), None);
+        assert_eq!(parse("99999999999999999999d"), None);
+    }
+
+    #[test]
+    fn parses_unit_names_and_spaces() {
+        assert_eq!(
+            parse("2 hours 30 minutes"),
+            Some(Duration::from_secs(9_000))
+        );
+        assert_eq!(parse("1h 30m"), Some(Duration::from_secs(5_400)));
+        assert_eq!(parse("3 dias"), Some(Duration::from_secs(3 * 86_400)));
+        assert_eq!(parse("2 hours and"), None);
+    }
+
+    #[test]
+    fn parses_the_duration_before_a_text() {
+        assert_eq!(
+            parse_prefix("2h30m take a break"),
+            Some((Duration::from_secs(9_000), "take a break"))
+        );
+        assert_eq!(
+            parse_prefix("1 day 2 hours  check the build"),
+            Some((Duration::from_secs(93_600), "check the build"))
+        );
+        assert_eq!(parse_prefix("10m"), Some((Duration::from_secs(600), "")));
+        assert_eq!(parse_prefix("take a break"), None);
+    }
+
+    #[test]
+    fn formats_from_the_largest_unit() {
+        assert_eq!(format(Duration::from_secs(5_400)), "1h 30m");
+        assert_eq!(format(Duration::from_secs(8 * 86_400 + 1)), "1w 1d 1s");
+        assert_eq!(format(Duration::from_secs(0)), "0s");
+    }
+}
@@ -0,0 +1,525 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+};
+
+use serenity::{
+    async_trait,
+    client::bridge::gateway::GatewayIntents,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{
+        channel::Message,
+        event::MessageUpdateEvent,
+        guild::Member,
+        id::{ChannelId, GuildId, MessageId, RoleId, UserId},
+        user::User,
+    },
+    prelude::*,
+    utils::Colour as Color,
+};
+
+use crate::{
+    config::Config,
+    module::Module,
+    reply::Reply,
+    types::{RecentMessages, StorageContainer},
+};
+
+/// Number of messages kept to show what an edited or deleted message said.
+const CACHED_MESSAGES: usize = 1000;
+
+/// Maximum number of characters in an embed field.
+const FIELD_LIMIT: usize = 1024;
+
+#[group]
+#[commands(eventlog)]
+struct EventLog;
+
+/// Posts message edits and deletions, joins, leaves and member updates in a log channel.
+///
+/// Needs the privileged server members intent to be told about joins, leaves and member
+/// updates.
+pub struct EventLogModule;
+
+#[async_trait]
+impl Module for EventLogModule {
+    fn name(&self) -> &'static str {
+        "eventlog"
+    }
+
+    fn groups(&self) -> Vec<&'static CommandGroup> {
+        vec![&EVENTLOG_GROUP]
+    }
+
+    fn intents(&self) -> GatewayIntents {
+        GatewayIntents::GUILD_MEMBERS
+    }
+
+    fn init(&self, data: &mut TypeMap, _config: &Config) {
+        data.insert::<RecentMessages>(Arc::new(RwLock::new(MessageCache::default())));
+    }
+
+    async fn message(&self, ctx: &Context, msg: &Message) {
+        if msg.author.bot || msg.guild_id.is_none() {
+            return;
+        }
+
+        if let Some(cache) = ctx.data.read().await.get::<RecentMessages>() {
+            cache.write().await.insert(msg.id, CachedMessage {
+                author:  msg.author.id,
+                content: msg.content.clone(),
+            });
+        }
+    }
+
+    async fn message_update(
+        &self, ctx: &Context, old: Option<&Message>, _new: Option<&Message>,
+        event: &MessageUpdateEvent,
+    ) {
+        let (guild_id, content) = match (event.guild_id, &event.content) {
+            (Some(guild_id), Some(content)) => (guild_id, content),
+            _ => return,
+        };
+        if event.author.as_ref().map_or(false, |a| a.bot) {
+            return;
+        }
+
+        let cached = match ctx.data.read().await.get::<RecentMessages>() {
+            Some(cache) => cache.write().await.update(event.id, content.clone()),
+            None => None,
+        };
+        let author = event
+            .author
+            .as_ref()
+            .map(|a| a.id)
+            .or_else(|| cached.as_ref().map(|m| m.author));
+        let before = cached
+            .map(|m| m.content)
+            .or_else(|| old.map(|m| m.content.clone()));
+
+        if let Some(reply) = edit_log(event.channel_id, author, before.as_deref(), content) {
+            post(ctx, guild_id, reply).await;
+        }
+    }
+
+    async fn message_delete(
+        &self, ctx: &Context, channel_id: ChannelId, message_id: MessageId,
+        guild_id: Option<GuildId>,
+    ) {
+        let guild_id = match guild_id {
+            Some(id) => id,
+            None => return,
+        };
+
+        let cached = match ctx.data.read().await.get::<RecentMessages>() {
+            Some(cache) => cache.write().await.remove(message_id),
+            None => None,
+        };
+
+        post(ctx, guild_id, delete_log(channel_id, cached.as_ref())).await;
+    }
+
+    async fn guild_member_addition(&self, ctx: &Context, guild_id: GuildId, member: &Member) {
+        post(ctx, guild_id, join_log(&member.user)).await;
+    }
+
+    async fn guild_member_removal(
+        &self, ctx: &Context, guild_id: GuildId, user: &User, _member: Option<&Member>,
+    ) {
+        post(ctx, guild_id, leave_log(user)).await;
+    }
+
+    async fn guild_member_update(&self, ctx: &Context, old: Option<&Member>, new: &Member) {
+        let old = match old {
+            Some(old) => old,
+            None => return,
+        };
+
+        let reply = member_update_log(
+            new.user.id,
+            &old.roles,
+            &new.roles,
+            old.nick.as_deref(),
+            new.nick.as_deref(),
+        );
+        if let Some(reply) = reply {
+            post(ctx, new.guild_id, reply).await;
+        }
+    }
+}
+
+/// The last messages sent in guilds.
+///
+/// Only the newest [`CACHED_MESSAGES`] messages are kept.
+#[derive(Debug, Default)]
+pub struct MessageCache {
+    messages: HashMap<MessageId, CachedMessage>,
+    order:    VecDeque<MessageId>,
+}
+
+/// What a cached message said.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedMessage {
+    pub author:  UserId,
+    pub content: String,
+}
+
+impl MessageCache {
+    /// Cache a message, forgetting the oldest one if the cache is full.
+    pub fn insert(&mut self, id: MessageId, message: CachedMessage) {
+        if self.messages.insert(id, message).is_none() {
+            self.order.push_back(id);
+            if self.order.len() > CACHED_MESSAGES {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.messages.remove(&oldest);
+                }
+            }
+        }
+    }
+
+    /// Change the content of a cached message, and return the message before the change.
+    pub fn update(&mut self, id: MessageId, content: String) -> Option<CachedMessage> {
+        let message = self.messages.get_mut(&id)?;
+
+        Some(CachedMessage {
+            author:  message.author,
+            content: std::mem::replace(&mut message.content, content),
+        })
+    }
+
+    /// Forget a message and return it.
+    pub fn remove(&mut self, id: MessageId) -> Option<CachedMessage> {
+        let message = self.messages.remove(&id)?;
+        // Deleted messages are usually recent, so look from the newest
+        if let Some(i) = self.order.iter().rposition(|&m| m == id) {
+            self.order.remove(i);
+        }
+        Some(message)
+    }
+}
+
+/// Set the channel where the events of the guild are posted.
+///
+/// Without arguments it shows the current channel, and `off` stops posting the events.
+#[command]
+#[only_in(guild)]
+#[required_permissions("MANAGE_GUILD")]
+#[max_args(1)]
+#[usage = "eventlog [#CHANNEL | off]"]
+async fn eventlog(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let storage = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(s) => s.clone(),
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    if args.is_empty() {
+        let text = match storage
+            .read()
+            .await
+            .guild(guild_id)
+            .and_then(|g| g.event_log)
+        {
+            Some(id) => format!("Events are posted in <#{}>.", id),
+            None => "Events are not posted anywhere.".to_string(),
+        };
+        return Reply::embed("EVENT LOG", Color::BLUE, text)
+            .send(ctx, msg)
+            .await;
+    }
+
+    let arg = args.single::<String>().unwrap_or_default();
+    let channel_id = if arg == "off" {
+        None
+    } else {
+        match arg.parse::<ChannelId>() {
+            Ok(id) => Some(id),
+            Err(_) => {
+                return Reply::embed(" ", Color::RED, format!("Invalid channel: `{}`", arg))
+                    .send(ctx, msg)
+                    .await;
+            },
+        }
+    };
+
+    {
+        let mut storage = storage.write().await;
+        storage.guild_mut(guild_id).event_log = channel_id.map(|id| id.0);
+        storage.save()?;
+    }
+
+    let text = match channel_id {
+        Some(id) => format!("Events are now posted in <#{}>.", id),
+        None => "Events are no longer posted.".to_string(),
+    };
+    Reply::embed("EVENT LOG", Color::TEAL, text)
+        .send(ctx, msg)
+        .await
+}
+
+/// Post `reply` in the event log channel of the guild, if it has one.
+async fn post(ctx: &Context, guild_id: GuildId, reply: Reply) {
+    let channel_id = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(storage) => storage
+            .read()
+            .await
+            .guild(guild_id)
+            .and_then(|g| g.event_log),
+        None => None,
+    };
+
+    if let Some(channel_id) = channel_id {
+        if let Err(why) = reply.send_to(ctx, ChannelId(channel_id)).await {
+            eprintln!("Failed to post in the event log of {}: {}", guild_id, why);
+        }
+    }
+}
+
+/// Log of an edited message, or `None` if its content did not change.
+fn edit_log(
+    channel_id: ChannelId, author: Option<UserId>, before: Option<&str>, after: &str,
+) -> Option<Reply> {
+    if before == Some(after) {
+        return None;
+    }
+
+    let mut reply = Reply::embed(
+        "MESSAGE EDITED",
+        Color::BLUE,
+        format!(
+            "A message of {} was edited in <#{}>.",
+            mention(author),
+            channel_id
+        ),
+    );
+    if let Some(embed) = &mut reply.embed {
+        embed.fields.push((
+            "Before".to_string(),
+            before.map_or_else(|| "*Not cached*".to_string(), clip),
+            false,
+        ));
+        embed.fields.push(("After".to_string(), clip(after), false));
+    }
+
+    Some(reply)
+}
+
+/// Log of a deleted message.
+fn delete_log(channel_id: ChannelId, cached: Option<&CachedMessage>) -> Reply {
+    let author = cached.map(|m| m.author);
+    let mut reply = Reply::embed(
+        "MESSAGE DELETED",
+        Color::RED,
+        format!(
+            "A message of {} was deleted in <#{}>.",
+            mention(author),
+            channel_id
+        ),
+    );
+    if let Some(embed) = &mut reply.embed {
+        let content = cached.map_or_else(|| "*Not cached*".to_string(), |m| clip(&m.content));
+        embed.fields.push(("Content".to_string(), content, false));
+    }
+
+    reply
+}
+
+fn join_log(user: &User) -> Reply {
+    Reply::embed(
+        "MEMBER JOINED",
+        Color::TEAL,
+        format!("<@{}> ({}) joined.", user.id, user.tag()),
+    )
+}
+
+fn leave_log(user: &User) -> Reply {
+    Reply::embed(
+        "MEMBER LEFT",
+        Color::RED,
+        format!("<@{}> ({}) left.", user.id, user.tag()),
+    )
+}
+
+/// Log of the role and nickname changes of a member, or `None` if neither changed.
+fn member_update_log(
+    user_id: UserId, old_roles: &[RoleId], new_roles: &[RoleId], old_nick: Option<&str>,
+    new_nick: Option<&str>,
+) -> Option<Reply> {
+    let old_set = old_roles.iter().collect::<HashSet<_>>();
+    let new_set = new_roles.iter().collect::<HashSet<_>>();
+    let roles = |ids: Vec<&&RoleId>| {
+        ids.iter()
+            .map(|id| format!("<@&{}>", id))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let added = roles(new_set.difference(&old_set).collect());
+    let removed = roles(old_set.difference(&new_set).collect());
+
+    let mut fields = Vec::new();
+    if !added.is_empty() {
+        fields.push(("Roles added".to_string(), added, false));
+    }
+    if !removed.is_empty() {
+        fields.push(("Roles removed".to_string(), removed, false));
+    }
+    if old_nick != new_nick {
+        fields.push((
+            "Nickname".to_string(),
+            format!(
+                "{} -> {}",
+                old_nick.unwrap_or("*none*"),
+                new_nick.unwrap_or("*none*")
+            ),
+            false,
+        ));
+    }
+
+    if fields.is_empty() {
+        return None;
+    }
+
+    let mut reply = Reply::embed(
+        "MEMBER UPDATED",
+        Color::BLUE,
+        format!("<@{}> was updated.", user_id),
+    );
+    if let Some(embed) = &mut reply.embed {
+        embed.fields = fields;
+    }
+
+    Some(reply)
+}
+
+fn mention(user_id: Option<UserId>) -> String {
+    match user_id {
+        Some(id) => format!("<@{}>", id),
+        None => "someone".to_string(),
+    }
+}
+
+/// Fit `text` in an embed field.
+fn clip(text: &str) -> String {
+    if text.is_empty() {
+        return "*Empty*".to_string();
+    }
+
+    let mut clipped = text.chars().take(FIELD_LIMIT).collect::<String>();
+    if clipped.len() < text.len() {
+        clipped.pop();
+        clipped.push('…');
+    }
+    clipped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::MockDiscord;
+
+    use super::*;
+
+    fn cached(author: u64, content: &str) -> CachedMessage {
+        CachedMessage {
+            author:  UserId(author),
+            content: content.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn deleted_messages_are_posted_with_their_content() {
+        let mock = MockDiscord::start().await;
+        {
+            let data = mock.ctx.data.read().await;
+            let mut storage = data.get::<StorageContainer>().unwrap().write().await;
+            storage.guild_mut(GuildId(30)).event_log = Some(99);
+        }
+        EventLogModule.init(&mut *mock.ctx.data.write().await, &Config::default());
+
+        let mut msg = crate::testing::message("secret plans", 50, Some(30), 20);
+        msg.id = MessageId(300);
+        EventLogModule.message(&mock.ctx, &msg).await;
+        EventLogModule
+            .message_delete(&mock.ctx, ChannelId(20), MessageId(300), Some(GuildId(30)))
+            .await;
+
+        let embeds = mock.sent_embeds();
+        assert_eq!(embeds.len(), 1);
+        assert_eq!(embeds[0]["title"], "MESSAGE DELETED");
+        assert_eq!(embeds[0]["fields"][0]["value"], "secret plans");
+        assert!(mock
+            .requests()
+            .iter()
+            .any(|r| r.path == "/channels/99/messages"));
+    }
+
+    #[test]
+    fn the_cache_forgets_the_oldest_messages() {
+        let mut cache = MessageCache::default();
+        for id in 0..=CACHED_MESSAGES as u64 {
+            cache.insert(MessageId(id), cached(1, "hi"));
+        }
+
+        assert_eq!(cache.remove(MessageId(0)), None);
+        assert_eq!(
+            cache.update(MessageId(1), "edited".to_string()),
+            Some(cached(1, "hi"))
+        );
+        assert_eq!(cache.remove(MessageId(1)), Some(cached(1, "edited")));
+    }
+
+    #[test]
+    fn removed_messages_free_their_place() {
+        let mut cache = MessageCache::default();
+        cache.insert(MessageId(0), cached(1, "first"));
+        for id in 1..=CACHED_MESSAGES as u64 {
+            cache.insert(MessageId(id), cached(1, "hi"));
+            cache.remove(MessageId(id));
+        }
+
+        assert_eq!(cache.remove(MessageId(0)), Some(cached(1, "first")));
+        assert!(cache.order.is_empty());
+    }
+
+    #[test]
+    fn unchanged_edits_are_not_logged() {
+        assert!(edit_log(ChannelId(20), None, Some("same"), "same").is_none());
+
+        let reply = edit_log(ChannelId(20), Some(UserId(50)), None, "new").unwrap();
+        let embed = reply.embed.unwrap();
+        assert_eq!(embed.description, "A message of <@50> was edited in <#20>.");
+        assert_eq!(embed.fields[0].1, "*Not cached*");
+    }
+
+    #[test]
+    fn member_updates_list_the_role_changes() {
+        let reply = member_update_log(
+            UserId(50),
+            &[RoleId(1), RoleId(2)],
+            &[RoleId(2), RoleId(3)],
+            None,
+            None,
+        )
+        .unwrap();
+
+        let fields = reply.embed.unwrap().fields;
+        assert_eq!(fields, vec![
+            ("Roles added".to_string(), "<@&3>".to_string(), false),
+            ("Roles removed".to_string(), "<@&1>".to_string(), false),
+        ]);
+
+        assert!(member_update_log(UserId(50), &[RoleId(1)], &[RoleId(1)], None, None).is_none());
+    }
+}
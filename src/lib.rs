@@ -0,0 +1,483 @@
+//! OxiBot, a Discord bot, as a library.
+//!
+//! Embed OxiBot in your own bot and add your own command groups with the builder:
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use oxibot::OxiBot;
+//!
+//! OxiBot::builder()
+//!     .token(std::env::var("DISCORD_TOKEN")?)
+//!     .run()
+//!     .await
+//! # }
+//! ```
+//!
+//! Features are [`Module`]s: register your own with [`OxiBotBuilder::module`], or a lone
+//! command group with [`OxiBotBuilder::with_group`]. Use [`OxiBotBuilder::build`] to get
+//! the [`Client`] and insert your own data before starting it.
+//!
+//! [`Module`]: crate::module::Module
+
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    error::Error,
+    io,
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use serenity::{
+    framework::standard::{
+        help_commands::{self, CustomisedHelpData, GroupCommandsPair},
+        macros::{help, hook},
+        Args, Command as FrameworkCommand, CommandGroup, CommandResult, HelpOptions,
+        StandardFramework,
+    },
+    http::Http,
+    model::{channel::Message, id::UserId, prelude::MembershipState},
+    prelude::*,
+};
+
+use once_cell::sync::Lazy;
+use time::Instant;
+
+use crate::{
+    config::Config,
+    module::{Module, ModuleRegistry},
+    paginator::Pages,
+    storage::Storage,
+    types::*,
+};
+
+pub use crate::types::OxiHandler;
+
+pub mod admin;
+pub mod autorole;
+pub mod config;
+pub mod duration;
+pub mod eventlog;
+pub mod management;
+pub mod meme;
+pub mod moderation;
+pub mod module;
+pub mod net;
+pub mod owner;
+pub mod paginator;
+pub mod prompt;
+pub mod remind;
+pub mod reply;
+pub mod scheduler;
+pub mod storage;
+pub mod tags;
+#[cfg(test)]
+mod testing;
+pub mod types;
+pub mod util;
+pub mod welcome;
+
+static mut UPTIME: Lazy<Instant> = Lazy::new(Instant::now);
+
+/// OxiBot entry point.
+pub struct OxiBot;
+
+/// Builder of an OxiBot [`Client`].
+pub struct OxiBotBuilder {
+    token:   Option<String>,
+    config:  Option<Config>,
+    modules: ModuleRegistry,
+    groups:  Vec<&'static CommandGroup>,
+}
+
+impl OxiBot {
+    /// Start building a bot with the builtin modules.
+    pub fn builder() -> OxiBotBuilder {
+        OxiBotBuilder {
+            token:   None,
+            config:  None,
+            modules: ModuleRegistry::builtin(),
+            groups:  Vec::new(),
+        }
+    }
+}
+
+impl OxiBotBuilder {
+    /// Set the Discord bot token. Required.
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Use this configuration instead of loading it from [`Config::path`].
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Register a module, replacing the module with the same name if there is one.
+    ///
+    /// Like the builtin modules, it is left out if disabled in the configuration.
+    pub fn module(mut self, module: impl Module + 'static) -> Self {
+        self.modules.register(module);
+        self
+    }
+
+    /// Register a command group outside of any module.
+    pub fn with_group(mut self, group: &'static CommandGroup) -> Self {
+        self.groups.push(group);
+        self
+    }
+
+    /// Build the client, with the OxiBot data already inserted.
+    pub async fn build(self) -> Result<Client, Box<dyn Error>> {
+        let token = self.token.ok_or("A token is required to build OxiBot")?;
+        let config = match self.config {
+            Some(c) => c,
+            None => Config::load()?,
+        };
+        let storage = Storage::load(&config.storage)?;
+
+        let modules = self.modules.enabled(&config);
+        if let Err(errors) = modules.check_config(&config) {
+            return Err(format!("Invalid configuration:\n{}", errors.join("\n")).into());
+        }
+        let mut groups = modules.groups();
+        groups.extend(self.groups);
+
+        let http = Http::new_with_token(&token);
+
+        // Fetch bot's owners and id
+        let info = http
+            .get_current_application_info()
+            .await
+            .map_err(|why| format!("Could not access application info: {:?}", why))?;
+        let mut primary_owners = HashSet::new();
+        if let Some(team) = info.team {
+            primary_owners.insert(team.owner_user_id);
+            primary_owners.extend(
+                team.members
+                    .iter()
+                    .filter(|m| m.membership_state == MembershipState::Accepted)
+                    .map(|m| m.user.id),
+            );
+        } else {
+            primary_owners.insert(info.owner.id);
+        }
+        let _bot_id = http
+            .get_current_user()
+            .await
+            .map_err(|why| format!("Could not access the bot id: {:?}", why))?
+            .id;
+
+        // Framework owners skip every check and required permission, and that set is fixed
+        // at startup. Co-owners change at runtime, so they only pass the `Owner` check.
+        let framework = framework(primary_owners.clone(), &groups);
+
+        // Interactions, like the role menus, need the application id
+        let client = Client::builder(&token)
+            .application_id(info.id.0)
+            .intents(modules.intents())
+            .event_handler(OxiHandler)
+            .framework(framework)
+            .await?;
+
+        insert_data(&client, config, storage, modules, &groups, primary_owners).await;
+
+        Ok(client)
+    }
+
+    /// Build the client and run it until it shuts down.
+    ///
+    /// If the shutdown came from the `restart` command, the process is replaced by a new
+    /// instance of itself.
+    pub async fn run(self) -> Result<(), Box<dyn Error>> {
+        let mut client = self.build().await?;
+
+        if let Err(why) = client.start().await {
+            eprintln!("Client error: {:?}", why);
+        }
+        scheduler::save_unsaved(&client.data).await;
+
+        let restart = match client.data.read().await.get::<RestartFlag>() {
+            Some(flag) => flag.load(Ordering::SeqCst),
+            None => false,
+        };
+
+        if restart {
+            return Err(re_exec().into());
+        }
+
+        Ok(())
+    }
+}
+
+/// The command framework with the OxiBot prefixes, hooks and help, and the given groups.
+fn framework(owners: HashSet<UserId>, groups: &[&'static CommandGroup]) -> StandardFramework {
+    let mut framework = StandardFramework::new()
+        .configure(|c| c.owners(owners).prefix("").dynamic_prefix(prefix))
+        .before(before)
+        .after(after)
+        .unrecognised_command(unknown_command)
+        .help(&MY_HELP);
+
+    for group in groups {
+        framework = framework.group(group);
+    }
+
+    framework
+}
+
+/// Insert the data the commands and modules expect in a new client.
+async fn insert_data(
+    client: &Client, config: Config, storage: Storage, modules: ModuleRegistry,
+    groups: &[&'static CommandGroup], primary_owners: HashSet<UserId>,
+) {
+    let mut data = client.data.write().await;
+    for module in modules.iter() {
+        module.init(&mut data, &config);
+    }
+
+    data.insert::<CommandCounter>(HashMap::default());
+    data.insert::<ShardManagerContainer>(Arc::clone(&client.shard_manager));
+    data.insert::<ConfigContainer>(Arc::new(RwLock::new(config)));
+    data.insert::<RestartFlag>(Arc::new(AtomicBool::new(false)));
+    data.insert::<StorageContainer>(Arc::new(RwLock::new(storage)));
+    data.insert::<CommandGroups>(command_groups(groups));
+    data.insert::<PrimaryOwners>(primary_owners);
+    data.insert::<Modules>(modules);
+}
+
+/// Replace the current process with a new instance of the bot.
+///
+/// Only returns if the new instance could not be started.
+#[cfg(unix)]
+fn re_exec() -> io::Error {
+    use std::os::unix::process::CommandExt;
+
+    match env::current_exe() {
+        Ok(exe) => Command::new(exe).args(env::args_os().skip(1)).exec(),
+        Err(why) => why,
+    }
+}
+
+/// Start a new instance of the bot and exit the current one.
+///
+/// Only returns if the new instance could not be started.
+#[cfg(not(unix))]
+fn re_exec() -> io::Error {
+    let spawned =
+        env::current_exe().and_then(|exe| Command::new(exe).args(env::args_os().skip(1)).spawn());
+
+    match spawned {
+        Ok(_) => std::process::exit(0),
+        Err(why) => why,
+    }
+}
+
+/// Map every command name and alias to the names of its parent commands and groups.
+fn command_groups(groups: &[&'static CommandGroup]) -> HashMap<String, Vec<String>> {
+    fn add_commands(
+        map: &mut HashMap<String, Vec<String>>, commands: &[&'static FrameworkCommand],
+        parents: &[String],
+    ) {
+        for command in commands {
+            for name in command.options.names {
+                let entry = map.entry(name.to_lowercase()).or_default();
+                for parent in parents {
+                    if !entry.contains(parent) {
+                        entry.push(parent.clone());
+                    }
+                }
+            }
+
+            let mut chain = vec![command.options.names[0].to_lowercase()];
+            chain.extend_from_slice(parents);
+            add_commands(map, command.options.sub_commands, &chain);
+        }
+    }
+
+    fn add_groups(
+        map: &mut HashMap<String, Vec<String>>, groups: &[&'static CommandGroup],
+        parents: &[String],
+    ) {
+        for group in groups {
+            let mut chain = vec![group.name.to_lowercase()];
+            chain.extend_from_slice(parents);
+            add_commands(map, group.options.commands, &chain);
+            add_groups(map, group.options.sub_groups, &chain);
+        }
+    }
+
+    let mut map = HashMap::new();
+    add_groups(&mut map, groups, &[]);
+    map
+}
+
+#[help]
+#[individual_command_tip = "Hello! Olá! こんにちは！Hola! Bonjour! 您好!\nI'm OxiBot. How may I \
+                            help you?\n\n{}\n\nIf you want more information about a specific \
+                            command, just pass the command as argument."]
+#[command_not_found_text = "Could not find: `{}`."]
+#[max_levenshtein_distance(3)]
+#[lacking_permissions = "strike"]
+#[lacking_role = "strike"]
+#[wrong_channel = "strike"]
+async fn my_help(
+    context: &Context, msg: &Message, args: Args, help_options: &'static HelpOptions,
+    groups: &[&'static CommandGroup], owners: HashSet<UserId>,
+) -> CommandResult {
+    // The list of every command is paginated, it does not fit in one embed
+    let help = help_commands::create_customised_help_data(
+        context,
+        msg,
+        &args,
+        groups,
+        &owners,
+        help_options,
+    )
+    .await;
+    let pages = match help {
+        CustomisedHelpData::GroupedCommands {
+            help_description,
+            groups,
+        } => {
+            // The tip has a placeholder for the prefixes, they can change on reload
+            let prefixes = config::current(context).await.prefixes;
+            let mut lines = vec![help_description.replacen("{}", &prefixes_tip(&prefixes), 1)];
+            help_lines(&groups, 0, &mut lines);
+            Some(Pages::from_lines(
+                "HELP",
+                help_options.embed_success_colour,
+                &lines,
+                HELP_PAGE_SIZE,
+            ))
+        },
+        _ => None,
+    };
+    if let Some(pages) = pages {
+        return pages.send(context, msg).await;
+    }
+
+    let _ = help_commands::with_embeds(context, msg, args, help_options, groups, owners).await;
+    Ok(())
+}
+
+/// The sentence of the help telling the command prefixes.
+fn prefixes_tip(prefixes: &[String]) -> String {
+    let prefixes = prefixes
+        .iter()
+        .filter(|p| !p.is_empty())
+        .map(|p| format!("`{}`", p))
+        .collect::<Vec<_>>();
+
+    match prefixes.split_last() {
+        None => "I have no command prefixes".to_string(),
+        Some((prefix, [])) => format!("My command prefix is {}", prefix),
+        Some((last, rest)) => format!("My command prefixes are {} and {}", rest.join(", "), last),
+    }
+}
+
+/// Number of groups per page of the help.
+const HELP_PAGE_SIZE: usize = 8;
+
+/// Add a line with the commands of every group to `lines`, sub groups indented by
+/// `depth`.
+fn help_lines(groups: &[GroupCommandsPair], depth: usize, lines: &mut Vec<String>) {
+    for group in groups {
+        lines.push(format!(
+            "{}**{}**: {}",
+            "\u{2003}".repeat(depth),
+            group.name,
+            group.command_names.join(" ")
+        ));
+        help_lines(&group.sub_groups, depth + 1, lines);
+    }
+}
+
+#[hook]
+async fn prefix(ctx: &Context, msg: &Message) -> Option<String> {
+    let data = ctx.data.read().await;
+    let config = data.get::<ConfigContainer>()?.read().await;
+
+    config
+        .prefixes
+        .iter()
+        .find(|p| !p.is_empty() && msg.content.starts_with(p.as_str()))
+        .cloned()
+}
+
+#[hook]
+async fn before(ctx: &Context, msg: &Message, command_name: &str) -> bool {
+    println!(
+        "Got command '{}' by user '{}'",
+        command_name, msg.author.name
+    );
+
+    if !admin::is_enabled(ctx, msg, command_name).await {
+        println!(
+            "Command '{}' is disabled in channel {}",
+            command_name, msg.channel_id
+        );
+        return false;
+    }
+
+    // Increment the number of times this command has been run once. If
+    // the command's name does not exist in the counter, add a default
+    // value of 0.
+    let mut data = ctx.data.write().await;
+    let counter = data
+        .get_mut::<CommandCounter>()
+        .expect("Expected CommandCounter in TypeMap.");
+    let entry = counter.entry(command_name.to_string()).or_insert(0);
+    *entry += 1;
+
+    true // if `before` returns false, command processing doesn't happen.
+}
+
+#[hook]
+async fn after(_ctx: &Context, _msg: &Message, command_name: &str, command_result: CommandResult) {
+    match command_result {
+        Ok(()) => println!("Processed command '{}'", command_name),
+        Err(why) => println!("Command '{}' returned error {:?}", command_name, why),
+    }
+}
+
+#[hook]
+async fn unknown_command(ctx: &Context, msg: &Message, unknown_command_name: &str) {
+    println!("Could not find command named '{}'", unknown_command_name);
+
+    let modules = ctx
+        .data
+        .read()
+        .await
+        .get::<Modules>()
+        .cloned()
+        .unwrap_or_default();
+    for module in modules.iter() {
+        module.unknown_command(ctx, msg, unknown_command_name).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_help_lists_the_configured_prefixes() {
+        let prefixes = |list: &[&str]| list.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            prefixes_tip(&prefixes(&["!", ".", ";"])),
+            "My command prefixes are `!`, `.` and `;`"
+        );
+        assert_eq!(
+            prefixes_tip(&prefixes(&["", "?"])),
+            "My command prefix is `?`"
+        );
+        assert_eq!(prefixes_tip(&[]), "I have no command prefixes");
+    }
+}
@@ -1,120 +1,143 @@
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    time::Duration,
+};
+
 use serenity::{
-    framework::standard::{macros::command, Args, CommandResult},
-    model::channel::Message,
+    async_trait,
+    client::bridge::gateway::GatewayIntents,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{
+        channel::Message,
+        guild::{Guild, Role},
+        id::{ChannelId, GuildId, RoleId, UserId},
+        interactions::{
+            message_component::MessageComponentInteraction, Interaction,
+            InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+        },
+        permissions::Permissions,
+    },
     prelude::*,
     utils::Color,
 };
 
-// TODO: Make it not hardcoded someday
-const VALID_ESPECIAL: &[&str] = &["Gamer", "Otaku"];
-
-const VALID_PLATAFORMA: &[&str] = &[
-    "EpicGames",
-    "NintendoOnline",
-    "Origin",
-    "PlaystationNetwork",
-    "Steam",
-    "XboxLive",
-];
-
-const VALID_OS: &[&str] = &[
-    "DragonflyBSD",
-    "FreeBSD",
-    "OpenBSD",
-    "NetBSD",
-    "Linux",
-    "Illumos",
-    "Solaris",
-    "MacOS",
-    "Windows",
-];
-
-const VALID_PROGRAMMING: &[&str] = &[
-    "Ada",
-    "Agda",
-    "Assembly",
-    "BrainFuck",
-    "C-lang",
-    "C++",
-    "C#",
-    "Carp",
-    "Clojure",
-    "CommonLisp",
-    "Coq",
-    "Crystal",
-    "CSS",
-    "D-lang",
-    "Dart",
-    "ECMAScript",
-    "Elixir",
-    "Elm",
-    "Erlang",
-    "F#",
-    "Fortran",
-    "Go",
-    "Groovy",
-    "Haskell",
-    "HTML",
-    "Idris",
-    "Janet",
-    "Java",
-    "Julia",
-    "Kotlin",
-    "Matlab",
-    "Nim",
-    "Latex",
-    "Lua",
-    "OCaml",
-    "Octave",
-    "PureScript",
-    "Python",
-    "R-lang",
-    "Racket",
-    "Ruby",
-    "Rust",
-    "Scala",
-    "Scheme",
-    "Shell",
-    "Swift",
-    "TypeScript",
-    "WebAssembly",
-    "Zig",
-];
-
-const REACTION_OK: char = '🟢';
-const REACTION_FAIL: char = '🔴';
-const REACTION_WARNING: char = '⚠';
+use time::OffsetDateTime;
+
+use crate::{
+    config::{self, Category, Config},
+    duration,
+    module::Module,
+    paginator::Pages,
+    reply::{self, Reply, RoleChange, REACTION_FAIL, REACTION_WARNING},
+    storage::{RoleChangeRecord, RoleChangeSource, ScheduledTask, TaskAction},
+    types::StorageContainer,
+};
+
+/// What `role add`, `role rm` and `role set` do with the requested roles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ChangeKind {
+    Add,
+    Remove,
+    /// Add the requested roles and remove the other roles of the category.
+    Set,
+}
+
+/// Start of the custom id of the select menus posted by `role menu`.
+const MENU_PREFIX: &str = "role-menu:";
+
+/// Most options of a select menu, and most select menus of a message.
+const MENU_OPTIONS: usize = 25;
+const MENU_LISTS: usize = 5;
+
+/// Number of roles per page of `role list <CATEGORY>`.
+const CATEGORY_PAGE_SIZE: usize = 20;
+
+/// Number of role changes shown by `role history`.
+const HISTORY_LIMIT: usize = 10;
+
+/// Number of members per page of `role who`.
+const WHO_PAGE_SIZE: usize = 20;
+
+/// Longest time roles can be added for with `--for`.
+const MAX_EXPIRY: Duration = Duration::from_secs(365 * 86_400);
+
+#[group]
+#[commands(role, rolelog)]
+struct Management;
+
+/// Self-service role commands.
+///
+/// Needs the privileged server members intent: `role who` and the member counts of
+/// `role list` read the cached members of the guild.
+pub struct ManagementModule;
+
+#[async_trait]
+impl Module for ManagementModule {
+    fn name(&self) -> &'static str {
+        "management"
+    }
+
+    fn groups(&self) -> Vec<&'static CommandGroup> {
+        vec![&MANAGEMENT_GROUP]
+    }
+
+    fn intents(&self) -> GatewayIntents {
+        GatewayIntents::GUILD_MEMBERS
+    }
+
+    async fn interaction_create(&self, ctx: &Context, interaction: &Interaction) {
+        if let Interaction::MessageComponent(component) = interaction {
+            if component.data.custom_id.starts_with(MENU_PREFIX) {
+                menu_interaction(ctx, component).await;
+            }
+        }
+    }
+}
 
 // TODO:
 /// Manage roles for the caller.
 ///
-/// It has 3 subcommands:
-///     - add: Add roles
+/// It has 9 subcommands:
+///     - add: Add roles, for a while if `--for` is given
 ///     - rm: Remove roles
+///     - set: Replace your roles of a category
 ///     - list: list categories and roles
+///     - mine: list your roles and when they expire
+///     - who: list the members with a role
+///     - history: list the role changes of a member
+///     - sync: compare the catalog with the guild roles
+///     - menu: post a menu to pick roles
 ///
-/// `add` and `remove` subcommands reacts to the command message in case of:
+/// Roles can be separated by commas, and `*` stands for every role of the category.
+///
+/// `add`, `remove` and `set` subcommands reacts to the command message in case of:
 ///     success: 🟢
 ///     fail: 🔴
 ///     a role is invalid for the category: ⚠
 #[command]
 #[only_in(guild)]
-#[sub_commands(add, rm, list)]
-#[usage = "role <add | adicionar> <CATEGORY> <ROLES ...>` or `role <rm | remove | remover> \
-           <CATEGORY> <ROLES ...>` or `role <list | lista> [CATEGORY]"]
+#[sub_commands(add, rm, set, list, mine, who, history, sync, menu)]
+#[usage = "role <add | adicionar> <CATEGORY> <ROLES ...> [--for DURATION]` or `role <rm | remove | \
+           remover> <CATEGORY> <ROLES ...>` or `role set <CATEGORY> <ROLES ...>` or `role <list | \
+           lista> [CATEGORY]` or `role mine` or `role who <ROLE> [PAGE]` or `role history [@USER]` \
+           or `role sync [create]` or `role menu <CATEGORY>"]
 async fn role(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     if args.is_empty() {
-        msg.channel_id
-            .send_message(&ctx.http, |m| {
-                m.embed(|e| {
-                    e.title(" ").color(Color::RED).description(
-                        "Wrong usage of command.\n\nUsage: `role <add | adicionar> <CATEGORY> \
-                         <ROLES ...>` or `role <rm | remove | remover> <CATEGORY> <ROLES ...>` or \
-                         `role <list | lista> [CATEGORY]`\n\nFor more information do `help role`",
-                    )
-                })
-            })
-            .await?;
+        Reply::embed(
+            " ",
+            Color::RED,
+            "Wrong usage of command.\n\nUsage: `role <add | adicionar> <CATEGORY> <ROLES ...> \
+             [--for DURATION]` or `role <rm | remove | remover> <CATEGORY> <ROLES ...>` or `role \
+             set <CATEGORY> <ROLES ...>` or `role <list | lista> [CATEGORY]` or `role mine` or \
+             `role who <ROLE> [PAGE]` or `role history [@USER]` or `role sync [create]` or `role \
+             menu <CATEGORY>`\n\nFor more information do `help role`",
+        )
+        .send(ctx, msg)
+        .await?;
     }
 
     Ok(())
@@ -122,7 +145,8 @@ async fn role(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
 
 /// Add roles for the caller.
 ///
-/// You can get the categories with `role list`.
+/// You can get the categories with `role list`. With `--for` and a `DURATION` like `3d`,
+/// at most a year, the roles are removed again once it passed.
 ///
 /// It reacts to the command message in case of:
 ///     success: `🟢`
@@ -131,101 +155,23 @@ async fn role(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
 #[command]
 #[only_in(guild)]
 #[aliases(adicionar)]
-#[usage = "role add <CATEGORY> <ROLES ...>` or `role adicionar <CATEGORY> <ROLES ...>"]
-async fn add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+#[usage = "role add <CATEGORY> <ROLES ...> [--for DURATION]` or `role adicionar <CATEGORY> <ROLES \
+           ...> [--for DURATION]"]
+#[example = "role add prog Rust,Zig"]
+#[example = "role add events game-night --for 3d"]
+async fn add(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     if args.is_empty() {
-        msg.channel_id
-            .send_message(&ctx.http, |m| {
-                m.embed(|e| {
-                    e.title(" ").color(Color::RED).description(
-                        "Usage: `role add <CATEGORY> <ROLES ...>` or `role adicionar <CATEGORY> \
-                         <ROLES ...>`",
-                    )
-                })
-            })
-            .await?;
-    }
-
-    let category = args.single::<String>().unwrap_or_default();
-    let category_list = category_valid_roles(&category);
-
-    let cache = &ctx.cache;
-    let (roles, roles_str) = {
-        let mut roles_str = String::new();
-        let mut roles = Vec::new();
-
-        while let Ok(arg) = args.single::<String>() {
-            if is_valid_role(&arg, category_list) {
-                roles_str.push_str(&arg);
-                roles_str.push(' ');
-                for guild_id in cache.guilds().await.iter() {
-                    if let Some(guild) = cache.guild(guild_id).await {
-                        for (&id, role) in guild.roles.iter() {
-                            if arg == role.name {
-                                roles.push(id);
-                            }
-                        }
-                    }
-                }
-            } else {
-                eprintln!("Invalid role for {}: {}", category, &arg);
-                msg.react(&ctx.http, REACTION_WARNING).await?;
-            }
-        }
-
-        (roles, roles_str)
-    };
-
-    if roles.is_empty() || roles_str.is_empty() {
-        eprintln!("Roles {} not found", roles_str);
-        msg.react(&ctx.http, REACTION_FAIL).await?;
-    } else {
-        let channel = match cache.guild_channel(msg.channel_id).await {
-            Some(c) => c,
-            _ => {
-                eprintln!("Failed to get guild channel");
-                msg.react(&ctx.http, REACTION_FAIL).await?;
-                return Ok(());
-            },
-        };
-
-        let guild = match channel.guild(cache).await {
-            Some(g) => g,
-            _ => {
-                eprintln!("Failed to get channel guild");
-                msg.react(&ctx.http, REACTION_FAIL).await?;
-                return Ok(());
-            },
-        };
-
-        let mut member = match guild.member(&ctx.http, msg.author.id).await {
-            Ok(m) => m,
-            _ => {
-                eprintln!("Failed to get member");
-                msg.react(&ctx.http, REACTION_FAIL).await?;
-                return Ok(());
-            },
-        };
-
-        match member.add_roles(&ctx.http, &roles).await {
-            Ok(_) => {
-                println!(
-                    "Successfully added {} to roles {}",
-                    msg.author.name, roles_str
-                );
-                msg.react(&ctx.http, REACTION_OK).await?;
-            },
-            Err(why) => {
-                eprintln!(
-                    "Failed to add {} to roles {}: {}",
-                    msg.author.name, roles_str, why
-                );
-                msg.react(&ctx.http, REACTION_FAIL).await?;
-            },
-        };
+        return Reply::embed(
+            " ",
+            Color::RED,
+            "Usage: `role add <CATEGORY> <ROLES ...> [--for DURATION]` or `role adicionar \
+             <CATEGORY> <ROLES ...> [--for DURATION]`",
+        )
+        .send(ctx, msg)
+        .await;
     }
 
-    Ok(())
+    change_roles(ctx, msg, args, ChangeKind::Add).await
 }
 
 /// Remove roles for the caller.
@@ -241,166 +187,1767 @@ async fn add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
 #[aliases(remove, remover)]
 #[usage = "role rm <CATEGORY> <ROLES ...>` or `role remove <CATEGORY> <ROLES>` or `role remover \
            <CATEGORY> <ROLES ...>"]
-async fn rm(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+#[example = "role rm prog *"]
+async fn rm(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     if args.is_empty() {
-        msg.channel_id
-            .send_message(&ctx.http, |m| {
-                m.embed(|e| {
-                    e.title(" ").color(Color::RED).description(
-                        "Usage: `role rm <CATEGORY> <ROLES ...>` or `role remove <CATEGORY> \
-                         <ROLES ...>` or `role remover <CATEGORY> <ROLES ...>`",
-                    )
-                })
-            })
-            .await?;
+        return Reply::embed(
+            " ",
+            Color::RED,
+            "Usage: `role rm <CATEGORY> <ROLES ...>` or `role remove <CATEGORY> <ROLES ...>` or \
+             `role remover <CATEGORY> <ROLES ...>`",
+        )
+        .send(ctx, msg)
+        .await;
     }
 
-    let category = args.single::<String>().unwrap_or_default();
-    let category_list = category_valid_roles(&category);
-
-    let cache = &ctx.cache;
-    let (roles, roles_str) = {
-        let mut roles_str = String::new();
-        let mut roles = Vec::new();
-
-        while let Ok(arg) = args.single::<String>() {
-            if is_valid_role(&arg, category_list) {
-                roles_str.push_str(&arg);
-                roles_str.push(' ');
-                for guild_id in cache.guilds().await.iter() {
-                    if let Some(guild) = cache.guild(guild_id).await {
-                        for (&id, role) in guild.roles.iter() {
-                            if arg == role.name {
-                                roles.push(id);
-                            }
-                        }
-                    }
-                }
-            } else {
-                eprintln!("Invalid role for {}: {}", category, &arg);
-                msg.react(&ctx.http, REACTION_WARNING).await?;
-            }
-        }
+    change_roles(ctx, msg, args, ChangeKind::Remove).await
+}
+
+/// Replace your roles of a category with the given ones, in a single change.
+///
+/// It reacts to the command message like `role add`.
+#[command]
+#[only_in(guild)]
+#[aliases(definir)]
+#[min_args(2)]
+#[usage = "role set <CATEGORY> <ROLES ...>` or `role definir <CATEGORY> <ROLES ...>"]
+#[example = "role set os Linux"]
+async fn set(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    change_roles(ctx, msg, args, ChangeKind::Set).await
+}
+
+/// List the categories or list the category roles.
+///
+/// The roles of a category show how many members have them, 20 per page.
+#[command]
+#[max_args(1)]
+#[only_in(guild)]
+#[aliases(listar)]
+#[usage = "role list [CATEGORY]` or `role listar [CATEGORY]"]
+async fn list(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let config = config::current(ctx).await;
+
+    if args.is_empty() {
+        return categories_reply(&config.catalog).send(ctx, msg).await;
+    }
+
+    let name = args.single::<String>().unwrap_or_default();
+    let counts = match msg.guild(&ctx.cache).await {
+        Some(guild) => role_counts(&guild),
+        None => HashMap::new(),
+    };
+    category_pages(&name, config.category(&name), &counts)
+        .send(ctx, msg)
+        .await
+}
+
+/// List the members with a role of the catalog.
+///
+/// Only the members in the cache are listed, 20 per page.
+#[command]
+#[min_args(1)]
+#[max_args(2)]
+#[only_in(guild)]
+#[aliases(quem)]
+#[usage = "role who <ROLE> [PAGE]"]
+async fn who(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let name = args.single::<String>().unwrap_or_default();
+    let page = args.single::<usize>().unwrap_or(1);
+
+    let config = config::current(ctx).await;
+    if !config.is_self_assignable(&name) {
+        return Reply::embed(
+            " ",
+            Color::RED,
+            format!("`{}` is not a role of the catalog.", name),
+        )
+        .send(ctx, msg)
+        .await;
+    }
+
+    let guild = match msg.guild(&ctx.cache).await {
+        Some(guild) => guild,
+        None => return Ok(()),
+    };
+    let role_id = match guild.role_by_name(&name) {
+        Some(role) => role.id,
+        None => {
+            return Reply::embed(
+                " ",
+                Color::RED,
+                format!("There is no role `{}` in this guild.", name),
+            )
+            .send(ctx, msg)
+            .await;
+        },
+    };
+
+    let mut members = guild
+        .members
+        .values()
+        .filter(|m| m.roles.contains(&role_id))
+        .map(|m| m.user.id)
+        .collect::<Vec<_>>();
+    members.sort_unstable();
+
+    who_reply(&name, &members, page).send(ctx, msg).await
+}
+
+/// List your roles of the catalog by category, and when the temporary ones expire.
+#[command]
+#[num_args(0)]
+#[only_in(guild)]
+#[aliases(meus)]
+#[usage = "role mine"]
+async fn mine(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let member = guild_id.member(ctx, msg.author.id).await?;
+    let guild_roles = guild_roles(ctx, msg).await;
+    let config = config::current(ctx).await;
+
+    let expiries = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(storage) => role_expiries(&storage.read().await.tasks, guild_id, msg.author.id),
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    mine_reply(&config, &member.roles, &guild_roles, &expiries, now)
+        .send(ctx, msg)
+        .await
+}
 
-        (roles, roles_str)
+/// List the last role changes made through the bot for a member, yours by default.
+#[command]
+#[max_args(1)]
+#[only_in(guild)]
+#[aliases(historico)]
+#[usage = "role history [@USER]"]
+async fn history(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
     };
 
-    if roles.is_empty() || roles_str.is_empty() {
-        eprintln!("Roles {}not found", roles_str);
-        msg.react(&ctx.http, REACTION_FAIL).await?;
+    let user_id = if args.is_empty() {
+        msg.author.id
     } else {
-        let channel = match cache.guild_channel(msg.channel_id).await {
-            Some(c) => c,
-            _ => {
-                eprintln!("Failed to get guild channel");
-                msg.react(&ctx.http, REACTION_FAIL).await?;
-                return Ok(());
+        match args.single::<UserId>() {
+            Ok(id) => id,
+            Err(_) => {
+                return Reply::embed(
+                    " ",
+                    Color::RED,
+                    "Invalid user. Mention them or use their id.",
+                )
+                .send(ctx, msg)
+                .await;
             },
-        };
+        }
+    };
 
-        let guild = match channel.guild(cache).await {
-            Some(g) => g,
-            _ => {
-                eprintln!("Failed to get channel guild");
-                msg.react(&ctx.http, REACTION_FAIL).await?;
-                return Ok(());
-            },
-        };
+    let changes = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(storage) => storage
+            .read()
+            .await
+            .guild(guild_id)
+            .map(|g| {
+                g.role_history(user_id.0)
+                    .into_iter()
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default(),
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
 
-        let mut member = match guild.member(&ctx.http, msg.author.id).await {
-            Ok(m) => m,
-            _ => {
-                eprintln!("Failed to get member");
-                msg.react(&ctx.http, REACTION_FAIL).await?;
-                return Ok(());
-            },
-        };
+            return Ok(());
+        },
+    };
 
-        match member.remove_roles(&ctx.http, &roles).await {
-            Ok(_) => {
-                println!(
-                    "Successfully removed {} to roles {}",
-                    msg.author.name, roles_str
-                );
-                msg.react(&ctx.http, REACTION_OK).await?;
-            },
-            Err(why) => {
-                eprintln!(
-                    "Failed to remove {} to roles {}: {}",
-                    msg.author.name, roles_str, why
-                );
-                msg.react(&ctx.http, REACTION_FAIL).await?;
-            },
-        };
+    history_reply(user_id, &changes).send(ctx, msg).await
+}
+
+/// Post a menu where members pick their roles of a category.
+///
+/// The roles are split in lists of 25. The roles picked in a list replace the roles of
+/// that list the member had, up to the `limit` of the category.
+#[command]
+#[num_args(1)]
+#[only_in(guild)]
+#[required_permissions("MANAGE_ROLES")]
+#[usage = "role menu <CATEGORY>"]
+#[example = "role menu prog"]
+async fn menu(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let name = args.single::<String>().unwrap_or_default();
+    let config = config::current(ctx).await;
+    let category = match config.category(&name) {
+        Some(c) => c,
+        None => {
+            return Reply::embed(" ", Color::RED, format!("Invalid category: `{}`", name))
+                .send(ctx, msg)
+                .await;
+        },
+    };
+
+    let lists = menu_lists(category);
+    if lists.len() > MENU_LISTS {
+        return Reply::embed(
+            " ",
+            Color::RED,
+            format!(
+                "`{}` has too many roles for a menu, at most {} fit.",
+                category.name,
+                MENU_LISTS * MENU_OPTIONS
+            ),
+        )
+        .send(ctx, msg)
+        .await;
     }
 
+    let mut text = format!("Pick your roles of `{}`.", category.name);
+    if let Some(limit) = category.limit {
+        text.push_str(&format!(" You can have up to {}.", limit));
+    }
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title(category.name.to_uppercase())
+                    .color(Color::BLUE)
+                    .description(text)
+            })
+            .components(|c| {
+                for (index, roles) in lists.iter().enumerate() {
+                    let max = category.limit.map_or(roles.len(), |l| l.min(roles.len()));
+                    c.create_action_row(|row| {
+                        row.create_select_menu(|menu| {
+                            menu.custom_id(format!("{}{}:{}", MENU_PREFIX, category.name, index))
+                                .placeholder(format!("{} to {}", roles[0], roles[roles.len() - 1]))
+                                .min_values(0)
+                                .max_values(max as u64)
+                                .options(|o| {
+                                    for role in roles {
+                                        o.create_option(|opt| opt.label(role).value(role));
+                                    }
+                                    o
+                                })
+                        })
+                    });
+                }
+                c
+            })
+        })
+        .await?;
+
     Ok(())
 }
 
-/// List the categories or list the category roles.
+/// Apply the roles picked in a `role menu` and answer only to the member.
+async fn menu_interaction(ctx: &Context, component: &MessageComponentInteraction) {
+    let text = match menu_pick(ctx, component).await {
+        Ok(text) => text,
+        Err(why) => why,
+    };
+
+    let result = component
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|d| {
+                    d.content(text)
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                })
+        })
+        .await;
+    if let Err(why) = result {
+        eprintln!("Failed to answer a role menu: {:?}", why);
+    }
+}
+
+/// Change the roles of the member who picked roles in a `role menu`.
+///
+/// Returns what to tell the member, as an error if nothing changed.
+async fn menu_pick(
+    ctx: &Context, component: &MessageComponentInteraction,
+) -> Result<String, String> {
+    let (guild_id, member) = match (component.guild_id, &component.member) {
+        (Some(guild_id), Some(member)) => (guild_id, member),
+        _ => return Err("Role menus only work in a guild.".to_string()),
+    };
+    let (category, index) = parse_menu_id(&component.data.custom_id)
+        .ok_or_else(|| "This menu is outdated.".to_string())?;
+
+    let config = config::current(ctx).await;
+    let guild = guild_id
+        .to_guild_cached(&ctx.cache)
+        .await
+        .ok_or_else(|| "I can't see the roles of this guild yet, try again later.".to_string())?;
+    let guild_roles = guild
+        .roles
+        .values()
+        .map(|r| (r.id, r.name.clone()))
+        .collect::<Vec<_>>();
+
+    let change = menu_change(
+        config.category(category),
+        index,
+        &component.data.values,
+        &member.roles,
+        &guild_roles,
+    )?;
+
+    // Only the roles the member gains or loses have to be manageable
+    let bot_id = ctx.cache.current_user_id().await;
+    let bot_position = bot_position(&guild, bot_id);
+    let problems = role_problems(
+        &change.applied_to(&member.roles),
+        &guild.roles,
+        bot_position,
+    );
+    if !problems.is_empty() {
+        return Err(problems.join("\n"));
+    }
+
+    let applied = match reply::apply_roles(ctx, guild_id, member.user.id, &change).await {
+        Ok(applied) => applied,
+        Err(why) => {
+            eprintln!(
+                "Failed to change the roles of {}: {:?}: {}",
+                member.user.name, change, why
+            );
+            return Err("I could not change your roles.".to_string());
+        },
+    };
+    if applied.is_empty() {
+        return Ok("You already have these roles.".to_string());
+    }
+
+    record_role_change(
+        ctx,
+        guild_id,
+        member.user.id,
+        &applied,
+        RoleChangeSource::Menu,
+    )
+    .await;
+    let ids = |roles: &[RoleId]| roles.iter().map(|r| r.0).collect::<Vec<_>>();
+    Ok(format!(
+        "You {}.",
+        describe_roles(&ids(&applied.add), &ids(&applied.remove), None)
+    ))
+}
+
+/// The sorted roles of `category`, in lists that fit in a select menu.
+fn menu_lists(category: &Category) -> Vec<Vec<String>> {
+    let mut roles = category.roles.clone();
+    roles.sort_unstable();
+    roles.chunks(MENU_OPTIONS).map(<[String]>::to_vec).collect()
+}
+
+/// The category name and the list index of the custom id of a `role menu`.
+fn parse_menu_id(id: &str) -> Option<(&str, usize)> {
+    let rest = id.strip_prefix(MENU_PREFIX)?;
+    let mut parts = rest.rsplitn(2, ':');
+    let index = parts.next()?.parse().ok()?;
+    let category = parts.next()?;
+    Some((category, index))
+}
+
+/// The change of a member with `member_roles` who picked `picked` in the list `index` of
+/// `category`: the picked roles are added and the other roles of the list removed.
+fn menu_change(
+    category: Option<&Category>, index: usize, picked: &[String], member_roles: &[RoleId],
+    guild_roles: &[(RoleId, String)],
+) -> Result<RoleChange, String> {
+    let outdated = || "This menu is outdated.".to_string();
+    let category = category.ok_or_else(outdated)?;
+    let list = menu_lists(category)
+        .into_iter()
+        .nth(index)
+        .ok_or_else(outdated)?;
+    if picked.iter().any(|p| !list.contains(p)) {
+        return Err(outdated());
+    }
+
+    let role_id = |name: &str| {
+        guild_roles
+            .iter()
+            .find(|(_, r)| r == name)
+            .map(|(id, _)| *id)
+    };
+    let change = RoleChange {
+        add: picked.iter().filter_map(|p| role_id(p)).collect(),
+        remove: list
+            .iter()
+            .filter(|r| !picked.contains(r))
+            .filter_map(|r| role_id(r))
+            .collect(),
+        expires_in: None,
+    };
+    if change.add.len() < picked.len() {
+        return Err("Some of these roles don't exist in this guild.".to_string());
+    }
+
+    if let Some(limit) = category.limit {
+        let kept = category
+            .roles
+            .iter()
+            .filter(|r| !list.contains(r))
+            .filter_map(|r| role_id(r))
+            .filter(|id| member_roles.contains(id))
+            .count();
+        if kept + change.add.len() > limit {
+            return Err(format!(
+                "You can have at most {} roles of `{}`.",
+                limit, category.name
+            ));
+        }
+    }
+
+    Ok(change)
+}
+
+/// Compare the catalog with the roles of the guild, and create the missing ones with
+/// `create`.
+///
+/// Missing roles are catalog roles without a guild role of the same name, and orphaned
+/// roles are guild roles given through the bot that are no longer in the catalog. Created
+/// roles use the `color`, `hoist` and `mentionable` options of their category.
 #[command]
+#[only_in(guild)]
+#[required_permissions("MANAGE_ROLES")]
 #[max_args(1)]
+#[aliases(sincronizar)]
+#[usage = "role sync [create]"]
+async fn sync(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let create = match args.single::<String>() {
+        Ok(arg) if arg == "create" => true,
+        Ok(arg) => {
+            return Reply::embed(" ", Color::RED, format!("Invalid argument: `{}`", arg))
+                .send(ctx, msg)
+                .await;
+        },
+        Err(_) => false,
+    };
+
+    let given = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(storage) => storage
+            .read()
+            .await
+            .guild(guild_id)
+            .map(|g| {
+                g.role_changes
+                    .iter()
+                    .flat_map(|c| c.added.iter().copied())
+                    .collect()
+            })
+            .unwrap_or_default(),
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    let config = config::current(ctx).await;
+    let guild_roles = guild_roles(ctx, msg).await;
+    let mut missing = missing_roles(&config.catalog, &guild_roles);
+    let orphaned = orphaned_roles(&config, &guild_roles, &given);
+
+    let mut created = Vec::new();
+    if create {
+        let mut failed = Vec::new();
+        for (category, name) in missing {
+            let result = guild_id
+                .create_role(&ctx.http, |r| {
+                    r.name(name)
+                        .hoist(category.hoist)
+                        .mentionable(category.mentionable);
+                    if let Some(color) = category.color {
+                        r.colour(u64::from(color));
+                    }
+                    r
+                })
+                .await;
+            match result {
+                Ok(_) => created.push(name),
+                Err(why) => {
+                    eprintln!("Failed to create the role {}: {:?}", name, why);
+                    failed.push((category, name));
+                },
+            }
+        }
+        missing = failed;
+    }
+
+    sync_reply(&missing, &orphaned, &created, &guild_roles)
+        .send(ctx, msg)
+        .await
+}
+
+/// Set the channel where every role change made through the bot is posted.
+///
+/// Without arguments it shows the current channel, and `off` stops posting the changes.
+#[command]
 #[only_in(guild)]
-#[aliases(listar)]
-#[usage = "role list [CATEGORY]` or `role listar [CATEGORY]"]
-async fn list(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+#[required_permissions("MANAGE_GUILD")]
+#[max_args(1)]
+#[usage = "rolelog [#CHANNEL | off]"]
+async fn rolelog(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let storage = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(s) => s.clone(),
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
     if args.is_empty() {
-        let categories = {
-            let mut categories = [
-                "especial",
-                "os | so | sistema-operacional",
-                "plataforma | plataforma-de-jogos",
-                "prog | programming | programação",
-            ];
-            categories.sort_unstable();
-            format!("```\n{}\n```", categories.join("\n"))
+        let text = match storage
+            .read()
+            .await
+            .guild(guild_id)
+            .and_then(|g| g.role_log)
+        {
+            Some(id) => format!("Role changes are posted in <#{}>.", id),
+            None => "Role changes are not posted anywhere.".to_string(),
         };
+        return Reply::embed("ROLE LOG", Color::BLUE, text)
+            .send(ctx, msg)
+            .await;
+    }
 
-        msg.channel_id
-            .send_message(&ctx.http, |m| {
-                m.embed(|e| {
-                    e.title("CATEGORIES")
-                        .color(Color::BLUE)
-                        .description(categories)
-                })
+    let arg = args.single::<String>().unwrap_or_default();
+    let channel_id = if arg == "off" {
+        None
+    } else {
+        match arg.parse::<ChannelId>() {
+            Ok(id) => Some(id),
+            Err(_) => {
+                return Reply::embed(" ", Color::RED, format!("Invalid channel: `{}`", arg))
+                    .send(ctx, msg)
+                    .await;
+            },
+        }
+    };
+
+    {
+        let mut storage = storage.write().await;
+        storage.guild_mut(guild_id).role_log = channel_id.map(|id| id.0);
+        storage.save()?;
+    }
+
+    let text = match channel_id {
+        Some(id) => format!("Role changes are now posted in <#{}>.", id),
+        None => "Role changes are no longer posted.".to_string(),
+    };
+    Reply::embed("ROLE LOG", Color::TEAL, text)
+        .send(ctx, msg)
+        .await
+}
+
+/// Record a role change of `user_id` in the storage and post it in the role log channel.
+///
+/// Failures are only logged: the roles already changed.
+pub async fn record_role_change(
+    ctx: &Context, guild_id: GuildId, user_id: UserId, change: &RoleChange,
+    source: RoleChangeSource,
+) {
+    let storage = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(s) => s.clone(),
+        None => return,
+    };
+
+    let record = RoleChangeRecord {
+        user: user_id.0,
+        added: change.add.iter().map(|r| r.0).collect(),
+        removed: change.remove.iter().map(|r| r.0).collect(),
+        time: OffsetDateTime::now_utc().unix_timestamp(),
+        source,
+        duration: change.expires_in,
+    };
+
+    let role_log = {
+        let mut storage = storage.write().await;
+        let guild = storage.guild_mut(guild_id);
+        guild.role_changes.push(record.clone());
+        let role_log = guild.role_log;
+
+        // A role added for good or removed no longer expires
+        for &role in record.added.iter().chain(&record.removed) {
+            let expiry = TaskAction::RemoveRole {
+                guild: guild_id.0,
+                user: user_id.0,
+                role,
+            };
+            match record.duration {
+                Some(secs) if record.added.contains(&role) => {
+                    match i64::try_from(secs)
+                        .ok()
+                        .and_then(|secs| record.time.checked_add(secs))
+                    {
+                        Some(due) => {
+                            storage.schedule(due, expiry);
+                        },
+                        None => eprintln!("Role {} expires too late to be removed", role),
+                    }
+                },
+                _ => {
+                    storage.cancel(&expiry);
+                },
+            }
+        }
+
+        if let Err(why) = storage.save() {
+            eprintln!("Failed to save the storage: {}", why);
+        }
+        role_log
+    };
+
+    if let Some(channel_id) = role_log {
+        if let Err(why) = role_log_reply(&record)
+            .send_to(ctx, ChannelId(channel_id))
+            .await
+        {
+            eprintln!("Failed to post in the role log of {}: {}", guild_id, why);
+        }
+    }
+}
+
+async fn change_roles(
+    ctx: &Context, msg: &Message, mut args: Args, kind: ChangeKind,
+) -> CommandResult {
+    let category = args.single::<String>().unwrap_or_default();
+    let mut requested = Vec::new();
+    while let Ok(arg) = args.single::<String>() {
+        requested.push(arg);
+    }
+
+    let (requested, expires_in) = match split_duration(requested) {
+        Ok((_, Some(_))) if kind == ChangeKind::Remove => {
+            return Reply::embed(" ", Color::RED, "Only added roles can expire.")
+                .send(ctx, msg)
+                .await;
+        },
+        Ok(split) => split,
+        Err(why) => return Reply::embed(" ", Color::RED, why).send(ctx, msg).await,
+    };
+
+    let guild_roles = guild_roles(ctx, msg).await;
+    let config = config::current(ctx).await;
+
+    let mut reply = role_change_reply(config.category(&category), &requested, &guild_roles, kind);
+    if let Some(change) = &mut reply.roles {
+        change.expires_in = expires_in.map(|d| d.as_secs());
+    }
+
+    if let (Some(change), Some(guild)) = (&reply.roles, msg.guild(&ctx.cache).await) {
+        let bot_id = ctx.cache.current_user_id().await;
+        let bot_position = bot_position(&guild, bot_id);
+
+        // Only the roles the author gains or loses have to be manageable
+        let member_roles = match guild.member(ctx, msg.author.id).await {
+            Ok(member) => member.roles,
+            Err(_) => Vec::new(),
+        };
+        let problems = role_problems(
+            &change.applied_to(&member_roles),
+            &guild.roles,
+            bot_position,
+        );
+        if !problems.is_empty() {
+            reply.roles = None;
+            reply.reactions.push(REACTION_FAIL);
+            reply.embed = Reply::embed(" ", Color::RED, problems.join("\n")).embed;
+        }
+    }
+
+    reply.send(ctx, msg).await
+}
+
+/// The catalog roles without a guild role of the same name, with their category.
+fn missing_roles<'a>(
+    catalog: &'a [Category], guild_roles: &[(RoleId, String)],
+) -> Vec<(&'a Category, &'a str)> {
+    catalog
+        .iter()
+        .flat_map(|c| c.roles.iter().map(move |r| (c, r.as_str())))
+        .filter(|(_, name)| !guild_roles.iter().any(|(_, r)| r == name))
+        .collect()
+}
+
+/// The names of the guild roles in `given` that are no longer in the catalog, sorted.
+fn orphaned_roles(
+    config: &Config, guild_roles: &[(RoleId, String)], given: &HashSet<u64>,
+) -> Vec<String> {
+    let mut orphaned = guild_roles
+        .iter()
+        .filter(|(id, name)| given.contains(&id.0) && !config.is_self_assignable(name))
+        .map(|(_, name)| name.clone())
+        .collect::<Vec<_>>();
+    orphaned.sort();
+    orphaned
+}
+
+fn sync_reply(
+    missing: &[(&Category, &str)], orphaned: &[String], created: &[&str],
+    guild_roles: &[(RoleId, String)],
+) -> Reply {
+    if missing.is_empty() && orphaned.is_empty() && created.is_empty() {
+        return Reply::embed(
+            "ROLE SYNC",
+            Color::DARK_GREEN,
+            "Every role of the catalog exists in this guild.",
+        );
+    }
+
+    let mut sections = Vec::new();
+    if !created.is_empty() {
+        let lines = created
+            .iter()
+            .map(|name| format!("`{}`", name))
+            .collect::<Vec<_>>();
+        sections.push(format!("**Created**\n{}", lines.join("\n")));
+    }
+    if !missing.is_empty() {
+        let lines = missing
+            .iter()
+            .map(|(category, name)| {
+                // Names that only differ by case are most likely typos in the catalog
+                let similar = guild_roles
+                    .iter()
+                    .find(|(_, r)| r.to_lowercase() == name.to_lowercase());
+                match similar {
+                    Some((_, r)) => {
+                        format!("`{}` ({}), the guild has `{}`", name, category.name, r)
+                    },
+                    None => format!("`{}` ({})", name, category.name),
+                }
             })
-            .await?;
+            .collect::<Vec<_>>();
+        sections.push(format!("**Missing**\n{}", lines.join("\n")));
+    }
+    if !orphaned.is_empty() {
+        let lines = orphaned
+            .iter()
+            .map(|name| format!("`{}`", name))
+            .collect::<Vec<_>>();
+        sections.push(format!(
+            "**Orphaned** (given through the bot, not in the catalog)\n{}",
+            lines.join("\n")
+        ));
+    }
+    if !missing.is_empty() && created.is_empty() {
+        sections.push("Use `role sync create` to create the missing roles.".to_string());
+    }
+
+    let color = if missing.is_empty() && orphaned.is_empty() {
+        Color::DARK_GREEN
     } else {
-        let category = args.single::<String>().unwrap_or_default();
-        let category_list = category_valid_roles(&category);
+        Color::ORANGE
+    };
+    Reply::embed("ROLE SYNC", color, sections.join("\n\n"))
+}
 
-        let s = {
-            let mut s = category_list
-                .iter()
-                .map(|&s| s.to_string())
-                .collect::<Vec<_>>();
-            s.sort_unstable();
-            format!("```\n{}\n```", s.join("\n"))
+/// The position of the highest role of the bot, if its member is cached.
+pub fn bot_position(guild: &Guild, bot_id: UserId) -> Option<i64> {
+    guild
+        .members
+        .get(&bot_id)
+        .map(|bot| top_position(&guild.roles, &bot.roles))
+}
+
+/// The position of the highest of `member_roles`, 0 like `@everyone` if it has none.
+pub fn top_position(roles: &HashMap<RoleId, Role>, member_roles: &[RoleId]) -> i64 {
+    member_roles
+        .iter()
+        .filter_map(|id| roles.get(id))
+        .map(|r| r.position)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Why the roles of `change` can't be given or taken, one line per problem.
+///
+/// Roles with moderation or administration permissions are never given, and the bot can
+/// only manage roles below its highest role (`bot_position`), if it is known.
+pub fn role_problems(
+    change: &RoleChange, roles: &HashMap<RoleId, Role>, bot_position: Option<i64>,
+) -> Vec<String> {
+    let elevated = Permissions::ADMINISTRATOR
+        | Permissions::MANAGE_GUILD
+        | Permissions::MANAGE_ROLES
+        | Permissions::MANAGE_CHANNELS
+        | Permissions::MANAGE_MESSAGES
+        | Permissions::MANAGE_NICKNAMES
+        | Permissions::MANAGE_WEBHOOKS
+        | Permissions::MANAGE_EMOJIS
+        | Permissions::KICK_MEMBERS
+        | Permissions::BAN_MEMBERS;
+
+    let mut problems = Vec::new();
+    for id in change.add.iter().chain(&change.remove) {
+        let role = match roles.get(id) {
+            Some(role) => role,
+            None => continue,
         };
 
-        msg.channel_id
-            .send_message(&ctx.http, |m| {
-                m.embed(|e| {
-                    e.title(category.to_uppercase())
-                        .color(Color::BLUE)
-                        .description(s)
-                })
+        if change.add.contains(id) && role.permissions.intersects(elevated) {
+            problems.push(format!(
+                "`{}` has moderation or administration permissions, so it can't be self-assigned.",
+                role.name
+            ));
+        } else if role.managed {
+            problems.push(format!(
+                "`{}` is managed by an integration, so nobody can be given it.",
+                role.name
+            ));
+        } else if bot_position.map_or(false, |top| role.position >= top) {
+            problems.push(format!(
+                "`{}` is not below my highest role, so I can't give or take it. Move my role \
+                 above it in the server settings.",
+                role.name
+            ));
+        }
+    }
+    problems
+}
+
+/// The `(id, name)` of the roles of the guild of `msg`, if it is cached.
+async fn guild_roles(ctx: &Context, msg: &Message) -> Vec<(RoleId, String)> {
+    match msg.guild(&ctx.cache).await {
+        Some(guild) => guild
+            .roles
+            .iter()
+            .map(|(&id, role)| (id, role.name.clone()))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Take the `--for DURATION` option out of the arguments.
+fn split_duration(mut args: Vec<String>) -> Result<(Vec<String>, Option<Duration>), String> {
+    let i = match args.iter().position(|a| a == "--for") {
+        Some(i) => i,
+        None => return Ok((args, None)),
+    };
+
+    let duration = match args.get(i + 1) {
+        Some(text) => match duration::parse(text) {
+            Some(duration) if duration <= MAX_EXPIRY => duration,
+            Some(_) => return Err("Roles can be added for at most a year.".to_string()),
+            None => return Err(format!("Invalid duration: `{}`", text)),
+        },
+        None => return Err("Missing the duration after `--for`.".to_string()),
+    };
+    args.drain(i..i + 2);
+
+    Ok((args, Some(duration)))
+}
+
+/// When the temporary roles of `user_id` expire, as `(role, due)`.
+fn role_expiries(tasks: &[ScheduledTask], guild_id: GuildId, user_id: UserId) -> Vec<(u64, i64)> {
+    tasks
+        .iter()
+        .filter_map(|t| match t.action {
+            TaskAction::RemoveRole { guild, user, role }
+                if guild == guild_id.0 && user == user_id.0 =>
+            {
+                Some((role, t.due))
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// Reply listing every category with its aliases.
+fn categories_reply(catalog: &[Category]) -> Reply {
+    let mut categories = catalog
+        .iter()
+        .map(|c| {
+            let mut names = vec![c.name.as_str()];
+            names.extend(c.aliases.iter().map(String::as_str));
+            names.join(" | ")
+        })
+        .collect::<Vec<_>>();
+    categories.sort_unstable();
+
+    Reply::embed(
+        "CATEGORIES",
+        Color::BLUE,
+        format!("```\n{}\n```", categories.join("\n")),
+    )
+}
+
+/// Reply listing the roles of a category, with how many members have each of them.
+fn category_pages(
+    name: &str, category: Option<&Category>, counts: &HashMap<String, usize>,
+) -> Pages {
+    let mut roles = category.map(|c| c.roles.clone()).unwrap_or_default();
+    roles.sort_unstable();
+
+    let lines = roles
+        .iter()
+        .map(|role| format!("{} ({})", role, counts.get(role).copied().unwrap_or(0)))
+        .collect::<Vec<_>>();
+
+    Pages::from_lines(name.to_uppercase(), Color::BLUE, &lines, CATEGORY_PAGE_SIZE)
+        .wrap("```\n", "\n```")
+}
+
+/// Reply adding, removing or setting the `requested` roles of `category`.
+///
+/// Every requested role outside the category gets a warning reaction. `guild_roles` are
+/// the `(id, name)` of the roles of the guild; if none of the requested roles is found
+/// there, the reply is a failure reaction instead of a role change.
+fn role_change_reply(
+    category: Option<&Category>, requested: &[String], guild_roles: &[(RoleId, String)],
+    kind: ChangeKind,
+) -> Reply {
+    let mut reply = Reply::default();
+    let mut roles = Vec::new();
+
+    for name in &expand_roles(category, requested) {
+        if category.map_or(false, |c| is_valid_role(name, &c.roles)) {
+            roles.extend(
+                guild_roles
+                    .iter()
+                    .filter(|(_, role)| role == name)
+                    .map(|&(id, _)| id),
+            );
+        } else {
+            eprintln!("Invalid role: {}", name);
+            reply.reactions.push(REACTION_WARNING);
+        }
+    }
+
+    if roles.is_empty() {
+        eprintln!("Roles {:?} not found", requested);
+        reply.reactions.push(REACTION_FAIL);
+        return reply;
+    }
+
+    reply.roles = Some(match kind {
+        ChangeKind::Add => RoleChange {
+            add: roles,
+            ..RoleChange::default()
+        },
+        ChangeKind::Remove => RoleChange {
+            remove: roles,
+            ..RoleChange::default()
+        },
+        ChangeKind::Set => {
+            let others = category.map(|c| c.roles.as_slice()).unwrap_or_default();
+            let remove = guild_roles
+                .iter()
+                .filter(|(id, name)| is_valid_role(name, others) && !roles.contains(id))
+                .map(|&(id, _)| id)
+                .collect();
+            RoleChange {
+                add: roles,
+                remove,
+                ..RoleChange::default()
+            }
+        },
+    });
+
+    reply
+}
+
+/// Split the comma separated role names and replace `*` with every role of `category`.
+fn expand_roles(category: Option<&Category>, requested: &[String]) -> Vec<String> {
+    let mut names = Vec::new();
+    for name in requested.iter().flat_map(|r| r.split(',')) {
+        let name = name.trim();
+        match category {
+            _ if name.is_empty() => {},
+            Some(category) if name == "*" => names.extend(category.roles.iter().cloned()),
+            _ => names.push(name.to_string()),
+        }
+    }
+    names
+}
+
+/// Reply listing the roles of the catalog a member has by category, with `expiries` from
+/// [`role_expiries`].
+fn mine_reply(
+    config: &Config, roles: &[RoleId], guild_roles: &[(RoleId, String)], expiries: &[(u64, i64)],
+    now: i64,
+) -> Reply {
+    let held = guild_roles
+        .iter()
+        .filter(|(id, _)| roles.contains(id))
+        .collect::<Vec<_>>();
+
+    let mut fields = Vec::new();
+    for category in &config.catalog {
+        let mut lines = held
+            .iter()
+            .filter(|(_, name)| category.roles.contains(name))
+            .map(|(id, name)| {
+                let expiry = expiries.iter().find(|&&(role, _)| role == id.0);
+                match expiry {
+                    Some(&(_, due)) => {
+                        let wait = Duration::from_secs((due - now).max(0) as u64);
+                        format!("{}: expires in {}", name, duration::format(wait))
+                    },
+                    None => name.clone(),
+                }
             })
-            .await?;
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            continue;
+        }
+
+        lines.sort_unstable();
+        fields.push((category.name.clone(), lines.join("\n"), false));
     }
-    Ok(())
+
+    if fields.is_empty() {
+        return Reply::embed(
+            "YOUR ROLES",
+            Color::BLUE,
+            "You have no roles of the catalog.",
+        );
+    }
+
+    let mut reply = Reply::embed("YOUR ROLES", Color::BLUE, "Your roles of the catalog:");
+    if let Some(embed) = &mut reply.embed {
+        embed.fields = fields;
+    }
+    reply
+}
+
+/// Reply listing a page of the `members` with the role called `name`.
+fn who_reply(name: &str, members: &[UserId], page: usize) -> Reply {
+    if members.is_empty() {
+        return Reply::embed(
+            name.to_uppercase(),
+            Color::BLUE,
+            format!("Nobody has `{}`.", name),
+        );
+    }
+
+    let pages = (members.len() + WHO_PAGE_SIZE - 1) / WHO_PAGE_SIZE;
+    let page = page.max(1).min(pages);
+    let lines = members
+        .iter()
+        .skip((page - 1) * WHO_PAGE_SIZE)
+        .take(WHO_PAGE_SIZE)
+        .map(|id| format!("<@{}>", id))
+        .collect::<Vec<_>>();
+
+    Reply::embed(
+        name.to_uppercase(),
+        Color::BLUE,
+        format!(
+            "{} members have `{}`:\n{}\n\nPage {} of {}",
+            members.len(),
+            name,
+            lines.join("\n"),
+            page,
+            pages
+        ),
+    )
+}
+
+/// Number of cached members with each role, by role name.
+fn role_counts(guild: &Guild) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for member in guild.members.values() {
+        for id in &member.roles {
+            if let Some(role) = guild.roles.get(id) {
+                *counts.entry(role.name.clone()).or_insert(0) += 1;
+            }
+        }
+    }
+    counts
+}
+
+/// Reply posted in the role log for a role change.
+fn role_log_reply(record: &RoleChangeRecord) -> Reply {
+    Reply::embed(
+        "ROLES CHANGED",
+        Color::BLUE,
+        format!(
+            "<@{}> {} with a {}.",
+            record.user,
+            describe_change(record),
+            record.source.name()
+        ),
+    )
+}
+
+/// Reply listing the last role changes of `user_id`, newest first.
+fn history_reply(user_id: UserId, changes: &[RoleChangeRecord]) -> Reply {
+    if changes.is_empty() {
+        return Reply::embed(
+            "ROLE HISTORY",
+            Color::BLUE,
+            format!("<@{}> has no role changes.", user_id),
+        );
+    }
+
+    let mut text = format!("Role changes of <@{}>:", user_id);
+    for change in changes.iter().rev().take(HISTORY_LIMIT) {
+        text.push_str(&format!(
+            "\n`{}` {} ({})",
+            OffsetDateTime::from_unix_timestamp(change.time).format("%F %H:%M:%S"),
+            describe_change(change),
+            change.source.name()
+        ));
+    }
+
+    Reply::embed("ROLE HISTORY", Color::BLUE, text)
+}
+
+/// Describe the roles added and removed, like `got <@&1> and lost <@&2>`.
+fn describe_change(record: &RoleChangeRecord) -> String {
+    describe_roles(&record.added, &record.removed, record.duration)
 }
 
-fn category_valid_roles(category: &str) -> &[&str] {
-    match category {
-        "especial" => VALID_ESPECIAL,
-        "os" | "so" | "sistema-operacional" => VALID_OS,
-        "plataforma" | "plataforma-de-jogos" => VALID_PLATAFORMA,
-        "prog" | "programming" | "programação" => VALID_PROGRAMMING,
-        _ => &[],
+/// Describe added and removed roles, like "got @Rust for 3d and lost @Zig".
+fn describe_roles(added: &[u64], removed: &[u64], duration: Option<u64>) -> String {
+    let mentions = |ids: &[u64]| {
+        ids.iter()
+            .map(|id| format!("<@&{}>", id))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    let mut parts = Vec::new();
+    if !added.is_empty() {
+        match duration {
+            Some(secs) => parts.push(format!(
+                "got {} for {}",
+                mentions(added),
+                duration::format(Duration::from_secs(secs))
+            )),
+            None => parts.push(format!("got {}", mentions(added))),
+        }
+    }
+    if !removed.is_empty() {
+        parts.push(format!("lost {}", mentions(removed)));
     }
+    parts.join(" and ")
 }
 
-fn is_valid_role(role: &str, valid_list: &[&str]) -> bool {
-    valid_list.contains(&role)
+fn is_valid_role(role: &str, valid_list: &[String]) -> bool {
+    valid_list.iter().any(|r| r == role)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        testing::{message, MockDiscord},
+        types::ConfigContainer,
+    };
+
+    use super::*;
+
+    const GUILD: u64 = 30;
+    const CHANNEL: u64 = 20;
+
+    #[tokio::test]
+    async fn list_shows_the_categories() {
+        let mock = MockDiscord::start().await;
+        let msg = message("!role list", 10, Some(GUILD), CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        let embeds = mock.sent_embeds();
+        assert_eq!(embeds[0]["title"], "CATEGORIES");
+        assert!(embeds[0]["description"]
+            .as_str()
+            .unwrap()
+            .contains("prog | programming | programação"));
+    }
+
+    #[tokio::test]
+    async fn category_roles_show_their_member_count() {
+        let mock = MockDiscord::start().await;
+        mock.add_guild(GUILD, CHANNEL, &[(40, "Rust"), (41, "Zig")])
+            .await;
+        mock.add_member(GUILD, 50, &[40]).await;
+        mock.add_member(GUILD, 51, &[40, 41]).await;
+        mock.insert::<ConfigContainer>(Arc::new(RwLock::new(prog_config(&["Rust", "Zig", "Go"]))))
+            .await;
+        let msg = message("!role list prog", 10, Some(GUILD), CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        assert_eq!(
+            mock.sent_embeds()[0]["description"],
+            "```\nGo (0)\nRust (2)\nZig (1)\n```"
+        );
+    }
+
+    #[tokio::test]
+    async fn long_categories_are_paginated() {
+        let mock = MockDiscord::start().await;
+        let msg = message("!role list prog", 10, Some(GUILD), CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        let description = mock.sent_embeds()[0]["description"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(description.matches(" (0)").count(), CATEGORY_PAGE_SIZE);
+        assert!(description.ends_with("```\n\nPage 1 of 3"));
+        assert_eq!(mock.reactions(), vec![
+            crate::paginator::REACTION_PREVIOUS.to_string(),
+            crate::paginator::REACTION_NEXT.to_string(),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn who_lists_the_members_with_the_role() {
+        let mock = MockDiscord::start().await;
+        mock.add_guild(GUILD, CHANNEL, &[(40, "Rust"), (41, "Zig")])
+            .await;
+        mock.add_member(GUILD, 51, &[40, 41]).await;
+        mock.add_member(GUILD, 50, &[40]).await;
+        mock.add_member(GUILD, 52, &[41]).await;
+        let msg = message("!role who Rust", 10, Some(GUILD), CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        assert_eq!(
+            mock.sent_embeds()[0]["description"],
+            "2 members have `Rust`:\n<@50>\n<@51>\n\nPage 1 of 1"
+        );
+    }
+
+    #[test]
+    fn who_pages_are_clamped() {
+        let members = (0..45).map(UserId).collect::<Vec<_>>();
+
+        let description = who_reply("Rust", &members, 9).embed.unwrap().description;
+
+        assert!(description.starts_with("45 members have `Rust`:\n<@40>\n"));
+        assert!(description.ends_with("<@44>\n\nPage 3 of 3"));
+    }
+
+    #[tokio::test]
+    async fn add_gives_the_role() {
+        let mock = MockDiscord::start().await;
+        mock.add_guild(GUILD, CHANNEL, &[(40, "Rust"), (41, "Zig")])
+            .await;
+        let msg = message("!role add prog Rust", 10, Some(GUILD), CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        let edits = mock.member_edits();
+        assert_eq!(edits.len(), 1);
+        let roles = edits[0]["roles"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r.to_string().trim_matches('"').to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(roles, vec!["40".to_string()]);
+        assert_eq!(
+            mock.reactions(),
+            vec![crate::reply::REACTION_OK.to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn role_changes_are_recorded_and_posted() {
+        let mock = MockDiscord::start().await;
+        mock.add_guild(GUILD, CHANNEL, &[(40, "Rust")]).await;
+        {
+            let data = mock.ctx.data.read().await;
+            let mut storage = data.get::<StorageContainer>().unwrap().write().await;
+            storage.guild_mut(GuildId(GUILD)).role_log = Some(99);
+        }
+        let msg = message("!role add prog Rust", 10, Some(GUILD), CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        {
+            let data = mock.ctx.data.read().await;
+            let storage = data.get::<StorageContainer>().unwrap().read().await;
+            let history = storage.guild(GuildId(GUILD)).unwrap().role_history(10);
+            assert_eq!(history.len(), 1);
+            assert_eq!(history[0].added, vec![40]);
+            assert_eq!(history[0].source, RoleChangeSource::Command);
+        }
+        assert!(mock
+            .requests()
+            .iter()
+            .any(|r| r.path == "/channels/99/messages"));
+        assert_eq!(
+            mock.sent_embeds()[0]["description"],
+            "<@10> got <@&40> with a command."
+        );
+    }
+
+    #[tokio::test]
+    async fn temporary_roles_expire_until_removed() {
+        let mock = MockDiscord::start().await;
+        mock.add_guild(GUILD, CHANNEL, &[(40, "Rust")]).await;
+        let expiry = TaskAction::RemoveRole {
+            guild: GUILD,
+            user:  10,
+            role:  40,
+        };
+
+        let msg = message("!role add prog Rust --for 3d", 10, Some(GUILD), CHANNEL);
+        mock.send(&msg).await.unwrap();
+        {
+            let data = mock.ctx.data.read().await;
+            let storage = data.get::<StorageContainer>().unwrap().read().await;
+            assert_eq!(storage.tasks.len(), 1);
+            assert_eq!(storage.tasks[0].action, expiry);
+        }
+
+        let msg = message("!role rm prog Rust", 10, Some(GUILD), CHANNEL);
+        mock.send(&msg).await.unwrap();
+        let data = mock.ctx.data.read().await;
+        let storage = data.get::<StorageContainer>().unwrap().read().await;
+        assert!(storage.tasks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn add_warns_about_roles_outside_the_category() {
+        let mock = MockDiscord::start().await;
+        mock.add_guild(GUILD, CHANNEL, &[(40, "Rust")]).await;
+        let msg = message("!role add os Rust", 10, Some(GUILD), CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        assert!(mock.member_edits().is_empty());
+        assert_eq!(mock.reactions(), vec![
+            REACTION_WARNING.to_string(),
+            REACTION_FAIL.to_string()
+        ]);
+    }
+
+    #[test]
+    fn role_change_only_uses_roles_of_the_category() {
+        let config = Config::default();
+        let guild_roles = vec![
+            (RoleId(40), "Rust".to_string()),
+            (RoleId(41), "Linux".to_string()),
+        ];
+        let requested = vec!["Rust".to_string(), "Linux".to_string()];
+
+        let reply = role_change_reply(
+            config.category("prog"),
+            &requested,
+            &guild_roles,
+            ChangeKind::Remove,
+        );
+
+        assert_eq!(reply.reactions, vec![REACTION_WARNING]);
+        assert_eq!(
+            reply.roles,
+            Some(RoleChange {
+                remove: vec![RoleId(40)],
+                ..RoleChange::default()
+            })
+        );
+    }
+
+    #[test]
+    fn history_shows_the_newest_changes_first() {
+        let change = |added: Vec<u64>, removed: Vec<u64>, time| RoleChangeRecord {
+            user: 10,
+            added,
+            removed,
+            time,
+            source: RoleChangeSource::Command,
+            duration: None,
+        };
+        let changes = vec![
+            change(vec![40], vec![], 0),
+            change(vec![41], vec![40], 86_400),
+        ];
+
+        let reply = history_reply(UserId(10), &changes);
+
+        assert_eq!(
+            reply.embed.unwrap().description,
+            "Role changes of <@10>:\n`1970-01-02 00:00:00` got <@&41> and lost <@&40> \
+             (command)\n`1970-01-01 00:00:00` got <@&40> (command)"
+        );
+    }
+
+    #[test]
+    fn the_duration_is_taken_out_of_the_roles() {
+        let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+
+        assert_eq!(
+            split_duration(args("Rust --for 3d Zig")),
+            Ok((args("Rust Zig"), Some(Duration::from_secs(3 * 86_400))))
+        );
+        assert_eq!(split_duration(args("Rust")), Ok((args("Rust"), None)));
+        assert_eq!(
+            split_duration(args("Rust --for")),
+            Err("Missing the duration after `--for`.".to_string())
+        );
+        assert_eq!(
+            split_duration(args("Rust --for soon")),
+            Err("Invalid duration: `soon`".to_string())
+        );
+        assert_eq!(
+            split_duration(args("Rust --for 53w")),
+            Err("Roles can be added for at most a year.".to_string())
+        );
+    }
+
+    #[test]
+    fn mine_lists_the_catalog_roles_with_their_expiry() {
+        let guild_roles = vec![
+            (RoleId(40), "Rust".to_string()),
+            (RoleId(41), "Linux".to_string()),
+            (RoleId(42), "Moderator".to_string()),
+        ];
+        let roles = vec![RoleId(40), RoleId(41), RoleId(42)];
+
+        let reply = mine_reply(
+            &Config::default(),
+            &roles,
+            &guild_roles,
+            &[(40, 3_700)],
+            100,
+        );
+
+        let fields = reply.embed.unwrap().fields;
+        assert_eq!(fields.len(), 2);
+        assert!(fields.contains(&("prog".to_string(), "Rust: expires in 1h".to_string(), false)));
+        assert!(fields.contains(&("os".to_string(), "Linux".to_string(), false)));
+    }
+
+    #[test]
+    fn commas_and_wildcards_are_expanded() {
+        let config = Config::default();
+        let requested = vec!["Rust,Zig".to_string(), "*".to_string()];
+
+        let names = expand_roles(config.category("prog"), &requested);
+
+        assert_eq!(&names[..2], &["Rust".to_string(), "Zig".to_string()]);
+        assert_eq!(
+            names.len(),
+            2 + config.category("prog").unwrap().roles.len()
+        );
+        assert_eq!(expand_roles(None, &requested), vec!["Rust", "Zig", "*"]);
+    }
+
+    #[test]
+    fn set_removes_the_other_roles_of_the_category() {
+        let config = Config::default();
+        let guild_roles = vec![
+            (RoleId(40), "Rust".to_string()),
+            (RoleId(41), "Zig".to_string()),
+            (RoleId(42), "Linux".to_string()),
+        ];
+
+        let reply = role_change_reply(
+            config.category("prog"),
+            &["Zig".to_string()],
+            &guild_roles,
+            ChangeKind::Set,
+        );
+
+        assert_eq!(
+            reply.roles,
+            Some(RoleChange {
+                add: vec![RoleId(41)],
+                remove: vec![RoleId(40)],
+                ..RoleChange::default()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn set_replaces_the_roles_in_one_edit() {
+        let mock = MockDiscord::start().await;
+        mock.add_guild(GUILD, CHANNEL, &[(40, "Rust"), (41, "Zig"), (42, "Linux")])
+            .await;
+
+        let msg = message("!role add prog Rust", 10, Some(GUILD), CHANNEL);
+        mock.send(&msg).await.unwrap();
+        let msg = message("!role add os Linux", 10, Some(GUILD), CHANNEL);
+        mock.send(&msg).await.unwrap();
+        let msg = message("!role set prog Zig", 10, Some(GUILD), CHANNEL);
+        mock.send(&msg).await.unwrap();
+
+        let edits = mock.member_edits();
+        assert_eq!(edits.len(), 3);
+        let roles = edits[2]["roles"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r.to_string().trim_matches('"').to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(roles, vec!["42".to_string(), "41".to_string()]);
+    }
+
+    fn guild_role(id: u64, name: &str, permissions: Permissions, position: i64) -> Role {
+        serde_json::from_value(serde_json::json!({
+            "id": id.to_string(),
+            "guild_id": GUILD.to_string(),
+            "name": name,
+            "color": 0,
+            "hoist": false,
+            "managed": false,
+            "mentionable": false,
+            "permissions": permissions.bits().to_string(),
+            "position": position,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn unsafe_roles_are_explained() {
+        let roles = vec![
+            guild_role(40, "Rust", Permissions::empty(), 1),
+            guild_role(41, "Helper", Permissions::MANAGE_MESSAGES, 2),
+            guild_role(42, "Zig", Permissions::empty(), 5),
+        ]
+        .into_iter()
+        .map(|r| (r.id, r))
+        .collect::<HashMap<_, _>>();
+        let change = RoleChange {
+            add: vec![RoleId(40), RoleId(41)],
+            remove: vec![RoleId(42)],
+            ..RoleChange::default()
+        };
+
+        assert_eq!(role_problems(&change, &roles, Some(5)), vec![
+            "`Helper` has moderation or administration permissions, so it can't be self-assigned."
+                .to_string(),
+            "`Zig` is not below my highest role, so I can't give or take it. Move my role above \
+             it in the server settings."
+                .to_string(),
+        ]);
+        assert_eq!(role_problems(&change, &roles, None).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn roles_above_the_bot_are_refused() {
+        let mock = MockDiscord::start().await;
+        mock.add_guild(GUILD, CHANNEL, &[(45, "OxiBot"), (40, "Rust")])
+            .await;
+        let bot_id = mock.ctx.cache.current_user_id().await;
+        mock.add_member(GUILD, bot_id.0, &[45]).await;
+        let msg = message("!role add prog Rust", 10, Some(GUILD), CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        assert!(mock.member_edits().is_empty());
+        assert_eq!(mock.reactions(), vec![REACTION_FAIL.to_string()]);
+        assert!(mock.sent_embeds()[0]["description"]
+            .as_str()
+            .unwrap()
+            .starts_with("`Rust` is not below my highest role"));
+    }
+
+    #[tokio::test]
+    async fn only_roles_that_change_must_be_below_the_bot() {
+        let mock = MockDiscord::start().await;
+        mock.add_guild(GUILD, CHANNEL, &[(40, "Rust"), (45, "OxiBot"), (41, "Zig")])
+            .await;
+        let bot_id = mock.ctx.cache.current_user_id().await;
+        mock.add_member(GUILD, bot_id.0, &[45]).await;
+        mock.add_member(GUILD, 10, &[]).await;
+        // `Zig` is above the bot, but the author doesn't have it so it is not removed
+        let msg = message("!role set prog Rust", 10, Some(GUILD), CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        let edits = mock.member_edits();
+        assert_eq!(edits.len(), 1);
+        let roles = edits[0]["roles"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r.to_string().trim_matches('"').to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(roles, vec!["40".to_string()]);
+    }
+
+    fn prog_config(roles: &[&str]) -> Config {
+        Config {
+            catalog: vec![Category {
+                name: "prog".to_string(),
+                aliases: Vec::new(),
+                roles: roles.iter().map(|r| r.to_string()).collect(),
+                color: Some(0xdea584),
+                hoist: false,
+                mentionable: true,
+                limit: None,
+            }],
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn sync_reports_missing_and_orphaned_roles() {
+        let config = prog_config(&["Rust", "C-lang", "Zig"]);
+        let guild_roles = vec![
+            (RoleId(40), "Rust".to_string()),
+            (RoleId(41), "c-lang".to_string()),
+            (RoleId(42), "Haskell".to_string()),
+            (RoleId(43), "Moderator".to_string()),
+        ];
+        let given = vec![40, 42].into_iter().collect();
+
+        let missing = missing_roles(&config.catalog, &guild_roles);
+        let orphaned = orphaned_roles(&config, &guild_roles, &given);
+        assert_eq!(
+            missing.iter().map(|(_, name)| *name).collect::<Vec<_>>(),
+            vec!["C-lang", "Zig"]
+        );
+        assert_eq!(orphaned, vec!["Haskell".to_string()]);
+
+        let embed = sync_reply(&missing, &orphaned, &[], &guild_roles)
+            .embed
+            .unwrap();
+        assert_eq!(embed.color, Color::ORANGE);
+        assert!(embed
+            .description
+            .contains("`C-lang` (prog), the guild has `c-lang`\n`Zig` (prog)"));
+        assert!(embed.description.contains("**Orphaned**"));
+        assert!(embed.description.contains("role sync create"));
+    }
+
+    #[tokio::test]
+    async fn sync_creates_the_missing_roles() {
+        let mock = MockDiscord::start().await;
+        mock.add_guild(GUILD, CHANNEL, &[(40, "Rust")]).await;
+        mock.grant(GUILD, GUILD, Permissions::MANAGE_ROLES).await;
+        mock.add_member(GUILD, 10, &[]).await;
+        mock.insert::<ConfigContainer>(Arc::new(RwLock::new(prog_config(&["Rust", "Zig"]))))
+            .await;
+        let msg = message("!role sync create", 10, Some(GUILD), CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        let created = mock
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "POST" && r.path == format!("/guilds/{}/roles", GUILD))
+            .map(|r| r.body)
+            .collect::<Vec<_>>();
+        assert_eq!(created.len(), 1);
+        assert_eq!(created[0]["name"], "Zig");
+        assert_eq!(created[0]["color"], 0xdea584);
+        assert_eq!(created[0]["mentionable"], true);
+        let embed = &mock.sent_embeds()[0];
+        assert_eq!(embed["description"], "**Created**\n`Zig`");
+    }
+
+    #[test]
+    fn menu_ids_are_parsed() {
+        assert_eq!(parse_menu_id("role-menu:prog:1"), Some(("prog", 1)));
+        assert_eq!(parse_menu_id("role-menu:a:b:0"), Some(("a:b", 0)));
+        assert_eq!(parse_menu_id("role-menu:prog"), None);
+        assert_eq!(parse_menu_id("other:prog:1"), None);
+    }
+
+    #[test]
+    fn menu_picks_replace_the_roles_of_the_list() {
+        let mut config = prog_config(&["Zig", "Rust", "Go"]);
+        config.catalog[0].limit = Some(2);
+        let guild_roles = vec![
+            (RoleId(40), "Rust".to_string()),
+            (RoleId(41), "Zig".to_string()),
+            (RoleId(42), "Go".to_string()),
+        ];
+        let category = config.category("prog");
+        assert_eq!(menu_lists(category.unwrap()), vec![vec![
+            "Go".to_string(),
+            "Rust".to_string(),
+            "Zig".to_string()
+        ]]);
+
+        let change = menu_change(
+            category,
+            0,
+            &["Rust".to_string()],
+            &[RoleId(41)],
+            &guild_roles,
+        );
+        assert_eq!(
+            change,
+            Ok(RoleChange {
+                add: vec![RoleId(40)],
+                remove: vec![RoleId(42), RoleId(41)],
+                expires_in: None,
+            })
+        );
+
+        let all = vec!["Go".to_string(), "Rust".to_string(), "Zig".to_string()];
+        assert_eq!(
+            menu_change(category, 0, &all, &[], &guild_roles),
+            Err("You can have at most 2 roles of `prog`.".to_string())
+        );
+        assert_eq!(
+            menu_change(category, 1, &[], &[], &guild_roles),
+            Err("This menu is outdated.".to_string())
+        );
+        assert_eq!(
+            menu_change(category, 0, &["C".to_string()], &[], &guild_roles),
+            Err("This menu is outdated.".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn menu_posts_a_select_menu_per_list() {
+        let mock = MockDiscord::start().await;
+        let msg = message("!role menu prog", 10, Some(GUILD), CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        let sent = mock.sent_messages();
+        let rows = sent[0]["components"].as_array().unwrap();
+        assert_eq!(rows.len(), 2);
+        let menu = &rows[1]["components"][0];
+        assert_eq!(menu["custom_id"], "role-menu:prog:1");
+        assert_eq!(menu["min_values"], 0);
+        assert_eq!(menu["options"].as_array().unwrap().len(), 24);
+    }
+
+    #[test]
+    fn role_change_fails_without_guild_roles() {
+        let config = Config::default();
+        let requested = vec!["Rust".to_string()];
+
+        let reply = role_change_reply(config.category("prog"), &requested, &[], ChangeKind::Add);
+
+        assert_eq!(reply.reactions, vec![REACTION_FAIL]);
+        assert_eq!(reply.roles, None);
+    }
 }
@@ -1,25 +1,40 @@
 use std::process::Command;
 
+use crate::{module::Module, reply::Reply, types::ConfigContainer};
+
 use serenity::{
-    framework::standard::{macros::command, Args, CommandResult},
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
     model::channel::Message,
     prelude::*,
 };
 
+#[group]
+#[commands(fortune, ping)]
+struct Meme;
+
+/// Fun commands.
+pub struct MemeModule;
+
+impl Module for MemeModule {
+    fn name(&self) -> &'static str {
+        "meme"
+    }
+
+    fn groups(&self) -> Vec<&'static CommandGroup> {
+        vec![&MEME_GROUP]
+    }
+}
+
 /// Respond Pong.
 #[command]
 #[usage = "pint [TEXT]"]
 async fn ping(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    if args.is_empty() {
-        msg.reply(ctx, "Pong!").await?;
-    } else {
-        let person: String = args.single().unwrap();
-        msg.channel_id
-            .say(&ctx.http, format!("Pong {}", person))
-            .await?;
-    }
+    let person = args.single::<String>().ok();
 
-    Ok(())
+    ping_reply(person.as_deref()).send(ctx, msg).await
 }
 
 /// Tell a fortune.
@@ -29,7 +44,11 @@ async fn ping(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
 #[usage = "fortune [CATEGORY]"]
 async fn fortune(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
     let fortune = if args.is_empty() {
-        Command::new("fortune").arg("-s").output()
+        let files = match ctx.data.read().await.get::<ConfigContainer>() {
+            Some(config) => config.read().await.fortune.files.clone(),
+            None => Vec::new(),
+        };
+        Command::new("fortune").arg("-s").args(&files).output()
     } else {
         let arg = match args.single::<String>() {
             Ok(a) => a,
@@ -42,15 +61,37 @@ async fn fortune(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult
             .args(vec!["-s", "-c", &arg])
             .output()
     };
-    let mut str = String::from("```\n");
-    match fortune {
-        Ok(out) => str.push_str(&out.stdout.iter().map(|&c| c as char).collect::<String>()),
+
+    let output = match fortune {
+        Ok(out) => Some(out.stdout),
         Err(why) => {
-            println!("Error calling uname: {:?}", why);
-            str.push_str("Failed to get a fortune")
+            println!("Error calling fortune: {:?}", why);
+            None
         },
     };
 
+    fortune_reply(output.as_deref()).send(ctx, msg).await
+}
+
+/// Reply to `ping`, with the optional text.
+fn ping_reply(person: Option<&str>) -> Reply {
+    match person {
+        None => Reply {
+            reference: true,
+            ..Reply::text("Pong!")
+        },
+        Some(person) => Reply::text(format!("Pong {}", person)),
+    }
+}
+
+/// Reply with the output of `fortune`, or `None` if it couldn't run.
+fn fortune_reply(output: Option<&[u8]>) -> Reply {
+    let mut str = String::from("```\n");
+    match output {
+        Some(out) => str.push_str(&out.iter().map(|&c| c as char).collect::<String>()),
+        None => str.push_str("Failed to get a fortune"),
+    };
+
     if str == "```\n" {
         str.clear();
         str.push_str("> No fortunes found :slight_frown: ");
@@ -58,7 +99,49 @@ async fn fortune(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult
         str.push_str("\n```");
     }
 
-    msg.channel_id.say(&ctx.http, str).await?;
+    Reply::text(str)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{message, MockDiscord};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn ping_replies_pong() {
+        let mock = MockDiscord::start().await;
+        let msg = message("!ping", 10, None, 20);
+
+        mock.send(&msg).await.unwrap();
+
+        assert_eq!(mock.sent_contents(), vec!["Pong!".to_string()]);
+    }
 
-    Ok(())
+    #[tokio::test]
+    async fn ping_echoes_the_argument() {
+        let mock = MockDiscord::start().await;
+        let msg = message("!ping friend", 10, None, 20);
+
+        mock.send(&msg).await.unwrap();
+
+        assert_eq!(mock.sent_contents(), vec!["Pong friend".to_string()]);
+    }
+
+    #[test]
+    fn fortune_is_put_in_a_code_block() {
+        let reply = fortune_reply(Some(b"Be yourself."));
+
+        assert_eq!(reply.content.unwrap(), "```\nBe yourself.\n```");
+    }
+
+    #[test]
+    fn fortune_without_output() {
+        let reply = fortune_reply(Some(b""));
+
+        assert_eq!(
+            reply.content.unwrap(),
+            "> No fortunes found :slight_frown: "
+        );
+    }
 }
@@ -0,0 +1,1473 @@
+use std::{convert::TryFrom, time::Duration};
+
+use reqwest::header::{HeaderMap, HeaderValue};
+use serenity::{
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::{request::RequestBuilder, routing::RouteInfo},
+    model::{
+        channel::Message,
+        guild::Guild,
+        id::{ChannelId, GuildId, MessageId, RoleId, UserId},
+    },
+    prelude::*,
+    utils::Colour as Color,
+};
+
+use time::OffsetDateTime;
+
+use crate::{
+    config::{self, EscalationRule, Penalty},
+    duration,
+    management::top_position,
+    module::Module,
+    reply::Reply,
+    storage::{ModAction, ModActionKind, ScheduledTask, TaskAction},
+    types::StorageContainer,
+};
+
+/// Discord refuses to bulk delete messages older than this, in seconds.
+const BULK_DELETE_MAX_AGE: i64 = 14 * 86_400;
+
+/// Longest temporary ban or mute.
+const MAX_DURATION: Duration = Duration::from_secs(365 * 86_400);
+
+/// The Discord epoch, the first second of 2015, in milliseconds since the unix epoch.
+const DISCORD_EPOCH: u64 = 1_420_070_400_000;
+
+#[group]
+#[commands(
+    warn, warnings, kick, ban, unban, mute, unmute, purge, scheduled, case, modlog
+)]
+struct Moderation;
+
+/// Commands to warn, kick, ban, mute and purge, for the guild moderators.
+pub struct ModerationModule;
+
+impl Module for ModerationModule {
+    fn name(&self) -> &'static str {
+        "moderation"
+    }
+
+    fn groups(&self) -> Vec<&'static CommandGroup> {
+        vec![&MODERATION_GROUP]
+    }
+}
+
+/// Warn a member.
+///
+/// When the member reaches the number of warnings of an escalation rule of the
+/// configuration, the penalty of the rule is given right away.
+#[command]
+#[only_in(guild)]
+#[required_permissions("KICK_MEMBERS")]
+#[min_args(2)]
+#[usage = "warn <@USER> <REASON ...>"]
+async fn warn(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (guild_id, user_id) = match target(ctx, msg, &mut args).await? {
+        Some(target) => target,
+        None => return Ok(()),
+    };
+    let reason = reason(&args);
+
+    let action = new_action(ModActionKind::Warn, Some(user_id), msg, reason, None);
+    finish(ctx, msg, guild_id, action, Ok(()), None).await?;
+
+    let count = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(storage) => storage
+            .read()
+            .await
+            .guild(guild_id)
+            .map_or(0, |g| g.warnings(user_id.0).len()),
+        None => 0,
+    };
+
+    let config = config::current(ctx).await;
+    match config
+        .moderation
+        .escalation
+        .iter()
+        .find(|rule| rule.warnings == count)
+    {
+        Some(rule) => escalate(ctx, msg, guild_id, user_id, rule).await,
+        None => Ok(()),
+    }
+}
+
+/// List the warnings of a user.
+#[command]
+#[only_in(guild)]
+#[required_permissions("KICK_MEMBERS")]
+#[num_args(1)]
+#[usage = "warnings <@USER>"]
+async fn warnings(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let user_id = match args.single::<UserId>() {
+        Ok(id) => id,
+        Err(_) => {
+            return Reply::embed(
+                " ",
+                Color::RED,
+                "Missing or invalid user. Mention them or use their id.",
+            )
+            .send(ctx, msg)
+            .await;
+        },
+    };
+
+    let warnings = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(storage) => storage
+            .read()
+            .await
+            .guild(guild_id)
+            .map(|g| {
+                g.warnings(user_id.0)
+                    .into_iter()
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default(),
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    warnings_reply(user_id, &warnings).send(ctx, msg).await
+}
+
+/// Kick a member from the guild.
+///
+/// The reason is shown in the audit log of the guild.
+#[command]
+#[only_in(guild)]
+#[required_permissions("KICK_MEMBERS")]
+#[min_args(1)]
+#[usage = "kick <@USER> [REASON ...]"]
+async fn kick(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (guild_id, user_id) = match target(ctx, msg, &mut args).await? {
+        Some(target) => target,
+        None => return Ok(()),
+    };
+    let reason = reason(&args);
+
+    let result = kick_member(ctx, guild_id, user_id, reason.as_deref()).await;
+
+    let action = new_action(ModActionKind::Kick, Some(user_id), msg, reason, None);
+    finish(ctx, msg, guild_id, action, result, None).await
+}
+
+/// Ban a user from the guild.
+///
+/// A `DURATION` like `12h` or `7d` makes the ban temporary. `DELETE_DAYS`, from 0 to 7,
+/// deletes the messages the user sent in that many last days. The reason is shown in the
+/// audit log of the guild.
+#[command]
+#[only_in(guild)]
+#[required_permissions("BAN_MEMBERS")]
+#[min_args(1)]
+#[usage = "ban <@USER> [DURATION] [DELETE_DAYS] [REASON ...]"]
+async fn ban(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (guild_id, user_id) = match target(ctx, msg, &mut args).await? {
+        Some(target) => target,
+        None => return Ok(()),
+    };
+    let duration = optional_duration(&mut args);
+    if let Some(reply) = too_long(duration) {
+        return reply.send(ctx, msg).await;
+    }
+    let delete_days = args
+        .current()
+        .and_then(|a| a.parse::<u8>().ok())
+        .filter(|&days| days <= 7);
+    if delete_days.is_some() {
+        args.advance();
+    }
+    let delete_days = delete_days.unwrap_or(0);
+    let reason = reason(&args);
+
+    let result = ban_user(ctx, guild_id, user_id, delete_days, reason.as_deref()).await;
+
+    let kind = ModActionKind::Ban { delete_days };
+    let action = new_action(kind, Some(user_id), msg, reason, duration);
+    let lift = unban_task(guild_id, user_id);
+    finish(ctx, msg, guild_id, action, result, Some(lift)).await
+}
+
+/// Lift the ban of a user.
+#[command]
+#[only_in(guild)]
+#[required_permissions("BAN_MEMBERS")]
+#[min_args(1)]
+#[usage = "unban <@USER | USER_ID> [REASON ...]"]
+async fn unban(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (guild_id, user_id) = match target(ctx, msg, &mut args).await? {
+        Some(target) => target,
+        None => return Ok(()),
+    };
+    let reason = reason(&args);
+
+    let route = RouteInfo::RemoveBan {
+        guild_id: guild_id.0,
+        user_id:  user_id.0,
+    };
+    let result = with_reason(ctx, route, reason.as_deref()).await;
+
+    let action = new_action(ModActionKind::Unban, Some(user_id), msg, reason, None);
+    let lift = unban_task(guild_id, user_id);
+    finish(ctx, msg, guild_id, action, result, Some(lift)).await
+}
+
+/// Mute a member by giving them the mute role.
+///
+/// The mute role is set in the configuration and must exist in the guild. A `DURATION`
+/// like `10m` or `1h` makes the mute temporary.
+#[command]
+#[only_in(guild)]
+#[required_permissions("MANAGE_ROLES")]
+#[aliases(timeout)]
+#[min_args(1)]
+#[usage = "mute <@USER> [DURATION] [REASON ...]"]
+async fn mute(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (guild_id, user_id) = match target(ctx, msg, &mut args).await? {
+        Some(target) => target,
+        None => return Ok(()),
+    };
+    let duration = optional_duration(&mut args);
+    if let Some(reply) = too_long(duration) {
+        return reply.send(ctx, msg).await;
+    }
+    let reason = reason(&args);
+
+    let role_id = match mute_role(ctx, guild_id).await {
+        Ok(id) => id,
+        Err(reply) => return reply.send(ctx, msg).await,
+    };
+
+    let result = add_role(ctx, guild_id, user_id, role_id, reason.as_deref()).await;
+
+    let action = new_action(ModActionKind::Mute, Some(user_id), msg, reason, duration);
+    let lift = unmute_task(guild_id, user_id, role_id);
+    finish(ctx, msg, guild_id, action, result, Some(lift)).await
+}
+
+/// Unmute a member by removing the mute role.
+#[command]
+#[only_in(guild)]
+#[required_permissions("MANAGE_ROLES")]
+#[min_args(1)]
+#[usage = "unmute <@USER> [REASON ...]"]
+async fn unmute(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (guild_id, user_id) = match target(ctx, msg, &mut args).await? {
+        Some(target) => target,
+        None => return Ok(()),
+    };
+    let reason = reason(&args);
+
+    let role_id = match mute_role(ctx, guild_id).await {
+        Ok(id) => id,
+        Err(reply) => return reply.send(ctx, msg).await,
+    };
+
+    let route = RouteInfo::RemoveMemberRole {
+        guild_id: guild_id.0,
+        user_id:  user_id.0,
+        role_id:  role_id.0,
+    };
+    let result = with_reason(ctx, route, reason.as_deref()).await;
+
+    let action = new_action(ModActionKind::Unmute, Some(user_id), msg, reason, None);
+    let lift = unmute_task(guild_id, user_id, role_id);
+    finish(ctx, msg, guild_id, action, result, Some(lift)).await
+}
+
+/// Delete the last `COUNT` messages of the channel, or only the ones sent by `USER`.
+///
+/// Up to 100 messages are looked at, and messages older than 14 days are never deleted.
+#[command]
+#[only_in(guild)]
+#[required_permissions("MANAGE_MESSAGES")]
+#[min_args(1)]
+#[max_args(2)]
+#[usage = "purge <COUNT> [@USER]"]
+async fn purge(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let count = match args.single::<usize>() {
+        Ok(count) if (1..=100).contains(&count) => count,
+        _ => {
+            return Reply::embed(" ", Color::RED, "The count must be a number from 1 to 100.")
+                .send(ctx, msg)
+                .await;
+        },
+    };
+
+    let user_id = if args.is_empty() {
+        None
+    } else {
+        match args.single::<UserId>() {
+            Ok(id) => Some(id),
+            Err(_) => {
+                return Reply::embed(
+                    " ",
+                    Color::RED,
+                    "Invalid user. Mention them or use their id.",
+                )
+                .send(ctx, msg)
+                .await;
+            },
+        }
+    };
+
+    let messages = msg
+        .channel_id
+        .messages(&ctx.http, |r| r.before(msg.id).limit(100))
+        .await?
+        .iter()
+        .map(|m| (m.id, m.author.id))
+        .collect::<Vec<_>>();
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let ids = purge_targets(&messages, count, user_id, now);
+
+    let result = match ids.as_slice() {
+        [] => Ok(()),
+        [id] => msg.channel_id.delete_message(&ctx.http, *id).await,
+        _ => msg.channel_id.delete_messages(&ctx.http, ids.iter()).await,
+    };
+
+    let kind = ModActionKind::Purge {
+        channel: msg.channel_id.0,
+        count:   ids.len(),
+    };
+    let action = new_action(kind, user_id, msg, None, None);
+    finish(ctx, msg, guild_id, action, result, None).await
+}
+
+/// List the temporary bans and mutes of the guild and when they are lifted.
+#[command]
+#[only_in(guild)]
+#[required_permissions("KICK_MEMBERS")]
+#[aliases(pending)]
+#[usage = "scheduled"]
+async fn scheduled(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let tasks = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(storage) => storage
+            .read()
+            .await
+            .tasks
+            .iter()
+            // Reminders are private to the members who set them
+            .filter(|t| {
+                t.action.guild() == guild_id.0 && !matches!(t.action, TaskAction::Remind { .. })
+            })
+            .cloned()
+            .collect::<Vec<_>>(),
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    scheduled_reply(&tasks, now).send(ctx, msg).await
+}
+
+/// Show a moderation case.
+#[command]
+#[only_in(guild)]
+#[required_permissions("KICK_MEMBERS")]
+#[sub_commands(edit)]
+#[num_args(1)]
+#[usage = "case <ID>` or `case edit <ID> <REASON ...>"]
+async fn case(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let id = match args.single::<u64>() {
+        Ok(id) => id,
+        Err(_) => {
+            return Reply::embed(" ", Color::RED, "Invalid case number.")
+                .send(ctx, msg)
+                .await;
+        },
+    };
+
+    let case = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(storage) => storage
+            .read()
+            .await
+            .guild(guild_id)
+            .and_then(|g| g.case(id).cloned()),
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    match case {
+        Some(case) => case_reply(&case).send(ctx, msg).await,
+        None => missing_case_reply(id).send(ctx, msg).await,
+    }
+}
+
+/// Change the reason of a moderation case.
+///
+/// The edited case is posted again in the mod-log channel.
+#[command]
+#[only_in(guild)]
+#[required_permissions("KICK_MEMBERS")]
+#[min_args(2)]
+#[usage = "case edit <ID> <REASON ...>"]
+async fn edit(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let id = match args.single::<u64>() {
+        Ok(id) => id,
+        Err(_) => {
+            return Reply::embed(" ", Color::RED, "Invalid case number.")
+                .send(ctx, msg)
+                .await;
+        },
+    };
+    let reason = reason(&args);
+
+    let storage = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(s) => s.clone(),
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    let case = {
+        let mut storage = storage.write().await;
+        let case = storage.guild_mut(guild_id).case_mut(id).map(|case| {
+            case.reason = reason;
+            case.clone()
+        });
+        if case.is_some() {
+            storage.save()?;
+        }
+        case
+    };
+
+    match case {
+        Some(case) => {
+            mirror(ctx, guild_id, &case).await;
+            case_reply(&case).send(ctx, msg).await
+        },
+        None => missing_case_reply(id).send(ctx, msg).await,
+    }
+}
+
+/// Set the channel where every moderation case is posted.
+///
+/// Without arguments it shows the current channel, and `off` stops posting the cases.
+#[command]
+#[only_in(guild)]
+#[required_permissions("MANAGE_GUILD")]
+#[max_args(1)]
+#[usage = "modlog [#CHANNEL | off]"]
+async fn modlog(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let storage = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(s) => s.clone(),
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    if args.is_empty() {
+        let text = match storage.read().await.guild(guild_id).and_then(|g| g.mod_log) {
+            Some(id) => format!("Cases are posted in <#{}>.", id),
+            None => "Cases are not posted anywhere.".to_string(),
+        };
+        return Reply::embed("MOD LOG", Color::BLUE, text)
+            .send(ctx, msg)
+            .await;
+    }
+
+    let arg = args.single::<String>().unwrap_or_default();
+    let channel_id = if arg == "off" {
+        None
+    } else {
+        match arg.parse::<ChannelId>() {
+            Ok(id) => Some(id),
+            Err(_) => {
+                return Reply::embed(" ", Color::RED, format!("Invalid channel: `{}`", arg))
+                    .send(ctx, msg)
+                    .await;
+            },
+        }
+    };
+
+    {
+        let mut storage = storage.write().await;
+        storage.guild_mut(guild_id).mod_log = channel_id.map(|id| id.0);
+        storage.save()?;
+    }
+
+    let text = match channel_id {
+        Some(id) => format!("Cases are now posted in <#{}>.", id),
+        None => "Cases are no longer posted.".to_string(),
+    };
+    Reply::embed("MOD LOG", Color::TEAL, text)
+        .send(ctx, msg)
+        .await
+}
+
+/// The guild and the user a command acts on, the user taken from the first argument.
+///
+/// If the user is missing or can't be acted on, it replies with the problem and returns
+/// `None`.
+async fn target(
+    ctx: &Context, msg: &Message, args: &mut Args,
+) -> CommandResult<Option<(GuildId, UserId)>> {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(None),
+    };
+
+    let user_id = args.single::<UserId>().ok();
+    let bot_id = ctx.cache.current_user_id().await;
+
+    let error = match (target_error(user_id, msg.author.id, bot_id), user_id) {
+        (None, Some(user_id)) => hierarchy_error(ctx, guild_id, msg.author.id, user_id).await,
+        (error, _) => error,
+    };
+    match error {
+        Some(reply) => {
+            reply.send(ctx, msg).await?;
+            Ok(None)
+        },
+        None => Ok(user_id.map(|id| (guild_id, id))),
+    }
+}
+
+/// The reply refusing to act on `user_id` if it ranks as high as `author_id`.
+///
+/// Only the guild owner can act on members whose highest role is not below theirs.
+/// Users who are not members, like banned users, are below everyone.
+async fn hierarchy_error(
+    ctx: &Context, guild_id: GuildId, author_id: UserId, user_id: UserId,
+) -> Option<Reply> {
+    let guild = match ctx.cache.guild(guild_id).await {
+        Some(guild) => guild,
+        None => {
+            return Some(Reply::embed(
+                " ",
+                Color::RED,
+                "I can't check the roles of this guild right now, try again later.",
+            ))
+        },
+    };
+
+    let author = member_position(ctx, &guild, author_id).await;
+    let target = member_position(ctx, &guild, user_id).await;
+    if outranks(guild.owner_id, (author_id, author), (user_id, target)) {
+        None
+    } else {
+        Some(Reply::embed(
+            " ",
+            Color::RED,
+            format!(
+                "You can't do that to <@{}>, their highest role is not below yours.",
+                user_id
+            ),
+        ))
+    }
+}
+
+/// The position of the highest role of `user_id`, if they are a member of `guild`.
+async fn member_position(ctx: &Context, guild: &Guild, user_id: UserId) -> Option<i64> {
+    let roles = match guild.members.get(&user_id) {
+        Some(member) => member.roles.clone(),
+        None => ctx.http.get_member(guild.id.0, user_id.0).await.ok()?.roles,
+    };
+    Some(top_position(&guild.roles, &roles))
+}
+
+/// Check if the author can act on the target, given their ids and the positions of their
+/// highest roles, `None` if they are not members.
+fn outranks(
+    owner_id: UserId, (author_id, author): (UserId, Option<i64>),
+    (target_id, target): (UserId, Option<i64>),
+) -> bool {
+    if author_id == owner_id {
+        return true;
+    }
+    if target_id == owner_id {
+        return false;
+    }
+    match (author, target) {
+        (_, None) => true,
+        (Some(author), Some(target)) => author > target,
+        (None, Some(_)) => false,
+    }
+}
+
+/// Take the next argument as a duration, if it is one.
+fn optional_duration(args: &mut Args) -> Option<Duration> {
+    let duration = args.current().and_then(duration::parse);
+    if duration.is_some() {
+        args.advance();
+    }
+    duration
+}
+
+/// The reply refusing `duration`, if it is longer than [`MAX_DURATION`].
+fn too_long(duration: Option<Duration>) -> Option<Reply> {
+    duration
+        .filter(|&d| d > MAX_DURATION)
+        .map(|_| Reply::embed(" ", Color::RED, "Bans and mutes can last at most a year."))
+}
+
+/// The remaining arguments, as the reason of an action.
+fn reason(args: &Args) -> Option<String> {
+    let rest = args.rest().trim();
+    if rest.is_empty() {
+        None
+    } else {
+        Some(rest.to_string())
+    }
+}
+
+/// The id of the configured mute role in the guild, or the reply explaining it is
+/// missing.
+async fn mute_role(ctx: &Context, guild_id: GuildId) -> Result<RoleId, Reply> {
+    let name = config::current(ctx).await.moderation.mute_role;
+
+    let role_id = match ctx.cache.guild(guild_id).await {
+        Some(guild) => guild.roles.values().find(|r| r.name == name).map(|r| r.id),
+        None => None,
+    };
+
+    role_id.ok_or_else(|| {
+        Reply::embed(
+            " ",
+            Color::RED,
+            format!("There is no `{}` role in this guild.", name),
+        )
+    })
+}
+
+/// Record the action as a case if it succeeded, and reply with the outcome.
+///
+/// `lift` is the task undoing the action. It is scheduled if the action is temporary, and
+/// any pending one is cancelled otherwise, so a permanent ban replaces a temporary one
+/// and an unban cancels the scheduled unban.
+async fn finish(
+    ctx: &Context, msg: &Message, guild_id: GuildId, action: ModAction,
+    result: serenity::Result<()>, lift: Option<TaskAction>,
+) -> CommandResult {
+    if let Err(why) = result {
+        eprintln!("Moderation action {:?} failed: {}", action, why);
+        return action_reply(&action, Some(&why.to_string()))
+            .send(ctx, msg)
+            .await;
+    }
+
+    let storage = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(s) => s.clone(),
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    let case = {
+        let mut storage = storage.write().await;
+        let case = storage.guild_mut(guild_id).add_case(action);
+        match (lift, case.duration) {
+            (Some(lift), Some(duration)) => {
+                match i64::try_from(duration)
+                    .ok()
+                    .and_then(|secs| case.time.checked_add(secs))
+                {
+                    Some(due) => {
+                        storage.schedule(due, lift);
+                    },
+                    None => eprintln!("Case {} ends too late to be lifted", case.id),
+                }
+            },
+            (Some(lift), None) => {
+                storage.cancel(&lift);
+            },
+            (None, _) => {},
+        }
+        storage.save()?;
+        case
+    };
+
+    action_reply(&case, None).send(ctx, msg).await?;
+    mirror(ctx, guild_id, &case).await;
+
+    Ok(())
+}
+
+/// Post a case in the mod-log channel of the guild, if it has one.
+async fn mirror(ctx: &Context, guild_id: GuildId, case: &ModAction) {
+    let channel_id = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(storage) => storage.read().await.guild(guild_id).and_then(|g| g.mod_log),
+        None => None,
+    };
+
+    if let Some(channel_id) = channel_id {
+        if let Err(why) = case_reply(case).send_to(ctx, ChannelId(channel_id)).await {
+            eprintln!("Failed to post case {} in the mod log: {}", case.id, why);
+        }
+    }
+}
+
+/// Give the penalty of an escalation rule to `user_id`.
+async fn escalate(
+    ctx: &Context, msg: &Message, guild_id: GuildId, user_id: UserId, rule: &EscalationRule,
+) -> CommandResult {
+    let duration = match &rule.duration {
+        Some(text) => match duration::parse(text) {
+            Some(duration) if duration <= MAX_DURATION => Some(duration),
+            _ => {
+                eprintln!(
+                    "Invalid or too long duration in the escalation rule `{}`",
+                    rule
+                );
+                return Ok(());
+            },
+        },
+        None => None,
+    };
+    let reason = Some(format!("Automatic: {} warnings", rule.warnings));
+
+    let (kind, result, lift) = match rule.penalty {
+        Penalty::Mute => {
+            let role_id = match mute_role(ctx, guild_id).await {
+                Ok(id) => id,
+                Err(reply) => return reply.send(ctx, msg).await,
+            };
+            let result = add_role(ctx, guild_id, user_id, role_id, reason.as_deref()).await;
+            let lift = unmute_task(guild_id, user_id, role_id);
+            (ModActionKind::Mute, result, Some(lift))
+        },
+        Penalty::Kick => {
+            let result = kick_member(ctx, guild_id, user_id, reason.as_deref()).await;
+            (ModActionKind::Kick, result, None)
+        },
+        Penalty::Ban => {
+            let result = ban_user(ctx, guild_id, user_id, 0, reason.as_deref()).await;
+            let lift = unban_task(guild_id, user_id);
+            (ModActionKind::Ban { delete_days: 0 }, result, Some(lift))
+        },
+    };
+
+    // A kick can't be temporary
+    let duration = duration.filter(|_| lift.is_some());
+    let action = new_action(kind, Some(user_id), msg, reason, duration);
+    finish(ctx, msg, guild_id, action, result, lift).await
+}
+
+/// Kick `user_id`, with the reason shown in the audit log of the guild.
+async fn kick_member(
+    ctx: &Context, guild_id: GuildId, user_id: UserId, reason: Option<&str>,
+) -> serenity::Result<()> {
+    match reason {
+        Some(reason) => guild_id.kick_with_reason(&ctx.http, user_id, reason).await,
+        None => guild_id.kick(&ctx.http, user_id).await,
+    }
+}
+
+/// Ban `user_id`, with the reason shown in the audit log of the guild.
+async fn ban_user(
+    ctx: &Context, guild_id: GuildId, user_id: UserId, delete_days: u8, reason: Option<&str>,
+) -> serenity::Result<()> {
+    match reason {
+        Some(reason) => {
+            guild_id
+                .ban_with_reason(&ctx.http, user_id, delete_days, reason)
+                .await
+        },
+        None => guild_id.ban(&ctx.http, user_id, delete_days).await,
+    }
+}
+
+/// Give `role_id` to `user_id`, with the reason shown in the audit log of the guild.
+async fn add_role(
+    ctx: &Context, guild_id: GuildId, user_id: UserId, role_id: RoleId, reason: Option<&str>,
+) -> serenity::Result<()> {
+    let route = RouteInfo::AddMemberRole {
+        guild_id: guild_id.0,
+        user_id:  user_id.0,
+        role_id:  role_id.0,
+    };
+    with_reason(ctx, route, reason).await
+}
+
+/// Send a request to Discord with `reason` shown in the audit log of the guild.
+async fn with_reason(
+    ctx: &Context, route: RouteInfo<'_>, reason: Option<&str>,
+) -> serenity::Result<()> {
+    let mut request = RequestBuilder::new(route);
+    if let Some(value) = reason.and_then(|r| HeaderValue::from_str(&audit_log_reason(r)).ok()) {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Audit-Log-Reason", value);
+        request.headers(Some(headers));
+    }
+
+    ctx.http.request(request.build()).await.map(|_| ())
+}
+
+/// `reason` percent-encoded, as Discord reads the audit log reason header.
+fn audit_log_reason(reason: &str) -> String {
+    reason
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            },
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn unban_task(guild_id: GuildId, user_id: UserId) -> TaskAction {
+    TaskAction::Unban {
+        guild: guild_id.0,
+        user:  user_id.0,
+    }
+}
+
+fn unmute_task(guild_id: GuildId, user_id: UserId, role_id: RoleId) -> TaskAction {
+    TaskAction::RemoveRole {
+        guild: guild_id.0,
+        user:  user_id.0,
+        role:  role_id.0,
+    }
+}
+
+fn new_action(
+    kind: ModActionKind, target: Option<UserId>, msg: &Message, reason: Option<String>,
+    duration: Option<Duration>,
+) -> ModAction {
+    ModAction {
+        id: 0,
+        kind,
+        target: target.map(|id| id.0),
+        moderator: msg.author.id.0,
+        reason,
+        time: OffsetDateTime::now_utc().unix_timestamp(),
+        duration: duration.map(|d| d.as_secs()),
+    }
+}
+
+/// The reply refusing to act on `target`, if it can't be acted on.
+fn target_error(target: Option<UserId>, author: UserId, bot: UserId) -> Option<Reply> {
+    let text = match target {
+        None => "Missing or invalid user. Mention them or use their id.",
+        Some(id) if id == author => "You can't do that to yourself.",
+        Some(id) if id == bot => "I can't do that to myself.",
+        Some(_) => return None,
+    };
+
+    Some(Reply::embed(" ", Color::RED, text))
+}
+
+/// The newest `count` messages to purge, from `(id, author)` pairs sorted newest first.
+///
+/// Only messages of `user` are taken if given, and never messages too old to bulk delete.
+fn purge_targets(
+    messages: &[(MessageId, UserId)], count: usize, user: Option<UserId>, now: i64,
+) -> Vec<MessageId> {
+    messages
+        .iter()
+        .filter(|&&(id, _)| now - message_time(id) < BULK_DELETE_MAX_AGE)
+        .filter(|&&(_, author)| user.map_or(true, |user| user == author))
+        .take(count)
+        .map(|&(id, _)| id)
+        .collect()
+}
+
+/// When a message was sent, in seconds since the unix epoch, read from its id.
+fn message_time(id: MessageId) -> i64 {
+    (((id.0 >> 22) + DISCORD_EPOCH) / 1000) as i64
+}
+
+/// Reply listing the pending `tasks`, soonest first.
+fn scheduled_reply(tasks: &[ScheduledTask], now: i64) -> Reply {
+    let mut tasks = tasks.iter().collect::<Vec<_>>();
+    tasks.sort_by_key(|t| t.due);
+
+    let lines = tasks
+        .iter()
+        .map(|t| {
+            let what = match t.action {
+                TaskAction::Unban { user, .. } => format!("unban <@{}>", user),
+                TaskAction::RemoveRole { user, role, .. } => {
+                    format!("remove <@&{}> from <@{}>", role, user)
+                },
+                TaskAction::AddRole { user, role, .. } => {
+                    format!("give <@&{}> to <@{}>", role, user)
+                },
+                TaskAction::Remind { user, .. } => format!("remind <@{}>", user),
+            };
+            let wait = Duration::from_secs((t.due - now).max(0) as u64);
+            format!("`#{}` in {}: {}", t.id, duration::format(wait), what)
+        })
+        .collect::<Vec<_>>();
+
+    let description = if lines.is_empty() {
+        "Nothing is scheduled.".to_string()
+    } else {
+        lines.join("\n")
+    };
+
+    Reply::embed("SCHEDULED", Color::BLUE, description)
+}
+
+/// What `action` does and to whom, like `("ban", "Banned", "<@50>")`.
+fn describe(action: &ModAction) -> (&'static str, String, String) {
+    let user = action
+        .target
+        .map(|id| format!("<@{}>", id))
+        .unwrap_or_default();
+
+    match &action.kind {
+        ModActionKind::Warn => ("warn", "Warned".to_string(), user),
+        ModActionKind::Kick => ("kick", "Kicked".to_string(), user),
+        ModActionKind::Ban { .. } => ("ban", "Banned".to_string(), user),
+        ModActionKind::Unban => ("unban", "Unbanned".to_string(), user),
+        ModActionKind::Mute => ("mute", "Muted".to_string(), user),
+        ModActionKind::Unmute => ("unmute", "Unmuted".to_string(), user),
+        ModActionKind::Purge { count, .. } => (
+            "delete",
+            format!("Deleted {}", count),
+            if user.is_empty() {
+                "messages".to_string()
+            } else {
+                format!("messages of {}", user)
+            },
+        ),
+    }
+}
+
+/// What `action` did, like `Banned <@50> for 7d`.
+fn summary(action: &ModAction) -> String {
+    let (_, done, subject) = describe(action);
+
+    let mut text = format!("{} {}", done, subject);
+    if let Some(secs) = action.duration {
+        text.push_str(&format!(
+            " for {}",
+            duration::format(Duration::from_secs(secs))
+        ));
+    }
+    text
+}
+
+/// Reply with the outcome of a moderation action, `error` being why it failed.
+fn action_reply(action: &ModAction, error: Option<&str>) -> Reply {
+    let title = action.kind.name().to_uppercase();
+
+    match error {
+        Some(why) => {
+            let (verb, _, subject) = describe(action);
+            Reply::embed(
+                title,
+                Color::RED,
+                format!("Failed to {} {}: {}", verb, subject, why),
+            )
+        },
+        None => {
+            let mut text = format!("{}.", summary(action));
+            if let Some(reason) = &action.reason {
+                text.push_str(&format!("\nReason: {}", reason));
+            }
+            text.push_str(&format!("\nCase #{}", action.id));
+
+            Reply::embed(title, Color::TEAL, text)
+        },
+    }
+}
+
+/// Reply showing every detail of a case.
+fn case_reply(case: &ModAction) -> Reply {
+    let mut reply = Reply::embed(
+        format!("CASE #{}: {}", case.id, case.kind.name().to_uppercase()),
+        Color::BLUE,
+        format!("{}.", summary(case)),
+    );
+
+    if let Some(embed) = &mut reply.embed {
+        if let Some(target) = case.target {
+            embed
+                .fields
+                .push(("User".to_string(), format!("<@{}>", target), true));
+        }
+        embed.fields.push((
+            "Moderator".to_string(),
+            format!("<@{}>", case.moderator),
+            true,
+        ));
+        if let ModActionKind::Purge { channel, .. } = case.kind {
+            embed
+                .fields
+                .push(("Channel".to_string(), format!("<#{}>", channel), true));
+        }
+        embed.fields.push((
+            "Reason".to_string(),
+            case.reason
+                .clone()
+                .unwrap_or_else(|| "No reason given".to_string()),
+            false,
+        ));
+        embed.fields.push((
+            "When".to_string(),
+            OffsetDateTime::from_unix_timestamp(case.time).format("%F %T UTC"),
+            false,
+        ));
+    }
+
+    reply
+}
+
+fn missing_case_reply(id: u64) -> Reply {
+    Reply::embed(" ", Color::RED, format!("There is no case #{}.", id))
+}
+
+/// Reply listing the warnings of `user_id`.
+fn warnings_reply(user_id: UserId, warnings: &[ModAction]) -> Reply {
+    let mut text = format!("<@{}> has {} warnings.", user_id, warnings.len());
+    for warning in warnings {
+        text.push_str(&format!(
+            "\n`#{}` {} by <@{}>: {}",
+            warning.id,
+            OffsetDateTime::from_unix_timestamp(warning.time).format("%F"),
+            warning.moderator,
+            warning.reason.as_deref().unwrap_or("no reason given")
+        ));
+    }
+
+    Reply::embed("WARNINGS", Color::BLUE, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serenity::model::permissions::Permissions;
+
+    use crate::{
+        config::Config,
+        testing::{message, MockDiscord},
+        types::ConfigContainer,
+    };
+
+    use super::*;
+
+    const GUILD: u64 = 30;
+    const CHANNEL: u64 = 20;
+    const MODERATOR_ROLE: u64 = 61;
+
+    /// A mock with a guild where user 10 is a moderator.
+    async fn mock() -> MockDiscord {
+        let mock = MockDiscord::start().await;
+        mock.add_guild(GUILD, CHANNEL, &[
+            (60, "Muted"),
+            (MODERATOR_ROLE, "Moderator"),
+        ])
+        .await;
+        let permissions =
+            Permissions::KICK_MEMBERS | Permissions::BAN_MEMBERS | Permissions::MANAGE_ROLES;
+        mock.grant(GUILD, MODERATOR_ROLE, permissions).await;
+        mock.add_member(GUILD, 10, &[MODERATOR_ROLE]).await;
+        mock
+    }
+
+    async fn actions(mock: &MockDiscord) -> Vec<ModAction> {
+        let data = mock.ctx.data.read().await;
+        let storage = data.get::<StorageContainer>().unwrap().read().await;
+        storage
+            .guild(GuildId(GUILD))
+            .map(|g| g.actions.clone())
+            .unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn kick_forwards_the_reason_and_records_the_action() {
+        let mock = mock().await;
+        let msg = message("!kick <@50> spam links", 10, Some(GUILD), CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        let kicks = mock
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "DELETE" && r.path.starts_with("/guilds/30/members/50"))
+            .count();
+        assert_eq!(kicks, 1);
+
+        let actions = actions(&mock).await;
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].kind, ModActionKind::Kick);
+        assert_eq!(actions[0].target, Some(50));
+        assert_eq!(actions[0].moderator, 10);
+        assert_eq!(actions[0].reason.as_deref(), Some("spam links"));
+    }
+
+    #[tokio::test]
+    async fn kick_needs_the_permission_and_a_member() {
+        let mock = mock().await;
+        mock.add_member(GUILD, 11, &[]).await;
+
+        let refused = mock
+            .send(&message("!kick <@50>", 11, Some(GUILD), CHANNEL))
+            .await;
+        assert!(refused
+            .unwrap_err()
+            .to_string()
+            .contains("LackingPermissions"));
+        let empty = mock.send(&message("!kick", 10, Some(GUILD), CHANNEL)).await;
+        assert!(empty
+            .unwrap_err()
+            .to_string()
+            .contains("NotEnoughArguments"));
+
+        assert!(mock.requests().is_empty());
+        assert!(actions(&mock).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn mute_gives_the_configured_role() {
+        let mock = mock().await;
+        let msg = message("!mute <@50> 1h", 10, Some(GUILD), CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        let mutes = mock
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "PUT" && r.path == "/guilds/30/members/50/roles/60")
+            .count();
+        assert_eq!(mutes, 1);
+        assert_eq!(actions(&mock).await[0].duration, Some(3600));
+        assert!(mock.sent_embeds()[0]["description"]
+            .as_str()
+            .unwrap()
+            .starts_with("Muted <@50> for 1h."));
+    }
+
+    #[tokio::test]
+    async fn bans_and_mutes_last_at_most_a_year() {
+        let mock = mock().await;
+
+        for content in &["!ban <@50> 366d", "!mute <@50> 99999999999w"] {
+            mock.send(&message(content, 10, Some(GUILD), CHANNEL))
+                .await
+                .unwrap();
+        }
+
+        let embeds = mock.sent_embeds();
+        assert_eq!(embeds.len(), 2);
+        assert!(embeds
+            .iter()
+            .all(|e| e["description"] == "Bans and mutes can last at most a year."));
+        assert!(actions(&mock).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn mute_and_unmute_forward_the_reason() {
+        let mock = mock().await;
+        let msg = message("!mute <@50> 1h spam é", 10, Some(GUILD), CHANNEL);
+        mock.send(&msg).await.unwrap();
+        let msg = message("!unmute <@50> appeal", 10, Some(GUILD), CHANNEL);
+        mock.send(&msg).await.unwrap();
+
+        let reasons = mock
+            .requests()
+            .into_iter()
+            .filter(|r| r.path == "/guilds/30/members/50/roles/60")
+            .map(|r| (r.method, r.reason))
+            .collect::<Vec<_>>();
+        assert_eq!(reasons, vec![
+            ("PUT".to_string(), Some("spam%20%C3%A9".to_string())),
+            ("DELETE".to_string(), Some("appeal".to_string())),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn members_ranked_as_high_are_refused() {
+        let mock = mock().await;
+        mock.add_member(GUILD, 50, &[MODERATOR_ROLE]).await;
+        let msg = message("!kick <@50>", 10, Some(GUILD), CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        assert!(!mock.requests().iter().any(|r| r.method == "DELETE"));
+        assert!(actions(&mock).await.is_empty());
+        assert_eq!(
+            mock.sent_embeds()[0]["description"],
+            "You can't do that to <@50>, their highest role is not below yours."
+        );
+    }
+
+    #[test]
+    fn only_the_owner_acts_on_higher_members() {
+        let (owner, author, target) = (UserId(2), UserId(10), UserId(50));
+
+        assert!(outranks(owner, (author, Some(3)), (target, Some(2))));
+        assert!(outranks(owner, (author, Some(3)), (target, None)));
+        assert!(!outranks(owner, (author, Some(3)), (target, Some(3))));
+        assert!(!outranks(owner, (author, Some(5)), (owner, Some(0))));
+        assert!(outranks(owner, (owner, Some(0)), (target, Some(3))));
+    }
+
+    #[tokio::test]
+    async fn temporary_ban_schedules_the_unban() {
+        let mock = mock().await;
+        let msg = message("!ban <@50> 7d 1 raid", 10, Some(GUILD), CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        let action = actions(&mock).await.remove(0);
+        assert_eq!(action.kind, ModActionKind::Ban { delete_days: 1 });
+        assert_eq!(action.reason.as_deref(), Some("raid"));
+
+        let data = mock.ctx.data.read().await;
+        let storage = data.get::<StorageContainer>().unwrap().read().await;
+        assert_eq!(storage.tasks.len(), 1);
+        assert_eq!(storage.tasks[0].due, action.time + 7 * 86_400);
+        assert_eq!(storage.tasks[0].action, TaskAction::Unban {
+            guild: GUILD,
+            user:  50,
+        });
+    }
+
+    #[tokio::test]
+    async fn unban_cancels_the_scheduled_unban() {
+        let mock = mock().await;
+        mock.send(&message("!ban <@50> 1d", 10, Some(GUILD), CHANNEL))
+            .await
+            .unwrap();
+
+        mock.send(&message("!unban <@50>", 10, Some(GUILD), CHANNEL))
+            .await
+            .unwrap();
+
+        let data = mock.ctx.data.read().await;
+        let storage = data.get::<StorageContainer>().unwrap().read().await;
+        assert!(storage.tasks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn mute_without_the_role_does_nothing() {
+        let mock = MockDiscord::start().await;
+        mock.add_guild(GUILD, CHANNEL, &[(MODERATOR_ROLE, "Moderator")])
+            .await;
+        mock.grant(GUILD, MODERATOR_ROLE, Permissions::MANAGE_ROLES)
+            .await;
+        mock.add_member(GUILD, 10, &[MODERATOR_ROLE]).await;
+        let msg = message("!mute <@50>", 10, Some(GUILD), CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        assert!(actions(&mock).await.is_empty());
+        assert_eq!(
+            mock.sent_embeds()[0]["description"],
+            "There is no `Muted` role in this guild."
+        );
+    }
+
+    #[tokio::test]
+    async fn warnings_escalate_to_the_configured_penalty() {
+        let mock = mock().await;
+        let mut config = Config::default();
+        config.moderation.escalation.push(EscalationRule {
+            warnings: 2,
+            penalty:  Penalty::Kick,
+            duration: None,
+        });
+        mock.insert::<ConfigContainer>(Arc::new(RwLock::new(config)))
+            .await;
+
+        for reason in &["spam", "more spam"] {
+            let content = format!("!warn <@50> {}", reason);
+            let msg = message(&content, 10, Some(GUILD), CHANNEL);
+            mock.send(&msg).await.unwrap();
+        }
+
+        let cases = actions(&mock)
+            .await
+            .into_iter()
+            .map(|a| (a.id, a.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(cases, vec![
+            (1, ModActionKind::Warn),
+            (2, ModActionKind::Warn),
+            (3, ModActionKind::Kick)
+        ]);
+        assert!(mock
+            .requests()
+            .iter()
+            .any(|r| r.method == "DELETE" && r.path == "/guilds/30/members/50"));
+    }
+
+    #[tokio::test]
+    async fn cases_are_posted_in_the_mod_log() {
+        let mock = mock().await;
+        {
+            let data = mock.ctx.data.read().await;
+            let mut storage = data.get::<StorageContainer>().unwrap().write().await;
+            storage.guild_mut(GuildId(GUILD)).mod_log = Some(99);
+        }
+        let msg = message("!kick <@50>", 10, Some(GUILD), CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        let posted = mock
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "POST" && r.path == "/channels/99/messages")
+            .count();
+        assert_eq!(posted, 1);
+        assert!(mock
+            .sent_embeds()
+            .iter()
+            .any(|e| e["title"] == "CASE #1: KICK"));
+    }
+
+    #[tokio::test]
+    async fn case_edit_changes_the_reason() {
+        let mock = mock().await;
+        let msg = message("!warn <@50> spam", 10, Some(GUILD), CHANNEL);
+        mock.send(&msg).await.unwrap();
+
+        let msg = message("!case edit 1 flooding", 10, Some(GUILD), CHANNEL);
+        mock.send(&msg).await.unwrap();
+
+        assert_eq!(actions(&mock).await[0].reason.as_deref(), Some("flooding"));
+    }
+
+    #[test]
+    fn targets_are_checked() {
+        let (author, bot) = (UserId(10), UserId(1));
+
+        assert!(target_error(None, author, bot).is_some());
+        assert!(target_error(Some(author), author, bot).is_some());
+        assert!(target_error(Some(bot), author, bot).is_some());
+        assert!(target_error(Some(UserId(50)), author, bot).is_none());
+    }
+
+    #[test]
+    fn purge_skips_other_users_and_old_messages() {
+        // A message id sent at `secs` seconds after the unix epoch
+        let id = |secs: u64| MessageId((secs * 1000 - DISCORD_EPOCH) << 22);
+        let now: i64 = 1_700_000_000;
+        let messages = vec![
+            (id(1_699_999_990), UserId(50)),
+            (id(1_699_999_980), UserId(51)),
+            (id(1_699_999_970), UserId(50)),
+            (id(1_600_000_000), UserId(50)),
+        ];
+
+        assert_eq!(purge_targets(&messages, 10, Some(UserId(50)), now), vec![
+            messages[0].0,
+            messages[2].0
+        ]);
+        assert_eq!(purge_targets(&messages, 2, None, now), vec![
+            messages[0].0,
+            messages[1].0
+        ]);
+    }
+
+    #[test]
+    fn scheduled_tasks_are_listed_soonest_first() {
+        let tasks = vec![
+            ScheduledTask {
+                id:     1,
+                due:    1_000 + 86_400,
+                action: TaskAction::Unban {
+                    guild: 30,
+                    user:  50,
+                },
+            },
+            ScheduledTask {
+                id:     2,
+                due:    1_000 + 600,
+                action: TaskAction::RemoveRole {
+                    guild: 30,
+                    user:  51,
+                    role:  60,
+                },
+            },
+        ];
+
+        let reply = scheduled_reply(&tasks, 1_000);
+
+        assert_eq!(
+            reply.embed.unwrap().description,
+            "`#2` in 10m: remove <@&60> from <@51>\n`#1` in 1d: unban <@50>"
+        );
+    }
+
+    #[test]
+    fn failed_actions_say_why() {
+        let action = ModAction {
+            id: 0,
+            kind: ModActionKind::Ban { delete_days: 0 },
+            target: Some(50),
+            moderator: 10,
+            reason: None,
+            time: 0,
+            duration: None,
+        };
+
+        let reply = action_reply(&action, Some("Missing Permissions"));
+
+        let embed = reply.embed.unwrap();
+        assert_eq!(embed.color, Color::RED);
+        assert_eq!(
+            embed.description,
+            "Failed to ban <@50>: Missing Permissions"
+        );
+    }
+}
@@ -0,0 +1,299 @@
+//! Bot modules.
+//!
+//! A [`Module`] bundles command groups with the data and event hooks they need. Modules
+//! are kept in a [`ModuleRegistry`]; the ones disabled in the configuration are left out
+//! when the bot is built.
+
+use std::{collections::HashSet, sync::Arc};
+
+use serenity::{
+    async_trait,
+    client::bridge::gateway::GatewayIntents,
+    framework::standard::CommandGroup,
+    model::{
+        channel::Message,
+        event::MessageUpdateEvent,
+        gateway::Ready,
+        guild::Member,
+        id::{ChannelId, GuildId, MessageId},
+        interactions::Interaction,
+        user::User,
+    },
+    prelude::*,
+};
+
+use crate::{
+    admin::AdminModule, autorole::AutoRoleModule, config::Config, eventlog::EventLogModule,
+    management::ManagementModule, meme::MemeModule, moderation::ModerationModule,
+    owner::OwnerModule, remind::RemindModule, tags::TagsModule, util::UtilModule,
+    welcome::WelcomeModule,
+};
+
+/// A feature of the bot.
+///
+/// Every method but [`Module::name`] has a default that does nothing.
+#[async_trait]
+pub trait Module: Send + Sync {
+    /// Unique name of the module, also its key in the `modules` table of the
+    /// configuration.
+    fn name(&self) -> &'static str;
+
+    /// Command groups of the module.
+    fn groups(&self) -> Vec<&'static CommandGroup> {
+        Vec::new()
+    }
+
+    /// Validate the settings of the module in the configuration.
+    ///
+    /// Called when the bot is built and before a configuration is reloaded. An error
+    /// refuses the configuration.
+    fn check_config(&self, _settings: &toml::value::Table) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Gateway intents the module needs, besides the non privileged ones.
+    ///
+    /// Privileged intents must also be enabled for the bot in the Discord developer
+    /// portal, and the module should say so in its documentation.
+    fn intents(&self) -> GatewayIntents {
+        GatewayIntents::empty()
+    }
+
+    /// Insert the data the module needs in the client data, before the client starts.
+    fn init(&self, _data: &mut TypeMap, _config: &Config) {}
+
+    /// Called when the bot connects or reconnects.
+    async fn ready(&self, _ctx: &Context, _ready: &Ready) {}
+
+    /// Called for every message, commands included.
+    async fn message(&self, _ctx: &Context, _msg: &Message) {}
+
+    /// Called when a message is edited. `old` is only known if it was cached.
+    async fn message_update(
+        &self, _ctx: &Context, _old: Option<&Message>, _new: Option<&Message>,
+        _event: &MessageUpdateEvent,
+    ) {
+    }
+
+    /// Called when a message is deleted.
+    async fn message_delete(
+        &self, _ctx: &Context, _channel_id: ChannelId, _message_id: MessageId,
+        _guild_id: Option<GuildId>,
+    ) {
+    }
+
+    /// Called when a member joins a guild.
+    async fn guild_member_addition(&self, _ctx: &Context, _guild_id: GuildId, _member: &Member) {}
+
+    /// Called when a member leaves a guild. `member` is only known if it was cached.
+    async fn guild_member_removal(
+        &self, _ctx: &Context, _guild_id: GuildId, _user: &User, _member: Option<&Member>,
+    ) {
+    }
+
+    /// Called when the roles or the nickname of a member change. `old` is only known if
+    /// it was cached.
+    async fn guild_member_update(&self, _ctx: &Context, _old: Option<&Member>, _new: &Member) {}
+
+    /// Called when a member uses a message component or an application command.
+    async fn interaction_create(&self, _ctx: &Context, _interaction: &Interaction) {}
+
+    /// Called when a message starts with a prefix but names no command.
+    async fn unknown_command(&self, _ctx: &Context, _msg: &Message, _name: &str) {}
+}
+
+/// An ordered set of modules with unique names.
+#[derive(Clone, Default)]
+pub struct ModuleRegistry {
+    modules: Vec<Arc<dyn Module>>,
+}
+
+impl ModuleRegistry {
+    /// An empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry with the modules that come with OxiBot.
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(UtilModule);
+        registry.register(MemeModule);
+        registry.register(ManagementModule);
+        registry.register(ModerationModule);
+        registry.register(EventLogModule);
+        registry.register(WelcomeModule);
+        registry.register(AutoRoleModule);
+        registry.register(TagsModule);
+        registry.register(RemindModule);
+        registry.register(OwnerModule);
+        registry.register(AdminModule);
+        registry
+    }
+
+    /// Add a module, replacing the module with the same name if there is one.
+    pub fn register(&mut self, module: impl Module + 'static) {
+        let module: Arc<dyn Module> = Arc::new(module);
+        match self.modules.iter().position(|m| m.name() == module.name()) {
+            Some(i) => self.modules[i] = module,
+            None => self.modules.push(module),
+        }
+    }
+
+    /// Find a module by its name.
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn Module>> {
+        self.modules.iter().find(|m| m.name() == name)
+    }
+
+    /// The modules, in registration order.
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<dyn Module>> {
+        self.modules.iter()
+    }
+
+    /// Names of the modules, in registration order.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.modules.iter().map(|m| m.name()).collect()
+    }
+
+    /// A registry with only the modules enabled in `config`.
+    pub fn enabled(&self, config: &Config) -> Self {
+        Self {
+            modules: self
+                .modules
+                .iter()
+                .filter(|m| config.module_enabled(m.name()))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Command groups of every module, without duplicates.
+    pub fn groups(&self) -> Vec<&'static CommandGroup> {
+        let mut seen = HashSet::new();
+        self.modules
+            .iter()
+            .flat_map(|m| m.groups())
+            .filter(|g| seen.insert(g.name))
+            .collect()
+    }
+
+    /// Gateway intents of every module, besides the non privileged ones.
+    pub fn intents(&self) -> GatewayIntents {
+        self.modules
+            .iter()
+            .fold(GatewayIntents::non_privileged(), |intents, m| {
+                intents | m.intents()
+            })
+    }
+
+    /// Check the settings of every module in `config`.
+    ///
+    /// Returns one line per module with invalid settings.
+    pub fn check_config(&self, config: &Config) -> Result<(), Vec<String>> {
+        let empty = toml::value::Table::new();
+        let errors = self
+            .modules
+            .iter()
+            .filter_map(|m| {
+                let settings = config.module_settings(m.name()).unwrap_or(&empty);
+                m.check_config(settings)
+                    .err()
+                    .map(|why| format!("module `{}`: {}", m.name(), why))
+            })
+            .collect::<Vec<_>>();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Strict;
+
+    impl Module for Strict {
+        fn name(&self) -> &'static str {
+            "strict"
+        }
+
+        fn check_config(&self, settings: &toml::value::Table) -> Result<(), String> {
+            match settings.get("level") {
+                Some(toml::Value::Integer(_)) => Ok(()),
+                _ => Err("`level` must be an integer".to_string()),
+            }
+        }
+    }
+
+    #[test]
+    fn disabled_modules_are_left_out() {
+        let config: Config = toml::from_str(
+            r#"
+            [modules.meme]
+            enabled = false
+            "#,
+        )
+        .unwrap();
+
+        let names = ModuleRegistry::builtin().enabled(&config).names();
+
+        assert_eq!(names, vec![
+            "util",
+            "management",
+            "moderation",
+            "eventlog",
+            "welcome",
+            "autorole",
+            "tags",
+            "remind",
+            "owner",
+            "admin"
+        ]);
+    }
+
+    #[test]
+    fn registering_a_name_again_replaces_the_module() {
+        let mut registry = ModuleRegistry::builtin();
+        registry.register(UtilModule);
+
+        assert_eq!(registry.names(), vec![
+            "util",
+            "meme",
+            "management",
+            "moderation",
+            "eventlog",
+            "welcome",
+            "autorole",
+            "tags",
+            "remind",
+            "owner",
+            "admin"
+        ]);
+    }
+
+    #[test]
+    fn module_settings_are_checked() {
+        let mut registry = ModuleRegistry::new();
+        registry.register(Strict);
+
+        assert_eq!(
+            registry.check_config(&Config::default()),
+            Err(vec![
+                "module `strict`: `level` must be an integer".to_string()
+            ])
+        );
+
+        let config: Config = toml::from_str(
+            r#"
+            [modules.strict]
+            level = 3
+            "#,
+        )
+        .unwrap();
+        assert_eq!(registry.check_config(&config), Ok(()));
+    }
+}
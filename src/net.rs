@@ -0,0 +1,220 @@
+use std::{
+    error::Error,
+    fmt, io,
+    net::{AddrParseError, IpAddr},
+    time::Duration,
+};
+
+/// An address of a local network interface.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterfaceAddr {
+    pub name: String,
+    pub addr: IpAddr,
+}
+
+/// Errors that can happen while asking an echo service for the public ip.
+#[derive(Debug)]
+pub enum PublicIpError {
+    Timeout,
+    Request(reqwest::Error),
+    Status(reqwest::StatusCode),
+    Invalid(String, AddrParseError),
+}
+
+/// List the addresses of every local network interface.
+#[cfg(unix)]
+pub fn local_addresses() -> io::Result<Vec<InterfaceAddr>> {
+    use std::{
+        ffi::CStr,
+        net::{Ipv4Addr, Ipv6Addr},
+        ptr,
+    };
+
+    let mut ifap: *mut libc::ifaddrs = ptr::null_mut();
+    // SAFETY: safe because `getifaddrs` only writes the list head into `ifap`
+    if unsafe { libc::getifaddrs(&mut ifap) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut addrs = Vec::new();
+    let mut cur = ifap;
+    while !cur.is_null() {
+        // SAFETY: safe because `cur` is a node of the list returned by `getifaddrs`, which is
+        // only freed after the loop
+        let ifa = unsafe { &*cur };
+        cur = ifa.ifa_next;
+
+        if ifa.ifa_addr.is_null() {
+            continue;
+        }
+
+        // SAFETY: safe because `ifa_addr` is not null and the cast matches the family
+        let addr = unsafe {
+            match i32::from((*ifa.ifa_addr).sa_family) {
+                libc::AF_INET => {
+                    let sa = &*(ifa.ifa_addr as *const libc::sockaddr_in);
+                    IpAddr::V4(Ipv4Addr::from(u32::from_be(sa.sin_addr.s_addr)))
+                },
+                libc::AF_INET6 => {
+                    let sa = &*(ifa.ifa_addr as *const libc::sockaddr_in6);
+                    IpAddr::V6(Ipv6Addr::from(sa.sin6_addr.s6_addr))
+                },
+                _ => continue,
+            }
+        };
+
+        // SAFETY: safe because `ifa_name` is a valid nul-terminated string
+        let name = unsafe { CStr::from_ptr(ifa.ifa_name) }
+            .to_string_lossy()
+            .into_owned();
+
+        addrs.push(InterfaceAddr { name, addr });
+    }
+
+    // SAFETY: safe because `ifap` came from `getifaddrs` and is not used anymore
+    unsafe { libc::freeifaddrs(ifap) };
+
+    Ok(addrs)
+}
+
+/// List the addresses of every local network interface.
+#[cfg(not(unix))]
+pub fn local_addresses() -> io::Result<Vec<InterfaceAddr>> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "listing local interfaces is not supported on this platform",
+    ))
+}
+
+/// Ask the echo service at `url` for the public ip.
+///
+/// The service must answer with the bare address as the body, like `https://api.ipify.org`.
+pub async fn public_ip(url: &str, timeout: Duration) -> Result<IpAddr, PublicIpError> {
+    let client = reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(PublicIpError::Request)?;
+
+    let response = client.get(url).send().await?;
+    if !response.status().is_success() {
+        return Err(PublicIpError::Status(response.status()));
+    }
+
+    let body = response.text().await?;
+    let body = body.trim();
+
+    body.parse()
+        .map_err(|why| PublicIpError::Invalid(body.to_string(), why))
+}
+
+impl From<reqwest::Error> for PublicIpError {
+    fn from(why: reqwest::Error) -> Self {
+        if why.is_timeout() {
+            PublicIpError::Timeout
+        } else {
+            PublicIpError::Request(why)
+        }
+    }
+}
+
+impl fmt::Display for PublicIpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PublicIpError::Timeout => write!(f, "the request timed out"),
+            PublicIpError::Request(why) => write!(f, "the request failed: {}", why),
+            PublicIpError::Status(status) => write!(f, "the service answered {}", status),
+            PublicIpError::Invalid(body, why) => {
+                write!(
+                    f,
+                    "the service answered `{}`, which is not an ip: {}",
+                    body, why
+                )
+            },
+        }
+    }
+}
+
+impl Error for PublicIpError {}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Serve one request on a local port with `response`, or never answer if it is
+    /// `None`.
+    async fn echo_service(response: Option<String>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut line = String::new();
+            while stream.read_line(&mut line).await.unwrap_or(0) > 0 && line != "\r\n" {
+                line.clear();
+            }
+
+            match response {
+                Some(response) => {
+                    let _ = stream.get_mut().write_all(response.as_bytes()).await;
+                },
+                None => tokio::time::sleep(Duration::from_secs(10)).await,
+            }
+        });
+
+        format!("http://{}/", addr)
+    }
+
+    fn http_response(status: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+    }
+
+    #[tokio::test]
+    async fn public_ip_reads_the_body() {
+        let url = echo_service(Some(http_response("200 OK", "203.0.113.7\n"))).await;
+
+        let ip = public_ip(&url, Duration::from_secs(5)).await.unwrap();
+
+        assert_eq!(ip, "203.0.113.7".parse::<IpAddr>().unwrap());
+    }
+
+    #[tokio::test]
+    async fn public_ip_refuses_error_statuses() {
+        let url = echo_service(Some(http_response("503 Service Unavailable", ""))).await;
+
+        match public_ip(&url, Duration::from_secs(5)).await {
+            Err(PublicIpError::Status(status)) => assert_eq!(status.as_u16(), 503),
+            other => panic!("Expected a status error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn public_ip_refuses_bodies_that_are_not_ips() {
+        let url = echo_service(Some(http_response("200 OK", "<html>"))).await;
+
+        match public_ip(&url, Duration::from_secs(5)).await {
+            Err(PublicIpError::Invalid(body, _)) => assert_eq!(body, "<html>"),
+            other => panic!("Expected an invalid body error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn public_ip_times_out() {
+        let url = echo_service(None).await;
+
+        match public_ip(&url, Duration::from_millis(200)).await {
+            Err(PublicIpError::Timeout) => {},
+            other => panic!("Expected a timeout, got {:?}", other),
+        }
+    }
+}
@@ -1,17 +1,95 @@
-use std::process::Command;
+use std::{
+    collections::HashSet,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
 
-use crate::types::ShardManagerContainer;
+use crate::{
+    config::{Config, ExecConfig, IpConfig},
+    module::Module,
+    net,
+    prompt::confirm,
+    types::{
+        ConfigContainer, Modules, PrimaryOwners, RestartFlag, ShardManagerContainer,
+        StorageContainer,
+    },
+};
 
 use serenity::{
-    framework::standard::{macros::command, CommandResult},
-    model::channel::Message,
+    framework::standard::{
+        macros::{check, command, group},
+        Args, CommandGroup, CommandOptions, CommandResult, Reason,
+    },
+    model::{channel::Message, id::UserId},
     prelude::*,
     utils::Colour as Color,
 };
 
+use time::OffsetDateTime;
+use tokio::{process::Command, task, time::timeout};
+
+/// Maximum number of characters in a Discord message.
+const MESSAGE_LIMIT: usize = 2000;
+
+#[group]
+#[commands(exec, ip, owners, quit, reload, restart)]
+struct Owner;
+
+/// Commands restricted to the owners of the bot.
+pub struct OwnerModule;
+
+impl Module for OwnerModule {
+    fn name(&self) -> &'static str {
+        "owner"
+    }
+
+    fn groups(&self) -> Vec<&'static CommandGroup> {
+        vec![&OWNER_GROUP]
+    }
+}
+
+// Only lets owners and co-owners run the command.
+#[check]
+#[name = "Owner"]
+async fn owner_check(
+    ctx: &Context, msg: &Message, _: &mut Args, _: &CommandOptions,
+) -> Result<(), Reason> {
+    if is_owner(ctx, msg.author.id).await {
+        Ok(())
+    } else {
+        Err(Reason::Log(format!(
+            "User '{}' is not an owner",
+            msg.author.name
+        )))
+    }
+}
+
+/// Check if the user is an application owner, a team member or a co-owner.
+pub async fn is_owner(ctx: &Context, user_id: UserId) -> bool {
+    all_owners(ctx).await.contains(&user_id)
+}
+
+/// Every user allowed to run the owner commands.
+pub async fn all_owners(ctx: &Context) -> HashSet<UserId> {
+    let data = ctx.data.read().await;
+
+    let mut owners = data.get::<PrimaryOwners>().cloned().unwrap_or_default();
+    if let Some(config) = data.get::<ConfigContainer>() {
+        owners.extend(config.read().await.owners.iter().map(|&id| UserId(id)));
+    }
+    if let Some(storage) = data.get::<StorageContainer>() {
+        owners.extend(storage.read().await.co_owners.iter().map(|&id| UserId(id)));
+    }
+
+    owners
+}
+
 /// Shutdown the bot.
 #[command]
-#[owners_only]
+#[checks(Owner)]
 #[only_in(dm)]
 async fn quit(ctx: &Context, msg: &Message) -> CommandResult {
     let data = ctx.data.read().await;
@@ -31,23 +109,598 @@ async fn quit(ctx: &Context, msg: &Message) -> CommandResult {
     Ok(())
 }
 
-/// Get the global ip of the bot
+/// Restart the bot.
+///
+/// Shuts down every shard and then executes the bot binary again.
 #[command]
-#[owners_only]
+#[checks(Owner)]
 #[only_in(dm)]
-async fn ip(ctx: &Context, msg: &Message) -> CommandResult {
-    let ip = Command::new("curl").arg("ifconfig.me").output();
-    let mut str = String::new();
-    match ip {
-        Ok(out) => str.push_str(&out.stdout.iter().map(|&c| c as char).collect::<String>()),
-        Err(why) => println!("Error calling ip: {:?}", why),
+async fn restart(ctx: &Context, msg: &Message) -> CommandResult {
+    if !confirm(ctx, msg, "Restart the bot?").await? {
+        return Ok(());
+    }
+
+    let data = ctx.data.read().await;
+
+    let manager = match data.get::<ShardManagerContainer>() {
+        Some(m) => m,
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the shard manager")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    if let Some(flag) = data.get::<RestartFlag>() {
+        flag.store(true, Ordering::SeqCst);
+    }
+
+    let _ = msg.reply(&ctx, "Restarting!").await?;
+
+    manager.lock().await.shutdown_all().await;
+
+    Ok(())
+}
+
+/// Reload the configuration without disconnecting.
+///
+/// Re-reads the prefixes, the role catalog and the fortune files and reports what
+/// changed.
+#[command]
+#[checks(Owner)]
+#[only_in(dm)]
+async fn reload(ctx: &Context, msg: &Message) -> CommandResult {
+    let new = match Config::load() {
+        Ok(c) => c,
+        Err(why) => {
+            msg.channel_id
+                .send_message(&ctx.http, |m| {
+                    m.embed(|e| {
+                        e.title("RELOAD")
+                            .color(Color::RED)
+                            .description(format!("Failed to load the configuration: {}", why))
+                    })
+                })
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    let modules = ctx.data.read().await.get::<Modules>().cloned();
+    if let Some(Err(errors)) = modules.map(|m| m.check_config(&new)) {
+        msg.channel_id
+            .send_message(&ctx.http, |m| {
+                m.embed(|e| {
+                    e.title("RELOAD")
+                        .color(Color::RED)
+                        .description(format!("Invalid configuration:\n{}", errors.join("\n")))
+                })
+            })
+            .await?;
+
+        return Ok(());
+    }
+
+    let config = {
+        let data = ctx.data.read().await;
+        match data.get::<ConfigContainer>() {
+            Some(c) => c.clone(),
+            None => {
+                let _ = msg
+                    .reply(&ctx, "There was a problem getting the configuration")
+                    .await?;
+
+                return Ok(());
+            },
+        }
+    };
+
+    let changes = config.read().await.diff(&new);
+    if changes.is_empty() {
+        let _ = msg.reply(&ctx, "Nothing changed.").await?;
+        return Ok(());
+    }
+
+    let report = changes
+        .iter()
+        .map(|c| format!("- {}", c))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    if !confirm(ctx, msg, &format!("Apply these changes?\n{}", report)).await? {
+        return Ok(());
+    }
+
+    *config.write().await = new;
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("RELOAD")
+                    .color(Color::TEAL)
+                    .description(format!("Configuration reloaded.\n\n{}", report))
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Get the ip addresses of the bot.
+///
+/// Lists the addresses of the local network interfaces and asks the configured echo
+/// services for the public addresses. Pass `local` or `public` to only get one of them.
+#[command]
+#[checks(Owner)]
+#[only_in(dm)]
+#[max_args(1)]
+#[usage = "ip [local | public]"]
+async fn ip(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (show_local, show_public) = match args.single::<String>().unwrap_or_default().as_str() {
+        "" => (true, true),
+        "local" => (true, false),
+        "public" => (false, true),
+        _ => {
+            let _ = msg.reply(&ctx, "Usage: `ip [local | public]`").await?;
+            return Ok(());
+        },
+    };
+
+    let local = if show_local {
+        let text = match net::local_addresses() {
+            Ok(addrs) if addrs.is_empty() => "No addresses found".to_string(),
+            Ok(addrs) => addrs
+                .iter()
+                .map(|a| format!("`{}`: {}", a.name, a.addr))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Err(why) => format!("Failed to list the interfaces: {}", why),
+        };
+        Some(text)
+    } else {
+        None
+    };
+
+    let public = if show_public {
+        let ip_config = match ctx.data.read().await.get::<ConfigContainer>() {
+            Some(config) => config.read().await.ip.clone(),
+            None => IpConfig::default(),
+        };
+        let timeout = Duration::from_secs(ip_config.timeout_secs);
+
+        let mut lines = Vec::new();
+        for url in &ip_config.echo_urls {
+            match net::public_ip(url, timeout).await {
+                Ok(addr) => lines.push(format!("`{}`: {}", url, addr)),
+                Err(why) => {
+                    eprintln!("Error asking {} for the public ip: {}", url, why);
+                    lines.push(format!("`{}`: {}", url, why));
+                },
+            }
+        }
+
+        if lines.is_empty() {
+            lines.push("No echo services configured".to_string());
+        }
+        Some(lines.join("\n"))
+    } else {
+        None
     };
 
     msg.channel_id
         .send_message(&ctx.http, |m| {
-            m.embed(|e| e.title("").color(Color::RED).description(&str))
+            m.embed(|e| {
+                e.title("IP").color(Color::RED);
+                if let Some(local) = local {
+                    e.field("Local interfaces", local, false);
+                }
+                if let Some(public) = public {
+                    e.field("Public", public, false);
+                }
+                e
+            })
         })
         .await?;
 
     Ok(())
 }
+
+/// Run an allowed program on the machine of the bot.
+///
+/// Only the programs in the `exec.allowed` configuration can be run. They are run
+/// directly, never through a shell, and killed if they exceed the configured timeout.
+/// Every invocation is written to the audit log, and the program is not run if that
+/// fails.
+#[command]
+#[checks(Owner)]
+#[only_in(dm)]
+#[min_args(1)]
+#[usage = "exec <PROGRAM> [ARGS ...]"]
+async fn exec(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let exec_config = match ctx.data.read().await.get::<ConfigContainer>() {
+        Some(config) => config.read().await.exec.clone(),
+        None => ExecConfig::default(),
+    };
+
+    let program = args.single_quoted::<String>().unwrap_or_default();
+    let mut program_args = Vec::new();
+    while let Ok(arg) = args.single_quoted::<String>() {
+        program_args.push(arg);
+    }
+    let command_line = format!("{} {}", program, program_args.join(" "))
+        .trim_end()
+        .to_string();
+
+    if !exec_config.allowed.contains(&program) {
+        if let Err(why) = audit(&exec_config.audit_log, msg, &command_line, "refused").await {
+            eprintln!("Failed to write the exec audit log: {}", why);
+        }
+        let _ = msg
+            .reply(&ctx, format!("`{}` is not in the exec allowlist", program))
+            .await?;
+        return Ok(());
+    }
+
+    if let Err(why) = audit(&exec_config.audit_log, msg, &command_line, "started").await {
+        eprintln!("Failed to write the exec audit log: {}", why);
+        let _ = msg
+            .reply(
+                &ctx,
+                format!("Refusing to run, the audit log failed: {}", why),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let start = Instant::now();
+    let run = Command::new(&program)
+        .args(&program_args)
+        .kill_on_drop(true)
+        .output();
+
+    let (status, output) = match timeout(Duration::from_secs(exec_config.timeout_secs), run).await {
+        Ok(Ok(out)) => {
+            let mut output = String::from_utf8_lossy(&out.stdout).into_owned();
+            output.push_str(&String::from_utf8_lossy(&out.stderr));
+            (format!("exited with {}", out.status), output)
+        },
+        Ok(Err(why)) => (format!("failed to start: {}", why), String::new()),
+        Err(_) => (
+            format!("killed after {} seconds", exec_config.timeout_secs),
+            String::new(),
+        ),
+    };
+
+    if let Err(why) = audit(&exec_config.audit_log, msg, &command_line, &status).await {
+        eprintln!("Failed to write the exec audit log: {}", why);
+    }
+
+    let (pages, truncated) = code_block_pages(&output, exec_config.max_pages);
+    let mut details = format!("{} in {:.2}s", status, start.elapsed().as_secs_f64());
+    if pages.is_empty() {
+        details.push_str("\nNo output");
+    }
+    if truncated {
+        details.push_str(&format!(
+            "\nOutput truncated to {} messages",
+            exec_config.max_pages
+        ));
+    }
+    let header = exec_header(&command_line, &details);
+
+    msg.channel_id.say(&ctx.http, header).await?;
+    for page in pages {
+        msg.channel_id.say(&ctx.http, page).await?;
+    }
+
+    Ok(())
+}
+
+/// Show the owners and co-owners of the bot.
+///
+/// Co-owners can be added and removed by the application owners with `owners add` and
+/// `owners rm`. Co-owners set in the configuration file can only be changed there.
+#[command]
+#[checks(Owner)]
+#[only_in(dm)]
+#[sub_commands(add, rm)]
+#[usage = "owners [<add | rm> <@USER>]"]
+async fn owners(ctx: &Context, msg: &Message) -> CommandResult {
+    let (primary, configured, stored) = {
+        let data = ctx.data.read().await;
+        let primary = data.get::<PrimaryOwners>().cloned().unwrap_or_default();
+        let configured = match data.get::<ConfigContainer>() {
+            Some(config) => config.read().await.owners.clone(),
+            None => Vec::new(),
+        };
+        let stored = match data.get::<StorageContainer>() {
+            Some(storage) => storage.read().await.co_owners.iter().copied().collect(),
+            None => Vec::new(),
+        };
+        (
+            primary.into_iter().map(|id| id.0).collect::<Vec<_>>(),
+            configured,
+            stored,
+        )
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("OWNERS")
+                    .color(Color::BLUE)
+                    .field("Application owners", mention_list(&primary), false)
+                    .field(
+                        "Co-owners (configuration)",
+                        mention_list(&configured),
+                        false,
+                    )
+                    .field("Co-owners (`owners add`)", mention_list(&stored), false)
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Add a co-owner.
+///
+/// Only the application owners can do it.
+#[command]
+#[checks(Owner)]
+#[only_in(dm)]
+#[num_args(1)]
+#[usage = "owners add <@USER>"]
+async fn add(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    edit_co_owners(ctx, msg, args, true).await
+}
+
+/// Remove a co-owner.
+///
+/// Only the application owners can do it.
+#[command]
+#[checks(Owner)]
+#[only_in(dm)]
+#[num_args(1)]
+#[aliases(remove)]
+#[usage = "owners rm <@USER>"]
+async fn rm(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    edit_co_owners(ctx, msg, args, false).await
+}
+
+async fn edit_co_owners(ctx: &Context, msg: &Message, mut args: Args, add: bool) -> CommandResult {
+    let (is_primary, storage) = {
+        let data = ctx.data.read().await;
+        let is_primary = match data.get::<PrimaryOwners>() {
+            Some(owners) => owners.contains(&msg.author.id),
+            None => false,
+        };
+        (is_primary, data.get::<StorageContainer>().cloned())
+    };
+
+    if !is_primary {
+        let _ = msg
+            .reply(&ctx, "Only the application owners can edit the co-owners")
+            .await?;
+        return Ok(());
+    }
+
+    let user_id = match args.single::<UserId>() {
+        Ok(id) => id,
+        Err(_) => {
+            let _ = msg.reply(&ctx, "Invalid user").await?;
+            return Ok(());
+        },
+    };
+
+    let storage = match storage {
+        Some(s) => s,
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
+            return Ok(());
+        },
+    };
+
+    let changed = {
+        let mut storage = storage.write().await;
+        let changed = if add {
+            storage.co_owners.insert(user_id.0)
+        } else {
+            storage.co_owners.remove(&user_id.0)
+        };
+        if changed {
+            storage.save()?;
+        }
+        changed
+    };
+
+    let text = match (add, changed) {
+        (true, true) => format!("<@{}> is now a co-owner.", user_id),
+        (true, false) => format!("<@{}> already is a co-owner.", user_id),
+        (false, true) => format!("<@{}> is no longer a co-owner.", user_id),
+        (false, false) => format!("<@{}> is not a co-owner.", user_id),
+    };
+    let _ = msg.reply(&ctx, text).await?;
+
+    Ok(())
+}
+
+fn mention_list(ids: &[u64]) -> String {
+    if ids.is_empty() {
+        "None".to_string()
+    } else {
+        ids.iter()
+            .map(|id| format!("<@{}>", id))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Append an `exec` invocation to the audit log.
+async fn audit(path: &Path, msg: &Message, command_line: &str, outcome: &str) -> io::Result<()> {
+    let path = path.to_path_buf();
+    let line = format!(
+        "{} {} ({}) `{}`: {}",
+        OffsetDateTime::now_utc().format("%F %T"),
+        msg.author.id,
+        msg.author.tag(),
+        command_line,
+        outcome
+    );
+
+    // Writing to the file blocks, so keep it off the runtime threads
+    task::spawn_blocking(move || {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", line)
+    })
+    .await
+    .map_err(|why| io::Error::new(io::ErrorKind::Other, why))?
+}
+
+/// The first message of `exec`, with the command line cut to fit in a message.
+fn exec_header(command_line: &str, details: &str) -> String {
+    // The command line is in inline code, followed by a space and the details
+    let limit = MESSAGE_LIMIT.saturating_sub(details.chars().count() + 3);
+    let command_line = if command_line.chars().count() > limit {
+        let mut cut = command_line
+            .chars()
+            .take(limit.saturating_sub(1))
+            .collect::<String>();
+        cut.push('…');
+        cut
+    } else {
+        command_line.to_string()
+    };
+
+    format!("`{}` {}", command_line, details)
+}
+
+/// Split `text` into code blocks that fit in a message, keeping at most `max_pages`.
+///
+/// Also returns whether pages were dropped.
+fn code_block_pages(text: &str, max_pages: usize) -> (Vec<String>, bool) {
+    // Leave room for the code block fences
+    let limit = MESSAGE_LIMIT - "```\n\n```".len();
+    // Break every run of backticks so the text can't close the code block early
+    let text = text.trim_end().replace('`', "`\u{200b}");
+
+    let mut pages = Vec::new();
+    let mut page = String::new();
+    let mut len = 0;
+    for c in text.chars() {
+        if len == limit {
+            pages.push(page);
+            page = String::new();
+            len = 0;
+        }
+        page.push(c);
+        len += 1;
+    }
+    if !page.is_empty() {
+        pages.push(page);
+    }
+
+    let truncated = pages.len() > max_pages;
+    pages.truncate(max_pages);
+
+    let pages = pages
+        .into_iter()
+        .map(|p| format!("```\n{}\n```", p))
+        .collect();
+
+    (pages, truncated)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{message, MockDiscord};
+
+    use super::*;
+
+    const OWNER: u64 = 10;
+
+    async fn mock() -> MockDiscord {
+        let mock = MockDiscord::start().await;
+        let mut owners = HashSet::new();
+        owners.insert(UserId(OWNER));
+        mock.insert::<PrimaryOwners>(owners).await;
+        mock
+    }
+
+    #[tokio::test]
+    async fn owner_commands_refuse_other_users() {
+        let mock = mock().await;
+        let msg = message("!quit", 11, None, 20);
+
+        assert!(mock.send(&msg).await.is_err());
+        assert!(mock.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn exec_is_only_run_in_direct_messages() {
+        let mock = mock().await;
+        let msg = message("!exec ls", OWNER, Some(30), 20);
+
+        let refused = mock.send(&msg).await;
+
+        assert!(refused.unwrap_err().to_string().contains("OnlyForDM"));
+        assert!(mock.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn owners_lists_the_owners() {
+        let mock = mock().await;
+        let msg = message("!owners", OWNER, None, 20);
+
+        mock.send(&msg).await.unwrap();
+
+        let embeds = mock.sent_embeds();
+        assert_eq!(embeds[0]["title"], "OWNERS");
+        assert_eq!(embeds[0]["fields"][0]["value"], format!("<@{}>", OWNER));
+    }
+
+    #[test]
+    fn long_command_lines_are_cut_in_the_header() {
+        let command_line = format!("echo {}", "a".repeat(MESSAGE_LIMIT));
+
+        let header = exec_header(&command_line, "exited with exit status: 0 in 0.01s");
+
+        assert_eq!(header.chars().count(), MESSAGE_LIMIT);
+        assert!(header.starts_with("`echo aaa"));
+        assert!(header.ends_with("a…` exited with exit status: 0 in 0.01s"));
+        assert_eq!(exec_header("ls", "exited"), "`ls` exited");
+    }
+
+    #[test]
+    fn code_block_pages_split_and_truncate() {
+        let text = "a".repeat(MESSAGE_LIMIT * 3);
+
+        let (pages, truncated) = code_block_pages(&text, 2);
+
+        assert!(truncated);
+        assert_eq!(pages.len(), 2);
+        assert!(pages.iter().all(|p| p.chars().count() <= MESSAGE_LIMIT));
+        assert!(pages[0].starts_with("```\n") && pages[0].ends_with("\n```"));
+    }
+
+    #[test]
+    fn code_block_pages_break_every_fence() {
+        for text in &["```", "``````", "a````b", "`` `` ``"] {
+            let (pages, _) = code_block_pages(text, 1);
+
+            let inner = &pages[0]["```\n".len()..pages[0].len() - "\n```".len()];
+            assert!(
+                !inner.contains("```"),
+                "{:?} kept a fence: {:?}",
+                text,
+                inner
+            );
+            assert_eq!(inner.replace('\u{200b}', ""), *text);
+        }
+    }
+}
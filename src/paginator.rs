@@ -0,0 +1,253 @@
+//! Long replies split in pages.
+//!
+//! [`Pages::send`] sends the first page and, if there are more, adds
+//! [`REACTION_PREVIOUS`] and [`REACTION_NEXT`] that the caller can use to flip them until
+//! [`PAGE_TIMEOUT`].
+
+use std::time::Duration;
+
+use serenity::{
+    collector::ReactionAction,
+    framework::standard::CommandResult,
+    model::{channel::Message, id::UserId},
+    prelude::*,
+    utils::Colour as Color,
+};
+
+use crate::reply::Embed;
+
+/// How long the pages can be flipped after the last reaction.
+pub const PAGE_TIMEOUT: Duration = Duration::from_secs(120);
+/// Reaction that shows the previous page.
+pub const REACTION_PREVIOUS: char = '◀';
+/// Reaction that shows the next page.
+pub const REACTION_NEXT: char = '▶';
+
+/// Maximum number of characters of a page, well below the embed description limit.
+const PAGE_LENGTH: usize = 2000;
+
+/// An embed whose description is split in pages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pages {
+    pub title: String,
+    pub color: Color,
+    pub pages: Vec<String>,
+}
+
+impl Pages {
+    /// Split `lines` in pages of at most `per_page` lines and [`PAGE_LENGTH`] characters.
+    ///
+    /// A line longer than a page is cut.
+    pub fn from_lines(
+        title: impl Into<String>, color: Color, lines: &[String], per_page: usize,
+    ) -> Self {
+        let mut pages = Vec::new();
+        let mut page = String::new();
+        let mut count = 0;
+
+        for line in lines {
+            let line = line.chars().take(PAGE_LENGTH).collect::<String>();
+            if count > 0 && (count == per_page || page.len() + line.len() + 1 > PAGE_LENGTH) {
+                pages.push(page);
+                page = String::new();
+                count = 0;
+            }
+            if count > 0 {
+                page.push('\n');
+            }
+            page.push_str(&line);
+            count += 1;
+        }
+        if count > 0 || pages.is_empty() {
+            pages.push(page);
+        }
+
+        Self {
+            title: title.into(),
+            color,
+            pages,
+        }
+    }
+
+    /// Wrap every page with `before` and `after`, like the fences of a code block.
+    pub fn wrap(mut self, before: &str, after: &str) -> Self {
+        for page in &mut self.pages {
+            *page = format!("{}{}{}", before, page, after);
+        }
+        self
+    }
+
+    /// The embed showing page `page`, counted from 0.
+    pub fn embed(&self, page: usize) -> Embed {
+        let mut description = self.pages.get(page).cloned().unwrap_or_default();
+        if self.pages.len() > 1 {
+            description.push_str(&format!("\n\nPage {} of {}", page + 1, self.pages.len()));
+        }
+
+        Embed {
+            title: self.title.clone(),
+            color: self.color,
+            description,
+            fields: Vec::new(),
+        }
+    }
+
+    /// Send the first page in the channel of `msg`, and let its author flip the others.
+    ///
+    /// The pages are flipped in the background, so this returns once the first page is
+    /// sent.
+    pub async fn send(self, ctx: &Context, msg: &Message) -> CommandResult {
+        let first = self.embed(0);
+        let sent = msg
+            .channel_id
+            .send_message(&ctx.http, |m| m.embed(|e| first.build(e)))
+            .await?;
+
+        if self.pages.len() > 1 {
+            sent.react(&ctx.http, REACTION_PREVIOUS).await?;
+            sent.react(&ctx.http, REACTION_NEXT).await?;
+            tokio::spawn(self.flip(ctx.clone(), sent, msg.author.id));
+        }
+
+        Ok(())
+    }
+
+    /// Show another page every time `author_id` reacts to `sent`, until the timeout.
+    async fn flip(self, ctx: Context, mut sent: Message, author_id: UserId) {
+        let mut page = 0;
+
+        while let Some(action) = sent
+            .await_reaction(&ctx)
+            .author_id(author_id.0)
+            .timeout(PAGE_TIMEOUT)
+            .await
+        {
+            let reaction = match &*action {
+                ReactionAction::Added(r) | ReactionAction::Removed(r) => r,
+            };
+            // Needs the permission to manage messages, so the caller may have to remove it
+            let _ = reaction.delete(&ctx).await;
+
+            let new_page = match turn(page, self.pages.len(), &reaction.emoji.to_string()) {
+                Some(p) => p,
+                None => continue,
+            };
+            page = new_page;
+
+            let embed = self.embed(page);
+            if let Err(why) = sent.edit(&ctx, |m| m.embed(|e| embed.build(e))).await {
+                eprintln!(
+                    "Failed to show page {} of {}: {:?}",
+                    page + 1,
+                    self.title,
+                    why
+                );
+                return;
+            }
+        }
+
+        let _ = sent.delete_reactions(&ctx).await;
+    }
+}
+
+/// The page shown after reacting with `emoji` on page `page` of `len`, if it changes.
+fn turn(page: usize, len: usize, emoji: &str) -> Option<usize> {
+    if emoji == REACTION_PREVIOUS.to_string() && page > 0 {
+        Some(page - 1)
+    } else if emoji == REACTION_NEXT.to_string() && page + 1 < len {
+        Some(page + 1)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::testing::{message, MockDiscord};
+
+    use super::*;
+
+    fn lines(n: usize) -> Vec<String> {
+        (1..=n).map(|i| format!("line {}", i)).collect()
+    }
+
+    #[test]
+    fn lines_are_split_in_pages() {
+        let pages = Pages::from_lines("TITLE", Color::BLUE, &lines(5), 2);
+
+        assert_eq!(pages.pages, vec![
+            "line 1\nline 2".to_string(),
+            "line 3\nline 4".to_string(),
+            "line 5".to_string(),
+        ]);
+        assert_eq!(pages.embed(1).description, "line 3\nline 4\n\nPage 2 of 3");
+    }
+
+    #[test]
+    fn pages_fit_in_an_embed() {
+        let long = vec!["x".repeat(1500); 3];
+
+        let pages = Pages::from_lines("TITLE", Color::BLUE, &long, 10);
+
+        assert_eq!(pages.pages.len(), 3);
+        assert!(pages.pages.iter().all(|p| p.len() <= PAGE_LENGTH));
+    }
+
+    #[test]
+    fn a_single_page_has_no_counter() {
+        let pages = Pages::from_lines("TITLE", Color::BLUE, &[], 10).wrap("```\n", "\n```");
+
+        assert_eq!(pages.embed(0).description, "```\n\n```");
+    }
+
+    #[test]
+    fn reactions_turn_the_pages_within_bounds() {
+        let previous = REACTION_PREVIOUS.to_string();
+        let next = REACTION_NEXT.to_string();
+
+        assert_eq!(turn(0, 3, &next), Some(1));
+        assert_eq!(turn(2, 3, &next), None);
+        assert_eq!(turn(2, 3, &previous), Some(1));
+        assert_eq!(turn(0, 3, &previous), None);
+        assert_eq!(turn(1, 3, "👍"), None);
+    }
+
+    #[tokio::test]
+    async fn reactions_of_the_author_flip_the_pages() {
+        let mock = MockDiscord::start().await;
+        let msg = message("!role list", 10, None, 20);
+        let pages = Pages::from_lines("TITLE", Color::BLUE, &lines(3), 2);
+
+        pages.send(&mock.ctx, &msg).await.unwrap();
+
+        // The reaction is only seen once the pages wait for it, so send it until they do
+        let mut edit = None;
+        for _ in 0..10 {
+            mock.dispatch(
+                "MESSAGE_REACTION_ADD",
+                json!({
+                    "user_id": "10",
+                    "channel_id": "20",
+                    "message_id": "200",
+                    "emoji": {"id": null, "name": REACTION_NEXT.to_string()},
+                }),
+            );
+            edit = mock
+                .requests()
+                .into_iter()
+                .find(|r| r.method == "PATCH" && r.path == "/channels/20/messages/200");
+            if edit.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        let edit = edit.expect("The pages were not flipped");
+        assert_eq!(
+            edit.body["embeds"][0]["description"],
+            "line 3\n\nPage 2 of 2"
+        );
+    }
+}
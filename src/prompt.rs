@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use serenity::{framework::standard::CommandResult, model::channel::Message, prelude::*};
+
+/// How long to wait for the caller to answer a confirmation prompt.
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Ask the author of `msg` to confirm an action.
+///
+/// Returns `true` only if the author replies `yes` in the same channel before the
+/// timeout.
+pub async fn confirm(ctx: &Context, msg: &Message, question: &str) -> CommandResult<bool> {
+    msg.channel_id
+        .say(
+            &ctx.http,
+            format!(
+                "{}\n\nReply `yes` within {} seconds to confirm.",
+                question,
+                CONFIRM_TIMEOUT.as_secs()
+            ),
+        )
+        .await?;
+
+    let answer = msg
+        .author
+        .await_reply(&ctx)
+        .channel_id(msg.channel_id)
+        .timeout(CONFIRM_TIMEOUT)
+        .await;
+
+    let confirmed = match answer {
+        Some(reply) => reply.content.trim().eq_ignore_ascii_case("yes"),
+        None => false,
+    };
+
+    if !confirmed {
+        msg.channel_id.say(&ctx.http, "Cancelled.").await?;
+    }
+
+    Ok(confirmed)
+}
@@ -0,0 +1,376 @@
+use std::time::Duration;
+
+use serenity::{
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{channel::Message, id::UserId},
+    prelude::*,
+    utils::Colour as Color,
+};
+
+use time::OffsetDateTime;
+
+use crate::{
+    duration,
+    module::Module,
+    paginator::Pages,
+    reply::Reply,
+    storage::{ScheduledTask, TaskAction},
+    types::StorageContainer,
+};
+
+/// Longest wait of a reminder.
+const MAX_WAIT: Duration = Duration::from_secs(365 * 86_400);
+/// Most pending reminders of a user.
+const MAX_REMINDERS: usize = 25;
+/// Longest text of a reminder.
+const TEXT_LIMIT: usize = 1000;
+/// Number of reminders per page of `remind list`.
+const LIST_PAGE_SIZE: usize = 10;
+
+#[group]
+#[commands(remind)]
+struct Remind;
+
+/// Reminders sent by the scheduler, in a direct message or in the channel they were set
+/// in.
+pub struct RemindModule;
+
+impl Module for RemindModule {
+    fn name(&self) -> &'static str {
+        "remind"
+    }
+
+    fn groups(&self) -> Vec<&'static CommandGroup> {
+        vec![&REMIND_GROUP]
+    }
+}
+
+/// Remind you of something after a while.
+///
+/// `me` sends the reminder in a direct message and `here` in this channel. The duration
+/// can be like `2h30m` or `2 hours 30 minutes`, up to a year.
+#[command]
+#[sub_commands(list, cancel)]
+#[aliases(lembrar)]
+#[min_args(3)]
+#[usage = "remind <me | here> [in] <DURATION> <TEXT>` or `remind list` or `remind cancel <ID>"]
+#[example = "remind me in 2h30m take the cake out of the oven"]
+async fn remind(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (wait, text, in_channel) = match parse_reminder(args.rest()) {
+        Ok(reminder) => reminder,
+        Err(why) => return Reply::embed(" ", Color::RED, why).send(ctx, msg).await,
+    };
+
+    let storage = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(s) => s.clone(),
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let result = {
+        let mut storage = storage.write().await;
+        if reminders(&storage.tasks, msg.author.id).len() >= MAX_REMINDERS {
+            Err(format!(
+                "You already have {} reminders, cancel one first.",
+                MAX_REMINDERS
+            ))
+        } else {
+            let id = storage.schedule(now + wait.as_secs() as i64, TaskAction::Remind {
+                guild: msg.guild_id.map_or(0, |id| id.0),
+                user: msg.author.id.0,
+                channel: if in_channel {
+                    Some(msg.channel_id.0)
+                } else {
+                    None
+                },
+                text,
+                set: now,
+            });
+            storage.save()?;
+            Ok(id)
+        }
+    };
+
+    let reply = match result {
+        Ok(id) => Reply::embed(
+            "REMINDER",
+            Color::TEAL,
+            format!(
+                "I will remind you {} in {}. Cancel it with `remind cancel {}`.",
+                if in_channel {
+                    "here"
+                } else {
+                    "in a direct message"
+                },
+                duration::format(wait),
+                id
+            ),
+        ),
+        Err(why) => Reply::embed(" ", Color::RED, why),
+    };
+    reply.send(ctx, msg).await
+}
+
+/// List your pending reminders, soonest first.
+#[command]
+#[aliases(listar)]
+#[num_args(0)]
+#[usage = "remind list"]
+async fn list(ctx: &Context, msg: &Message) -> CommandResult {
+    let tasks = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(storage) => reminders(&storage.read().await.tasks, msg.author.id),
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    list_pages(&tasks, now).send(ctx, msg).await
+}
+
+/// Cancel one of your reminders by its id, shown by `remind list`.
+#[command]
+#[aliases(cancelar)]
+#[num_args(1)]
+#[usage = "remind cancel <ID>"]
+async fn cancel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let id = match args.single::<u64>() {
+        Ok(id) => id,
+        Err(_) => {
+            return Reply::embed(" ", Color::RED, "Invalid reminder id.")
+                .send(ctx, msg)
+                .await;
+        },
+    };
+
+    let storage = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(s) => s.clone(),
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    let cancelled = {
+        let mut storage = storage.write().await;
+        let owned = reminders(&storage.tasks, msg.author.id)
+            .iter()
+            .any(|t| t.id == id);
+        if owned {
+            storage.remove_tasks(&[id]);
+            storage.save()?;
+        }
+        owned
+    };
+
+    let reply = if cancelled {
+        Reply::embed(
+            "REMINDER",
+            Color::TEAL,
+            format!("Cancelled reminder `#{}`.", id),
+        )
+    } else {
+        Reply::embed(" ", Color::RED, format!("You have no reminder `#{}`.", id))
+    };
+    reply.send(ctx, msg).await
+}
+
+/// The wait, the text and whether to send the reminder in the channel, from the arguments
+/// of `remind`.
+fn parse_reminder(args: &str) -> Result<(Duration, String, bool), String> {
+    let usage = || "Usage: `remind <me | here> [in] <DURATION> <TEXT>`".to_string();
+
+    let args = args.trim_start();
+    let (target, rest) = match args.find(char::is_whitespace) {
+        Some(i) => (&args[..i], args[i..].trim_start()),
+        None => return Err(usage()),
+    };
+    let in_channel = match target.to_lowercase().as_str() {
+        "me" | "mim" => false,
+        "here" | "aqui" => true,
+        _ => return Err(usage()),
+    };
+
+    let rest = ["in ", "em "]
+        .iter()
+        .find_map(|word| rest.strip_prefix(word))
+        .unwrap_or(rest);
+    let (wait, text) = match duration::parse_prefix(rest) {
+        Some(reminder) => reminder,
+        None => return Err(format!("Invalid duration.\n\n{}", usage())),
+    };
+
+    if wait > MAX_WAIT {
+        Err("Reminders can wait at most a year.".to_string())
+    } else if text.is_empty() {
+        Err(format!("What should I remind you of?\n\n{}", usage()))
+    } else if text.chars().count() > TEXT_LIMIT {
+        Err(format!(
+            "Reminders can have at most {} characters.",
+            TEXT_LIMIT
+        ))
+    } else {
+        Ok((wait, text.to_string(), in_channel))
+    }
+}
+
+/// The pending reminders of `user_id`, soonest first.
+fn reminders(tasks: &[ScheduledTask], user_id: UserId) -> Vec<ScheduledTask> {
+    let mut reminders = tasks
+        .iter()
+        .filter(|t| matches!(t.action, TaskAction::Remind { user, .. } if user == user_id.0))
+        .cloned()
+        .collect::<Vec<_>>();
+    reminders.sort_by_key(|t| t.due);
+    reminders
+}
+
+fn list_pages(reminders: &[ScheduledTask], now: i64) -> Pages {
+    let lines = if reminders.is_empty() {
+        vec!["You have no reminders.".to_string()]
+    } else {
+        reminders
+            .iter()
+            .filter_map(|t| match &t.action {
+                TaskAction::Remind { channel, text, .. } => {
+                    let wait = Duration::from_secs((t.due - now).max(0) as u64);
+                    let place = match channel {
+                        Some(id) => format!("<#{}>", id),
+                        None => "DM".to_string(),
+                    };
+                    let text = text.chars().take(100).collect::<String>();
+                    Some(format!(
+                        "`#{}` in {} ({}): {}",
+                        t.id,
+                        duration::format(wait),
+                        place,
+                        text
+                    ))
+                },
+                _ => None,
+            })
+            .collect()
+    };
+
+    Pages::from_lines("REMINDERS", Color::BLUE, &lines, LIST_PAGE_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{message, MockDiscord};
+
+    use super::*;
+
+    const CHANNEL: u64 = 20;
+
+    #[test]
+    fn reminders_are_parsed() {
+        assert_eq!(
+            parse_reminder("me in 2h30m take a break"),
+            Ok((
+                Duration::from_secs(9_000),
+                "take a break".to_string(),
+                false
+            ))
+        );
+        assert_eq!(
+            parse_reminder("here 10 minutes stand up"),
+            Ok((Duration::from_secs(600), "stand up".to_string(), true))
+        );
+        assert!(parse_reminder("me in 2h30m").is_err());
+        assert!(parse_reminder("me in soon stand up").is_err());
+        assert!(parse_reminder("you in 1h stand up").is_err());
+        assert_eq!(
+            parse_reminder("me in 53w stand up"),
+            Err("Reminders can wait at most a year.".to_string())
+        );
+    }
+
+    #[test]
+    fn only_the_reminders_of_the_user_are_listed() {
+        let remind = |id, due, user| ScheduledTask {
+            id,
+            due,
+            action: TaskAction::Remind {
+                guild: 0,
+                user,
+                channel: None,
+                text: format!("text {}", id),
+                set: 0,
+            },
+        };
+        let tasks = vec![remind(1, 500, 10), remind(2, 200, 11), remind(3, 300, 10)];
+
+        let mine = reminders(&tasks, UserId(10));
+        let description = list_pages(&mine, 100).embed(0).description;
+
+        assert_eq!(
+            description,
+            "`#3` in 3m 20s (DM): text 3\n`#1` in 6m 40s (DM): text 1"
+        );
+    }
+
+    #[tokio::test]
+    async fn reminders_are_scheduled_and_cancelled() {
+        let mock = MockDiscord::start().await;
+        let msg = message("!remind here in 1h stand up", 10, None, CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        let id = {
+            let data = mock.ctx.data.read().await;
+            let storage = data.get::<StorageContainer>().unwrap().read().await;
+            let task = &storage.tasks[0];
+            match &task.action {
+                TaskAction::Remind {
+                    user,
+                    channel,
+                    text,
+                    ..
+                } => assert_eq!(
+                    (*user, *channel, text.as_str()),
+                    (10, Some(CHANNEL), "stand up")
+                ),
+                action => panic!("unexpected action {:?}", action),
+            }
+            task.id
+        };
+
+        let content = format!("!remind cancel {}", id);
+        mock.send(&message(&content, 11, None, CHANNEL))
+            .await
+            .unwrap();
+        mock.send(&message(&content, 10, None, CHANNEL))
+            .await
+            .unwrap();
+
+        let embeds = mock.sent_embeds();
+        assert_eq!(
+            embeds[1]["description"],
+            format!("You have no reminder `#{}`.", id)
+        );
+        assert_eq!(
+            embeds[2]["description"],
+            format!("Cancelled reminder `#{}`.", id)
+        );
+        let data = mock.ctx.data.read().await;
+        let storage = data.get::<StorageContainer>().unwrap().read().await;
+        assert!(storage.tasks.is_empty());
+    }
+}
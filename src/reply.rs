@@ -0,0 +1,232 @@
+//! Command replies as plain data.
+//!
+//! Command logic builds a [`Reply`] from its inputs without touching Discord, and
+//! [`Reply::send`] is the only place that turns it into API calls. That keeps the logic
+//! testable with plain unit tests.
+
+use serenity::{
+    builder::CreateEmbed,
+    framework::standard::CommandResult,
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId, RoleId, UserId},
+    },
+    prelude::*,
+    utils::Colour as Color,
+};
+
+use crate::{management, storage::RoleChangeSource};
+
+/// Reaction for a successful action.
+pub const REACTION_OK: char = '🟢';
+/// Reaction for a failed action.
+pub const REACTION_FAIL: char = '🔴';
+/// Reaction for an invalid argument.
+pub const REACTION_WARNING: char = '⚠';
+
+/// Everything a command does in response to a message.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Reply {
+    /// Text of the message to send.
+    pub content:   Option<String>,
+    /// Embed of the message to send.
+    pub embed:     Option<Embed>,
+    /// Send the text as a reply to the command message. Ignored if there is an embed.
+    pub reference: bool,
+    /// Reactions added to the command message.
+    pub reactions: Vec<char>,
+    /// Roles changed on the author of the command message.
+    ///
+    /// The command message gets [`REACTION_OK`] or [`REACTION_FAIL`] after the change.
+    pub roles:     Option<RoleChange>,
+}
+
+/// A message embed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Embed {
+    pub title:       String,
+    pub color:       Color,
+    pub description: String,
+    /// `(name, value, inline)` fields.
+    pub fields:      Vec<(String, String, bool)>,
+}
+
+/// Roles to add to and remove from a member.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RoleChange {
+    pub add: Vec<RoleId>,
+    pub remove: Vec<RoleId>,
+    /// Seconds after which the added roles are removed again, if they are temporary.
+    pub expires_in: Option<u64>,
+}
+
+impl RoleChange {
+    /// Check if the change adds and removes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.remove.is_empty()
+    }
+
+    /// The part of the change that does something to a member with `roles`.
+    ///
+    /// A role both added and removed is added.
+    pub fn applied_to(&self, roles: &[RoleId]) -> RoleChange {
+        RoleChange {
+            add: self
+                .add
+                .iter()
+                .filter(|r| !roles.contains(r))
+                .copied()
+                .collect(),
+            remove: self
+                .remove
+                .iter()
+                .filter(|r| roles.contains(r) && !self.add.contains(r))
+                .copied()
+                .collect(),
+            expires_in: self.expires_in,
+        }
+    }
+}
+
+impl Embed {
+    /// Fill a serenity embed builder with the embed.
+    pub fn build(self, e: &mut CreateEmbed) -> &mut CreateEmbed {
+        e.title(self.title)
+            .color(self.color)
+            .description(self.description);
+        for (name, value, inline) in self.fields {
+            e.field(name, value, inline);
+        }
+        e
+    }
+}
+
+impl Reply {
+    /// A reply with only text.
+    pub fn text(content: impl Into<String>) -> Self {
+        Self {
+            content: Some(content.into()),
+            ..Self::default()
+        }
+    }
+
+    /// A reply with only an embed.
+    pub fn embed(title: impl Into<String>, color: Color, description: impl Into<String>) -> Self {
+        Self {
+            embed: Some(Embed {
+                title: title.into(),
+                color,
+                description: description.into(),
+                fields: Vec::new(),
+            }),
+            ..Self::default()
+        }
+    }
+
+    /// Send the reply in the channel of `msg`.
+    pub async fn send(self, ctx: &Context, msg: &Message) -> CommandResult {
+        for &reaction in &self.reactions {
+            msg.react(&ctx.http, reaction).await?;
+        }
+
+        if let Some(change) = &self.roles {
+            let result = match msg.guild_id {
+                Some(guild_id) => apply_roles(ctx, guild_id, msg.author.id, change)
+                    .await
+                    .map(|applied| (guild_id, applied)),
+                None => Err("Roles can only be changed in a guild".into()),
+            };
+            let reaction = match result {
+                Ok((guild_id, applied)) => {
+                    println!(
+                        "Successfully changed the roles of {}: {:?}",
+                        msg.author.name, applied
+                    );
+                    if !applied.is_empty() {
+                        management::record_role_change(
+                            ctx,
+                            guild_id,
+                            msg.author.id,
+                            &applied,
+                            RoleChangeSource::Command,
+                        )
+                        .await;
+                    }
+                    REACTION_OK
+                },
+                Err(why) => {
+                    eprintln!(
+                        "Failed to change the roles of {}: {:?}: {}",
+                        msg.author.name, change, why
+                    );
+                    REACTION_FAIL
+                },
+            };
+            msg.react(&ctx.http, reaction).await?;
+        }
+
+        match (self.content, self.embed) {
+            (None, None) => {},
+            (Some(content), None) if self.reference => {
+                msg.reply(ctx, content).await?;
+            },
+            (content, embed) => {
+                send_message(ctx, msg.channel_id, content, embed).await?;
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Send the text and the embed of the reply in `channel_id`.
+    ///
+    /// Reactions and role changes need a command message, so they are ignored.
+    pub async fn send_to(self, ctx: &Context, channel_id: ChannelId) -> CommandResult {
+        if self.content.is_some() || self.embed.is_some() {
+            send_message(ctx, channel_id, self.content, self.embed).await?;
+        }
+
+        Ok(())
+    }
+}
+
+async fn send_message(
+    ctx: &Context, channel_id: ChannelId, content: Option<String>, embed: Option<Embed>,
+) -> CommandResult {
+    channel_id
+        .send_message(&ctx.http, |m| {
+            if let Some(content) = content {
+                m.content(content);
+            }
+            if let Some(embed) = embed {
+                m.embed(|e| embed.build(e));
+            }
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Change the roles of a member in a single member edit.
+///
+/// Returns the roles that actually changed: the added roles the member did not have and
+/// the removed roles they had.
+pub async fn apply_roles(
+    ctx: &Context, guild_id: GuildId, user_id: UserId, change: &RoleChange,
+) -> CommandResult<RoleChange> {
+    let member = guild_id.member(ctx, user_id).await?;
+    let applied = change.applied_to(&member.roles);
+    if !applied.is_empty() {
+        let roles = member
+            .roles
+            .iter()
+            .filter(|r| !applied.remove.contains(r))
+            .chain(&applied.add)
+            .copied()
+            .collect::<Vec<_>>();
+        member.edit(&ctx.http, |m| m.roles(&roles)).await?;
+    }
+
+    Ok(applied)
+}
@@ -0,0 +1,231 @@
+//! Persistent scheduled tasks.
+//!
+//! Tasks are kept in the [`Storage`], so they survive restarts. A background loop checks
+//! them every [`TICK`] and runs the ones that are due; a task is only removed after it
+//! ran, so a task interrupted by a shutdown runs again on the next start.
+//!
+//! [`Storage`]: crate::storage::Storage
+
+use std::{sync::Arc, time::Duration};
+
+use serenity::{
+    http::Http,
+    model::id::{ChannelId, UserId},
+    prelude::*,
+    utils::Colour as Color,
+};
+
+use time::OffsetDateTime;
+use tokio::task::JoinHandle;
+
+use crate::{
+    storage::TaskAction,
+    types::{SchedulerHandle, StorageContainer},
+};
+
+/// How often the due tasks are checked.
+pub const TICK: Duration = Duration::from_secs(10);
+
+/// Start the loop running the due tasks, unless it is already running.
+///
+/// Called on every ready event, so every client gets the loop once, even after
+/// reconnecting or with many shards.
+pub async fn start(ctx: &Context) {
+    let mut data = ctx.data.write().await;
+    if !data.contains_key::<SchedulerHandle>() {
+        let handle = spawn(Arc::clone(&ctx.http), Arc::clone(&ctx.data));
+        data.insert::<SchedulerHandle>(handle);
+    }
+}
+
+/// Start the loop running the due tasks.
+pub fn spawn(http: Arc<Http>, data: Arc<RwLock<TypeMap>>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TICK);
+        loop {
+            interval.tick().await;
+            run_due(&http, &data, OffsetDateTime::now_utc().unix_timestamp()).await;
+            save_unsaved(&data).await;
+        }
+    })
+}
+
+/// Run the tasks due at `now` and remove them from the storage.
+///
+/// A task that fails is logged and removed as well, since retrying an unban or a role
+/// change that Discord refused would fail again.
+pub async fn run_due(http: &Http, data: &RwLock<TypeMap>, now: i64) {
+    let storage = match data.read().await.get::<StorageContainer>() {
+        Some(s) => s.clone(),
+        None => return,
+    };
+
+    let due = storage.read().await.due_tasks(now);
+    if due.is_empty() {
+        return;
+    }
+
+    for task in &due {
+        match execute(http, &task.action).await {
+            Ok(()) => println!("Ran scheduled task {}: {:?}", task.id, task.action),
+            Err(why) => eprintln!(
+                "Scheduled task {} failed: {:?}: {}",
+                task.id, task.action, why
+            ),
+        }
+    }
+
+    let ids = due.iter().map(|t| t.id).collect::<Vec<_>>();
+    let mut storage = storage.write().await;
+    storage.remove_tasks(&ids);
+    if let Err(why) = storage.save() {
+        eprintln!("Failed to save the storage: {}", why);
+    }
+}
+
+/// Save the changes left for a later save, like the uses of tags.
+pub async fn save_unsaved(data: &RwLock<TypeMap>) {
+    if let Some(storage) = data.read().await.get::<StorageContainer>() {
+        if let Err(why) = storage.read().await.save_unsaved() {
+            eprintln!("Failed to save the storage: {}", why);
+        }
+    }
+}
+
+async fn execute(http: &Http, action: &TaskAction) -> serenity::Result<()> {
+    match *action {
+        TaskAction::Unban { guild, user } => http.remove_ban(guild, user).await,
+        TaskAction::RemoveRole { guild, user, role } => {
+            http.remove_member_role(guild, user, role).await
+        },
+        TaskAction::AddRole { guild, user, role } => http.add_member_role(guild, user, role).await,
+        TaskAction::Remind {
+            user,
+            channel,
+            ref text,
+            set,
+            ..
+        } => remind(http, UserId(user), channel.map(ChannelId), text, set).await,
+    }
+}
+
+/// Send a reminder set at `set` to `user_id`, in `channel_id` or in a direct message.
+///
+/// The text is in an embed, so the only mention that pings is the user's.
+async fn remind(
+    http: &Http, user_id: UserId, channel_id: Option<ChannelId>, text: &str, set: i64,
+) -> serenity::Result<()> {
+    let channel_id = match channel_id {
+        Some(id) => id,
+        None => user_id.create_dm_channel(http).await?.id,
+    };
+
+    channel_id
+        .send_message(http, |m| {
+            m.content(user_id.mention())
+                .allowed_mentions(|a| a.empty_parse().users(vec![user_id]))
+                .embed(|e| {
+                    e.title("REMINDER")
+                        .color(Color::BLUE)
+                        .description(text)
+                        .footer(|f| {
+                            f.text(format!(
+                                "Set on {}",
+                                OffsetDateTime::from_unix_timestamp(set).format("%F %T UTC")
+                            ))
+                        })
+                })
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::MockDiscord;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn the_loop_starts_once() {
+        let mock = MockDiscord::start().await;
+        {
+            let data = mock.ctx.data.read().await;
+            let mut storage = data.get::<StorageContainer>().unwrap().write().await;
+            storage.schedule(100, TaskAction::Unban {
+                guild: 30,
+                user:  50,
+            });
+        }
+
+        start(&mock.ctx).await;
+        start(&mock.ctx).await;
+        // The first tick is right away
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let unbans = mock
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "DELETE" && r.path == "/guilds/30/bans/50")
+            .count();
+        assert_eq!(unbans, 1);
+        assert!(mock.ctx.data.read().await.contains_key::<SchedulerHandle>());
+    }
+
+    #[tokio::test]
+    async fn only_due_tasks_run() {
+        let mock = MockDiscord::start().await;
+        {
+            let data = mock.ctx.data.read().await;
+            let mut storage = data.get::<StorageContainer>().unwrap().write().await;
+            storage.schedule(100, TaskAction::Unban {
+                guild: 30,
+                user:  50,
+            });
+            storage.schedule(200, TaskAction::RemoveRole {
+                guild: 30,
+                user:  51,
+                role:  60,
+            });
+        }
+
+        run_due(&mock.ctx.http, &mock.ctx.data, 150).await;
+
+        let paths = mock
+            .requests()
+            .into_iter()
+            .map(|r| format!("{} {}", r.method, r.path))
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["DELETE /guilds/30/bans/50".to_string()]);
+
+        let data = mock.ctx.data.read().await;
+        let storage = data.get::<StorageContainer>().unwrap().read().await;
+        assert_eq!(storage.tasks.len(), 1);
+        assert_eq!(storage.tasks[0].due, 200);
+    }
+
+    #[tokio::test]
+    async fn reminders_are_sent_in_their_channel() {
+        let mock = MockDiscord::start().await;
+        {
+            let data = mock.ctx.data.read().await;
+            let mut storage = data.get::<StorageContainer>().unwrap().write().await;
+            storage.schedule(100, TaskAction::Remind {
+                guild:   30,
+                user:    50,
+                channel: Some(20),
+                text:    "Stand up @everyone".to_string(),
+                set:     0,
+            });
+        }
+
+        run_due(&mock.ctx.http, &mock.ctx.data, 150).await;
+
+        let sent = mock.sent_messages();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0]["content"], "<@50>");
+        assert_eq!(sent[0]["allowed_mentions"]["parse"], serde_json::json!([]));
+        assert_eq!(mock.sent_embeds()[0]["description"], "Stand up @everyone");
+    }
+}
@@ -0,0 +1,423 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{ChannelId, GuildId};
+
+/// Persistent bot state, saved as JSON.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Storage {
+    /// Per guild data, keyed by guild id.
+    pub guilds: HashMap<u64, GuildData>,
+    /// Ids of users added as co-owners with the `owners` command.
+    pub co_owners: HashSet<u64>,
+    /// Actions waiting to be run by the scheduler.
+    pub tasks: Vec<ScheduledTask>,
+    /// Id of the next scheduled task.
+    pub next_task_id: u64,
+    #[serde(skip)]
+    path: PathBuf,
+    /// Whether a change was left for a later save.
+    #[serde(skip)]
+    unsaved: AtomicBool,
+}
+
+/// Data stored for a single guild.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuildData {
+    /// Commands and groups disabled in every channel of the guild.
+    pub disabled: HashSet<String>,
+    /// Per channel overrides, keyed by channel id.
+    pub channels: HashMap<u64, ChannelRules>,
+    /// Moderation actions, oldest first. Each one is a numbered case.
+    pub actions: Vec<ModAction>,
+    /// Number of the last case.
+    pub last_case: u64,
+    /// Channel where every case is posted.
+    pub mod_log: Option<u64>,
+    /// Channel where message edits and deletions, joins, leaves and member updates are
+    /// posted.
+    pub event_log: Option<u64>,
+    /// Roles given or taken through the bot, oldest first.
+    pub role_changes: Vec<RoleChangeRecord>,
+    /// Channel where every role change is posted.
+    pub role_log: Option<u64>,
+    /// Message posted when a member joins.
+    pub welcome: Option<Greeting>,
+    /// Message posted when a member leaves.
+    pub goodbye: Option<Greeting>,
+    /// Rules sent in a direct message to every member who joins.
+    pub rules: Option<String>,
+    /// Roles given to members when they join.
+    pub auto_roles: AutoRoles,
+    /// Texts saved with `tag create`, keyed by lowercase name.
+    pub tags: BTreeMap<String, Tag>,
+}
+
+/// Command overrides for a single channel.
+///
+/// They take precedence over the guild-wide settings.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelRules {
+    pub enabled:  HashSet<String>,
+    pub disabled: HashSet<String>,
+}
+
+/// A moderation action taken through the bot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModAction {
+    /// Case number, unique in the guild. Zero until the action is recorded.
+    #[serde(default)]
+    pub id: u64,
+    pub kind: ModActionKind,
+    /// Id of the user the action was taken on, if it targets a single user.
+    pub target: Option<u64>,
+    /// Id of the user who took the action.
+    pub moderator: u64,
+    pub reason: Option<String>,
+    /// When the action was taken, in seconds since the unix epoch.
+    pub time: i64,
+    /// How long the action lasts, in seconds, if it is temporary.
+    pub duration: Option<u64>,
+}
+
+/// What a [`ModAction`] did.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ModActionKind {
+    Warn,
+    Kick,
+    Ban { delete_days: u8 },
+    Unban,
+    Mute,
+    Unmute,
+    Purge { channel: u64, count: usize },
+}
+
+/// Message posted when a member joins or leaves a guild.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Greeting {
+    /// Channel where the message is posted.
+    pub channel:  u64,
+    /// Text of the message, with the placeholders of [`crate::welcome::render`].
+    pub template: String,
+    /// Post the message in an embed instead of as plain text.
+    #[serde(default)]
+    pub embed:    bool,
+}
+
+/// A text saved with `tag create` and sent back by name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tag {
+    pub content: String,
+    /// Id of the member who created the tag.
+    pub owner:   u64,
+    /// Number of times the tag was sent.
+    #[serde(default)]
+    pub uses:    u64,
+    /// When the tag was created, in seconds since the unix epoch.
+    pub created: i64,
+}
+
+/// Roles given to members when they join a guild.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoRoles {
+    pub roles:     Vec<u64>,
+    /// Seconds to wait after the join before giving the roles.
+    pub delay:     Option<u64>,
+    /// Wait until the member passed the membership screening of the guild.
+    pub screening: bool,
+    /// Members who joined but did not pass the screening yet.
+    pub waiting:   HashSet<u64>,
+    /// Give back their self-assigned roles to members who rejoin.
+    pub restore:   bool,
+}
+
+/// Roles given to or taken from a member through the bot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoleChangeRecord {
+    pub user:     u64,
+    pub added:    Vec<u64>,
+    pub removed:  Vec<u64>,
+    /// When the roles changed, in seconds since the unix epoch.
+    pub time:     i64,
+    pub source:   RoleChangeSource,
+    /// How long the added roles last, in seconds, if they are temporary.
+    #[serde(default)]
+    pub duration: Option<u64>,
+}
+
+/// How a member asked for a [`RoleChangeRecord`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoleChangeSource {
+    Command,
+    Reaction,
+    Menu,
+}
+
+/// An action the scheduler runs once it is due.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledTask {
+    pub id:     u64,
+    /// When the task is due, in seconds since the unix epoch.
+    pub due:    i64,
+    pub action: TaskAction,
+}
+
+/// What a [`ScheduledTask`] does.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TaskAction {
+    /// Lift the ban of a user.
+    Unban { guild: u64, user: u64 },
+    /// Remove a role from a member.
+    RemoveRole { guild: u64, user: u64, role: u64 },
+    /// Give a role to a member.
+    AddRole { guild: u64, user: u64, role: u64 },
+    /// Remind a user of `text`, in `channel` or in a direct message.
+    ///
+    /// `guild` is 0 if the reminder was set in a direct message, and `set` is when it was
+    /// set, in seconds since the unix epoch.
+    Remind {
+        guild:   u64,
+        user:    u64,
+        channel: Option<u64>,
+        text:    String,
+        set:     i64,
+    },
+}
+
+impl Storage {
+    /// Load the storage from `path`.
+    ///
+    /// If the file does not exist, an empty storage that saves to `path` is returned.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+
+        let mut storage: Storage = match fs::read(path) {
+            Ok(content) => serde_json::from_slice(&content)?,
+            Err(why) if why.kind() == io::ErrorKind::NotFound => Storage::default(),
+            Err(why) => return Err(why),
+        };
+        storage.path = path.to_path_buf();
+
+        Ok(storage)
+    }
+
+    /// Write the storage back to the file it was loaded from.
+    pub fn save(&self) -> io::Result<()> {
+        let content = serde_json::to_vec_pretty(self)?;
+
+        // Write to a temporary file first so a crash never leaves a truncated file behind
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, &self.path)?;
+
+        self.unsaved.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Leave the last change for a later save, by [`Storage::save_unsaved`] or any save.
+    ///
+    /// For frequent changes that are cheap to lose, like the uses of tags.
+    pub fn defer_save(&self) {
+        self.unsaved.store(true, Ordering::SeqCst);
+    }
+
+    /// Save the storage if a change was left for later since the last save.
+    pub fn save_unsaved(&self) -> io::Result<()> {
+        if self.unsaved.load(Ordering::SeqCst) {
+            self.save()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Get the data of a guild, if there is any.
+    pub fn guild(&self, guild_id: GuildId) -> Option<&GuildData> {
+        self.guilds.get(&guild_id.0)
+    }
+
+    /// Get the data of a guild, creating it if needed.
+    pub fn guild_mut(&mut self, guild_id: GuildId) -> &mut GuildData {
+        self.guilds.entry(guild_id.0).or_default()
+    }
+
+    /// Schedule `action` to run at `due`, replacing any task doing the same action.
+    ///
+    /// Returns the id of the task.
+    pub fn schedule(&mut self, due: i64, action: TaskAction) -> u64 {
+        self.cancel(&action);
+
+        let id = self.next_task_id;
+        self.next_task_id += 1;
+        self.tasks.push(ScheduledTask { id, due, action });
+        id
+    }
+
+    /// Remove the tasks doing `action`. Returns how many were removed.
+    pub fn cancel(&mut self, action: &TaskAction) -> usize {
+        let before = self.tasks.len();
+        self.tasks.retain(|t| &t.action != action);
+        before - self.tasks.len()
+    }
+
+    /// The tasks due at `now`.
+    pub fn due_tasks(&self, now: i64) -> Vec<ScheduledTask> {
+        self.tasks
+            .iter()
+            .filter(|t| t.due <= now)
+            .cloned()
+            .collect()
+    }
+
+    /// Remove the tasks with the given ids.
+    pub fn remove_tasks(&mut self, ids: &[u64]) {
+        self.tasks.retain(|t| !ids.contains(&t.id));
+    }
+}
+
+impl ModActionKind {
+    /// Name of the kind of action.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ModActionKind::Warn => "warn",
+            ModActionKind::Kick => "kick",
+            ModActionKind::Ban { .. } => "ban",
+            ModActionKind::Unban => "unban",
+            ModActionKind::Mute => "mute",
+            ModActionKind::Unmute => "unmute",
+            ModActionKind::Purge { .. } => "purge",
+        }
+    }
+}
+
+impl RoleChangeSource {
+    /// Name of the source.
+    pub fn name(self) -> &'static str {
+        match self {
+            RoleChangeSource::Command => "command",
+            RoleChangeSource::Reaction => "reaction",
+            RoleChangeSource::Menu => "menu",
+        }
+    }
+}
+
+impl TaskAction {
+    /// Id of the guild the action happens in.
+    pub fn guild(&self) -> u64 {
+        match *self {
+            TaskAction::Unban { guild, .. }
+            | TaskAction::RemoveRole { guild, .. }
+            | TaskAction::AddRole { guild, .. }
+            | TaskAction::Remind { guild, .. } => guild,
+        }
+    }
+}
+
+impl GuildData {
+    /// Check if a command is enabled in a channel.
+    ///
+    /// `names` are the names the command is known by, from the most specific (the command
+    /// itself) to the least specific (its group). The first name with a channel override
+    /// decides; otherwise the command is enabled unless any name is disabled guild-wide.
+    pub fn is_enabled(&self, channel_id: ChannelId, names: &[&str]) -> bool {
+        if let Some(rules) = self.channels.get(&channel_id.0) {
+            for name in names {
+                if rules.enabled.contains(*name) {
+                    return true;
+                }
+                if rules.disabled.contains(*name) {
+                    return false;
+                }
+            }
+        }
+
+        !names.iter().any(|name| self.disabled.contains(*name))
+    }
+
+    /// Record `action` as a new case and return the numbered case.
+    pub fn add_case(&mut self, mut action: ModAction) -> ModAction {
+        self.last_case += 1;
+        action.id = self.last_case;
+        self.actions.push(action.clone());
+        action
+    }
+
+    /// Find a case by its number.
+    pub fn case(&self, id: u64) -> Option<&ModAction> {
+        self.actions.iter().find(|a| a.id == id)
+    }
+
+    /// Find a case by its number, to edit it.
+    pub fn case_mut(&mut self, id: u64) -> Option<&mut ModAction> {
+        self.actions.iter_mut().find(|a| a.id == id)
+    }
+
+    /// The warnings given to `user`, oldest first.
+    pub fn warnings(&self, user: u64) -> Vec<&ModAction> {
+        self.actions
+            .iter()
+            .filter(|a| a.kind == ModActionKind::Warn && a.target == Some(user))
+            .collect()
+    }
+
+    /// The role changes of `user`, oldest first.
+    pub fn role_history(&self, user: u64) -> Vec<&RoleChangeRecord> {
+        self.role_changes
+            .iter()
+            .filter(|c| c.user == user)
+            .collect()
+    }
+
+    /// The roles `user` gave themselves through the bot and did not remove since.
+    ///
+    /// Temporary roles are left out, since they were removed when they expired.
+    pub fn self_assigned_roles(&self, user: u64) -> Vec<u64> {
+        let mut roles = Vec::new();
+        for change in self.role_history(user) {
+            roles.retain(|r| !change.removed.contains(r) && !change.added.contains(r));
+            if change.duration.is_some() {
+                continue;
+            }
+            for &role in &change.added {
+                if !roles.contains(&role) {
+                    roles.push(role);
+                }
+            }
+        }
+        roles
+    }
+
+    /// Enable or disable `name`, guild-wide or only in `channel_id`.
+    pub fn set_enabled(&mut self, name: &str, channel_id: Option<ChannelId>, enabled: bool) {
+        match channel_id {
+            Some(channel_id) => {
+                let rules = self.channels.entry(channel_id.0).or_default();
+                if enabled {
+                    rules.disabled.remove(name);
+                    rules.enabled.insert(name.to_string());
+                } else {
+                    rules.enabled.remove(name);
+                    rules.disabled.insert(name.to_string());
+                }
+            },
+            None => {
+                if enabled {
+                    self.disabled.remove(name);
+                } else {
+                    self.disabled.insert(name.to_string());
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,582 @@
+use std::collections::BTreeMap;
+
+use serenity::{
+    async_trait,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{
+        channel::Message,
+        id::{GuildId, UserId},
+    },
+    prelude::*,
+    utils::Colour as Color,
+};
+
+use time::OffsetDateTime;
+
+use crate::{
+    admin, config,
+    module::Module,
+    paginator::Pages,
+    reply::Reply,
+    storage::Tag,
+    types::{CommandGroups, StorageContainer},
+};
+
+/// Longest tag name.
+const NAME_LIMIT: usize = 32;
+/// Longest tag content, the length of a message.
+const CONTENT_LIMIT: usize = 2000;
+/// Number of tags per page of `tag list`.
+const LIST_PAGE_SIZE: usize = 20;
+
+#[group]
+#[commands(tag)]
+struct Tags;
+
+/// Texts saved per guild and sent back by name.
+///
+/// With the `commands` setting, on by default, a tag can also be sent with its name as a
+/// command, like `!faq`.
+pub struct TagsModule;
+
+#[async_trait]
+impl Module for TagsModule {
+    fn name(&self) -> &'static str {
+        "tags"
+    }
+
+    fn groups(&self) -> Vec<&'static CommandGroup> {
+        vec![&TAGS_GROUP]
+    }
+
+    fn check_config(&self, settings: &toml::value::Table) -> Result<(), String> {
+        match settings.get("commands") {
+            Some(value) if !value.is_bool() => Err("`commands` must be true or false".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn unknown_command(&self, ctx: &Context, msg: &Message, name: &str) {
+        let config = config::current(ctx).await;
+        let commands = config
+            .module_settings("tags")
+            .and_then(|s| s.get("commands"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(true);
+
+        // A tag sent as a command is disabled with the `tag` command
+        if !admin::is_enabled(ctx, msg, "tag").await {
+            return;
+        }
+        if let (true, Some(guild_id)) = (commands, msg.guild_id) {
+            if let Err(why) = send_tag(ctx, msg, guild_id, name).await {
+                eprintln!("Failed to send the tag {}: {}", name, why);
+            }
+        }
+    }
+}
+
+/// Send a tag of the guild.
+///
+/// Use the subcommands to manage the tags.
+#[command]
+#[only_in(guild)]
+#[sub_commands(create, edit, delete, list, info)]
+#[usage = "tag <NAME>` or `tag create <NAME> <CONTENT>` or `tag edit <NAME> <CONTENT>` or `tag \
+           delete <NAME>` or `tag list` or `tag info <NAME>"]
+async fn tag(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    if args.is_empty() {
+        return Reply::embed(
+            " ",
+            Color::RED,
+            "Wrong usage of command.\n\nUsage: `tag <NAME>` or `tag create <NAME> <CONTENT>` or \
+             `tag edit <NAME> <CONTENT>` or `tag delete <NAME>` or `tag list` or `tag info \
+             <NAME>`\n\nFor more information do `help tag`",
+        )
+        .send(ctx, msg)
+        .await;
+    }
+
+    let name = args.single::<String>().unwrap_or_default();
+    if !send_tag(ctx, msg, guild_id, &name).await? {
+        Reply::embed(" ", Color::RED, format!("There is no tag `{}`.", name))
+            .send(ctx, msg)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Save a text as a tag. Names are case insensitive.
+#[command]
+#[only_in(guild)]
+#[aliases(criar)]
+#[min_args(2)]
+#[usage = "tag create <NAME> <CONTENT>"]
+#[example = "tag create faq Read the pinned messages first."]
+async fn create(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let name = args.single::<String>().unwrap_or_default();
+    let content = args.rest().trim().to_string();
+    let reserved = match ctx.data.read().await.get::<CommandGroups>() {
+        Some(groups) => groups.contains_key(&name.to_lowercase()),
+        None => false,
+    };
+
+    let owner = msg.author.id.0;
+    change_tags(ctx, msg, |tags| {
+        let name = check_name(&name, reserved)?;
+        check_content(&content)?;
+        if tags.contains_key(&name) {
+            return Err(format!("The tag `{}` already exists.", name));
+        }
+
+        tags.insert(name.clone(), Tag {
+            content,
+            owner,
+            uses: 0,
+            created: OffsetDateTime::now_utc().unix_timestamp(),
+        });
+        Ok(format!("Created the tag `{}`.", name))
+    })
+    .await
+}
+
+/// Replace the text of a tag.
+///
+/// Only its owner and members who can manage messages can edit it.
+#[command]
+#[only_in(guild)]
+#[aliases(editar)]
+#[min_args(2)]
+#[usage = "tag edit <NAME> <CONTENT>"]
+async fn edit(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let name = args.single::<String>().unwrap_or_default().to_lowercase();
+    let content = args.rest().trim().to_string();
+    let moderator = can_manage_messages(ctx, msg).await;
+
+    let user_id = msg.author.id;
+    change_tags(ctx, msg, |tags| {
+        check_content(&content)?;
+        let tag = owned_tag(tags, &name, user_id, moderator)?;
+        tag.content = content;
+        Ok(format!("Edited the tag `{}`.", name))
+    })
+    .await
+}
+
+/// Delete a tag.
+///
+/// Only its owner and members who can manage messages can delete it.
+#[command]
+#[only_in(guild)]
+#[aliases(del, apagar)]
+#[num_args(1)]
+#[usage = "tag delete <NAME>"]
+async fn delete(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let name = args.single::<String>().unwrap_or_default().to_lowercase();
+    let moderator = can_manage_messages(ctx, msg).await;
+
+    let user_id = msg.author.id;
+    change_tags(ctx, msg, |tags| {
+        owned_tag(tags, &name, user_id, moderator)?;
+        tags.remove(&name);
+        Ok(format!("Deleted the tag `{}`.", name))
+    })
+    .await
+}
+
+/// List the tags of the guild and how many times they were used.
+#[command]
+#[only_in(guild)]
+#[aliases(listar)]
+#[num_args(0)]
+#[usage = "tag list"]
+async fn list(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let tags = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(storage) => storage
+            .read()
+            .await
+            .guild(guild_id)
+            .map(|g| g.tags.clone())
+            .unwrap_or_default(),
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    list_pages(&tags).send(ctx, msg).await
+}
+
+/// Show who owns a tag, when it was created and how many times it was used.
+#[command]
+#[only_in(guild)]
+#[num_args(1)]
+#[usage = "tag info <NAME>"]
+async fn info(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    let name = args.single::<String>().unwrap_or_default().to_lowercase();
+
+    let tag = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(storage) => storage
+            .read()
+            .await
+            .guild(guild_id)
+            .and_then(|g| g.tags.get(&name).cloned()),
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    let reply = match tag {
+        Some(tag) => info_reply(&name, &tag),
+        None => Reply::embed(" ", Color::RED, format!("There is no tag `{}`.", name)),
+    };
+    reply.send(ctx, msg).await
+}
+
+/// Send the tag called `name` in the channel of `msg` and count the use.
+///
+/// The uses are saved with the next save of the storage, at the latest on the next tick
+/// of the scheduler. Returns `false` if the guild has no such tag. Mentions in the tag
+/// don't ping anyone.
+async fn send_tag(
+    ctx: &Context, msg: &Message, guild_id: GuildId, name: &str,
+) -> CommandResult<bool> {
+    let storage = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(s) => s.clone(),
+        None => return Ok(false),
+    };
+
+    let name = name.to_lowercase();
+    let content = {
+        let mut storage = storage.write().await;
+        let content = match storage.guilds.get_mut(&guild_id.0) {
+            Some(guild) => guild.tags.get_mut(&name).map(|tag| {
+                tag.uses += 1;
+                tag.content.clone()
+            }),
+            None => None,
+        };
+        match content {
+            Some(content) => {
+                storage.defer_save();
+                content
+            },
+            None => return Ok(false),
+        }
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.content(content).allowed_mentions(|a| a.empty_parse())
+        })
+        .await?;
+
+    Ok(true)
+}
+
+/// Apply `change` to the tags of the guild and reply with the text it returns.
+///
+/// An error from `change` is the reply instead, and nothing is saved.
+async fn change_tags(
+    ctx: &Context, msg: &Message,
+    change: impl FnOnce(&mut BTreeMap<String, Tag>) -> Result<String, String>,
+) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let storage = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(s) => s.clone(),
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    let result = {
+        let mut storage = storage.write().await;
+        let mut tags = storage
+            .guild(guild_id)
+            .map(|g| g.tags.clone())
+            .unwrap_or_default();
+        let result = change(&mut tags);
+        if result.is_ok() {
+            storage.guild_mut(guild_id).tags = tags;
+            storage.save()?;
+        }
+        result
+    };
+
+    let reply = match result {
+        Ok(text) => Reply::embed("TAGS", Color::TEAL, text),
+        Err(why) => Reply::embed(" ", Color::RED, why),
+    };
+    reply.send(ctx, msg).await
+}
+
+/// Check if the author of `msg` can manage messages in the guild.
+async fn can_manage_messages(ctx: &Context, msg: &Message) -> bool {
+    match msg.guild(&ctx.cache).await {
+        Some(guild) => guild
+            .member_permissions(ctx, msg.author.id)
+            .await
+            .map_or(false, |p| p.manage_messages()),
+        None => false,
+    }
+}
+
+/// The tag called `name`, if `user_id` owns it or is a `moderator`.
+fn owned_tag<'a>(
+    tags: &'a mut BTreeMap<String, Tag>, name: &str, user_id: UserId, moderator: bool,
+) -> Result<&'a mut Tag, String> {
+    match tags.get_mut(name) {
+        Some(tag) if tag.owner == user_id.0 || moderator => Ok(tag),
+        Some(tag) => Err(format!(
+            "The tag `{}` belongs to <@{}>, only they or a moderator can change it.",
+            name, tag.owner
+        )),
+        None => Err(format!("There is no tag `{}`.", name)),
+    }
+}
+
+/// The lowercase tag name, if it is valid.
+///
+/// A `reserved` name is already used by a command.
+fn check_name(name: &str, reserved: bool) -> Result<String, String> {
+    let name = name.to_lowercase();
+    let subcommands = [
+        "create", "criar", "edit", "editar", "delete", "del", "apagar", "list", "listar", "info",
+    ];
+
+    if name.chars().count() > NAME_LIMIT {
+        Err(format!(
+            "Tag names can have at most {} characters.",
+            NAME_LIMIT
+        ))
+    } else if reserved || subcommands.contains(&name.as_str()) {
+        Err(format!("`{}` is already a command.", name))
+    } else {
+        Ok(name)
+    }
+}
+
+fn check_content(content: &str) -> Result<(), String> {
+    if content.is_empty() {
+        Err("A tag needs a text.".to_string())
+    } else if content.chars().count() > CONTENT_LIMIT {
+        Err(format!(
+            "Tags can have at most {} characters.",
+            CONTENT_LIMIT
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn list_pages(tags: &BTreeMap<String, Tag>) -> Pages {
+    let lines = if tags.is_empty() {
+        vec!["There are no tags yet, create one with `tag create <NAME> <CONTENT>`.".to_string()]
+    } else {
+        tags.iter()
+            .map(|(name, tag)| format!("`{}` ({} uses)", name, tag.uses))
+            .collect()
+    };
+
+    Pages::from_lines("TAGS", Color::BLUE, &lines, LIST_PAGE_SIZE)
+}
+
+fn info_reply(name: &str, tag: &Tag) -> Reply {
+    let mut reply = Reply::embed(format!("TAG {}", name.to_uppercase()), Color::BLUE, "");
+    if let Some(embed) = &mut reply.embed {
+        embed.fields = vec![
+            ("Owner".to_string(), format!("<@{}>", tag.owner), true),
+            ("Uses".to_string(), tag.uses.to_string(), true),
+            (
+                "Created".to_string(),
+                OffsetDateTime::from_unix_timestamp(tag.created)
+                    .format("%F %H:%M:%S UTC")
+                    .to_string(),
+                true,
+            ),
+        ];
+    }
+    reply
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        scheduler,
+        storage::Storage,
+        testing::{message, MockDiscord},
+    };
+
+    use super::*;
+
+    const GUILD: u64 = 30;
+    const CHANNEL: u64 = 20;
+
+    fn faq(owner: u64) -> Tag {
+        Tag {
+            content: "Read the pins.".to_string(),
+            owner,
+            uses: 3,
+            created: 0,
+        }
+    }
+
+    #[test]
+    fn tag_names_are_checked() {
+        assert_eq!(check_name("FAQ", false), Ok("faq".to_string()));
+        assert_eq!(
+            check_name("list", false),
+            Err("`list` is already a command.".to_string())
+        );
+        assert_eq!(
+            check_name("ping", true),
+            Err("`ping` is already a command.".to_string())
+        );
+        assert!(check_name(&"x".repeat(NAME_LIMIT + 1), false).is_err());
+    }
+
+    #[test]
+    fn only_owners_and_moderators_change_tags() {
+        let mut tags = BTreeMap::new();
+        tags.insert("faq".to_string(), faq(50));
+
+        assert!(owned_tag(&mut tags, "faq", UserId(50), false).is_ok());
+        assert!(owned_tag(&mut tags, "faq", UserId(51), true).is_ok());
+        assert_eq!(
+            owned_tag(&mut tags, "faq", UserId(51), false).err(),
+            Some(
+                "The tag `faq` belongs to <@50>, only they or a moderator can change it."
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            owned_tag(&mut tags, "rules", UserId(50), true).err(),
+            Some("There is no tag `rules`.".to_string())
+        );
+    }
+
+    #[test]
+    fn info_shows_the_owner_and_uses() {
+        let embed = info_reply("faq", &faq(50)).embed.unwrap();
+
+        assert_eq!(embed.title, "TAG FAQ");
+        assert_eq!(embed.fields[0].1, "<@50>");
+        assert_eq!(embed.fields[1].1, "3");
+        assert_eq!(embed.fields[2].1, "1970-01-01 00:00:00 UTC");
+    }
+
+    #[tokio::test]
+    async fn created_tags_are_sent_and_counted() {
+        let mock = MockDiscord::start().await;
+        let msg = message(
+            "!tag create FAQ Read the pins, @everyone.",
+            10,
+            Some(GUILD),
+            CHANNEL,
+        );
+        mock.send(&msg).await.unwrap();
+
+        let msg = message("!tag faq", 11, Some(GUILD), CHANNEL);
+        mock.send(&msg).await.unwrap();
+
+        let sent = mock.sent_messages();
+        assert_eq!(sent[1]["content"], "Read the pins, @everyone.");
+        assert_eq!(sent[1]["allowed_mentions"]["parse"], serde_json::json!([]));
+
+        let data = mock.ctx.data.read().await;
+        let storage = data.get::<StorageContainer>().unwrap().read().await;
+        let tag = &storage.guild(GuildId(GUILD)).unwrap().tags["faq"];
+        assert_eq!((tag.owner, tag.uses), (10, 1));
+    }
+
+    #[tokio::test]
+    async fn tags_of_others_are_not_deleted() {
+        let mock = MockDiscord::start().await;
+        {
+            let data = mock.ctx.data.read().await;
+            let mut storage = data.get::<StorageContainer>().unwrap().write().await;
+            storage
+                .guild_mut(GuildId(GUILD))
+                .tags
+                .insert("faq".to_string(), faq(50));
+        }
+        let msg = message("!tag delete faq", 10, Some(GUILD), CHANNEL);
+
+        mock.send(&msg).await.unwrap();
+
+        assert_eq!(mock.sent_embeds()[0]["color"], Color::RED.0);
+        let data = mock.ctx.data.read().await;
+        let storage = data.get::<StorageContainer>().unwrap().read().await;
+        assert!(storage
+            .guild(GuildId(GUILD))
+            .unwrap()
+            .tags
+            .contains_key("faq"));
+    }
+
+    #[tokio::test]
+    async fn disabling_tag_disables_tags_sent_as_commands() {
+        let mock = MockDiscord::start().await;
+        let msg = message("!tag create faq Read the pins.", 10, Some(GUILD), CHANNEL);
+        mock.send(&msg).await.unwrap();
+        let msg = message("!command disable tag", 10, Some(GUILD), CHANNEL);
+        mock.send(&msg).await.unwrap();
+        let sent = mock.sent_messages().len();
+
+        mock.send(&message("!faq", 11, Some(GUILD), CHANNEL))
+            .await
+            .unwrap();
+
+        assert_eq!(mock.sent_messages().len(), sent);
+    }
+
+    #[tokio::test]
+    async fn uses_are_saved_later() {
+        let mock = MockDiscord::start().await;
+        let msg = message("!tag create faq Read the pins.", 10, Some(GUILD), CHANNEL);
+        mock.send(&msg).await.unwrap();
+        let saved_uses = |storage: Storage| storage.guild(GuildId(GUILD)).unwrap().tags["faq"].uses;
+
+        mock.send(&message("!faq", 11, Some(GUILD), CHANNEL))
+            .await
+            .unwrap();
+        assert_eq!(mock.sent_messages()[1]["content"], "Read the pins.");
+        assert_eq!(saved_uses(mock.saved_storage()), 0);
+
+        scheduler::save_unsaved(&mock.ctx.data).await;
+        assert_eq!(saved_uses(mock.saved_storage()), 1);
+    }
+}
@@ -0,0 +1,925 @@
+//! Offline test harness.
+//!
+//! [`MockDiscord`] serves a fake Discord REST API and a fake gateway on local ports, and
+//! connects a client with the OxiBot framework and handler to them, so commands can run
+//! without a network connection or a token.
+//!
+//! Tests send messages through the gateway with [`MockDiscord::send`], so they go through
+//! the framework like real ones: prefixes, the `before` hook, checks, required
+//! permissions, argument counts and buckets. Every REST request is recorded so tests can
+//! assert on the messages sent, the reactions added and the member edits made by a
+//! command, and so is every payload the client sends to the gateway besides the
+//! handshake and the heartbeats.
+
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex as StdMutex,
+    },
+    time::Duration,
+};
+
+use async_tungstenite::{tokio::accept_async, tungstenite::Message as WsMessage};
+use futures::{
+    channel::{mpsc, oneshot},
+    SinkExt, StreamExt,
+};
+use serde_json::{json, Value};
+use serenity::{
+    async_trait,
+    client::ClientBuilder,
+    framework::standard::{macros::hook, CommandResult, DispatchError},
+    http::HttpBuilder,
+    model::{
+        channel::Message,
+        event::{GuildCreateEvent, GuildMemberAddEvent, GuildRoleUpdateEvent, MessageUpdateEvent},
+        gateway::Ready,
+        guild::Member,
+        id::{ChannelId, GuildId, MessageId},
+        interactions::Interaction,
+        permissions::Permissions,
+        user::User,
+    },
+    prelude::*,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+
+use crate::{config::Config, module::ModuleRegistry, storage::Storage, types::OxiHandler};
+
+/// Id of the bot user in the fake API.
+pub const BOT_ID: u64 = 1;
+
+/// Id of every direct message channel in the fake API.
+pub const DM_CHANNEL: u64 = 500;
+
+/// How long [`MockDiscord::send`] waits for the framework to handle a message.
+const DISPATCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A request received by the fake API.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    /// Path after the API version, like `/channels/10/messages`.
+    pub path:   String,
+    /// JSON body, or `Value::Null` if there was none.
+    pub body:   Value,
+    /// Audit log reason, still percent-encoded.
+    pub reason: Option<String>,
+}
+
+/// Outcome of a message handled by the framework, sent by the hooks of the harness.
+struct Outcomes;
+
+impl TypeMapKey for Outcomes {
+    type Value = mpsc::UnboundedSender<Result<(), String>>;
+}
+
+/// A fake Discord API and gateway, and a client connected to them.
+pub struct MockDiscord {
+    /// Context of the connected shard, sharing the data and cache of the client.
+    pub ctx:      Context,
+    requests:     Arc<StdMutex<Vec<Request>>>,
+    events:       mpsc::UnboundedSender<(String, Value)>,
+    received:     Arc<StdMutex<Vec<Value>>>,
+    outcomes:     Mutex<mpsc::UnboundedReceiver<Result<(), String>>>,
+    storage_path: PathBuf,
+}
+
+impl MockDiscord {
+    /// Start the fake API and gateway and connect a client with the builtin modules, the
+    /// default configuration and an empty storage in a temporary file.
+    ///
+    /// Returns once the client got the ready event. The framework has no owners, owner
+    /// commands rely on the [`PrimaryOwners`] inserted by the test.
+    ///
+    /// [`PrimaryOwners`]: crate::types::PrimaryOwners
+    pub async fn start() -> Self {
+        let api = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind the mock API");
+        let api_addr = api
+            .local_addr()
+            .expect("Failed to get the mock API address");
+        let gateway = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind the mock gateway");
+        let gateway_url = format!(
+            "ws://{}",
+            gateway
+                .local_addr()
+                .expect("Failed to get the mock gateway address")
+        );
+
+        let requests = Arc::new(StdMutex::new(Vec::new()));
+        tokio::spawn(serve(api, Arc::clone(&requests), gateway_url));
+
+        let (events, events_rx) = mpsc::unbounded();
+        let received = Arc::new(StdMutex::new(Vec::new()));
+        tokio::spawn(serve_gateway(gateway, events_rx, Arc::clone(&received)));
+
+        // The proxy is only used without the ratelimiter, which would call Discord
+        let http = HttpBuilder::new("mock-token")
+            .application_id(BOT_ID)
+            .ratelimiter_disabled(true)
+            .proxy(format!("http://{}/", api_addr))
+            .expect("Invalid mock API address")
+            .await
+            .expect("Failed to build the mock Http");
+
+        let config = Config::default();
+        let modules = ModuleRegistry::builtin().enabled(&config);
+        let groups = modules.groups();
+
+        let framework = crate::framework(HashSet::new(), &groups)
+            .before(before)
+            .after(after)
+            .on_dispatch_error(dispatch_error)
+            .unrecognised_command(unknown_command);
+
+        let (ready, ready_rx) = oneshot::channel();
+        let mut client = ClientBuilder::new_with_http(http)
+            .intents(modules.intents())
+            .event_handler(MockHandler {
+                ready: StdMutex::new(Some(ready)),
+            })
+            .framework(framework)
+            .await
+            .expect("Failed to build the mock client");
+
+        let storage_path = temp_path();
+        let storage = Storage::load(&storage_path).expect("Failed to create the test storage");
+        crate::insert_data(&client, config, storage, modules, &groups, HashSet::new()).await;
+
+        let (outcomes_tx, outcomes) = mpsc::unbounded();
+        client.data.write().await.insert::<Outcomes>(outcomes_tx);
+
+        tokio::spawn(async move {
+            if let Err(why) = client.start().await {
+                eprintln!("Mock client error: {:?}", why);
+            }
+        });
+
+        let ctx = timeout(DISPATCH_TIMEOUT, ready_rx)
+            .await
+            .expect("The mock client did not connect in time")
+            .expect("The mock client stopped before it was ready");
+
+        // Only keep the requests made by the tests
+        requests.lock().unwrap().clear();
+
+        Self {
+            ctx,
+            requests,
+            events,
+            received,
+            outcomes: Mutex::new(outcomes),
+            storage_path,
+        }
+    }
+
+    /// Put a guild with a text channel and the given `(id, name)` roles in the cache.
+    pub async fn add_guild(&self, guild_id: u64, channel_id: u64, roles: &[(u64, &str)]) {
+        let mut event: GuildCreateEvent =
+            serde_json::from_value(guild_json(guild_id, channel_id, roles))
+                .expect("Invalid mock guild");
+        self.ctx.cache.update(&mut event).await;
+    }
+
+    /// Put a member with the given roles in a cached guild.
+    pub async fn add_member(&self, guild_id: u64, user_id: u64, roles: &[u64]) {
+        let roles = roles.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        let mut event: GuildMemberAddEvent = serde_json::from_value(member_json(
+            &guild_id.to_string(),
+            &user_id.to_string(),
+            &json!(roles),
+        ))
+        .expect("Invalid mock member");
+        self.ctx.cache.update(&mut event).await;
+    }
+
+    /// Insert a value in the data of the context.
+    pub async fn insert<K: TypeMapKey>(&self, value: K::Value) {
+        self.ctx.data.write().await.insert::<K>(value);
+    }
+
+    /// Send `msg` through the gateway and wait for the framework to handle it.
+    ///
+    /// Returns the result of the command, or an error if the framework refused to run it:
+    /// a failed check, missing permissions, a wrong number of arguments or the `before`
+    /// hook. Messages that are not commands are handled by the modules, like the tags.
+    pub async fn send(&self, msg: &Message) -> CommandResult {
+        let message = serde_json::to_value(msg).expect("Invalid mock message");
+        self.dispatch("MESSAGE_CREATE", message);
+
+        let mut outcomes = self.outcomes.lock().await;
+        match timeout(DISPATCH_TIMEOUT, outcomes.next()).await {
+            Ok(Some(outcome)) => outcome.map_err(Into::into),
+            Ok(None) => Err("the mock client stopped".into()),
+            Err(_) => Err("the framework did not handle the message in time".into()),
+        }
+    }
+
+    /// Send a dispatch event of type `kind` through the gateway.
+    ///
+    /// The event is handled after the events sent before it, but this does not wait for
+    /// it.
+    pub fn dispatch(&self, kind: &str, data: Value) {
+        self.events
+            .unbounded_send((kind.to_string(), data))
+            .expect("The mock gateway stopped");
+    }
+
+    /// Payloads sent to the gateway so far, besides the handshake and the heartbeats.
+    pub fn gateway_messages(&self) -> Vec<Value> {
+        self.received.lock().unwrap().clone()
+    }
+
+    /// Add `permissions` to a cached role of a cached guild.
+    pub async fn grant(&self, guild_id: u64, role_id: u64, permissions: Permissions) {
+        let role = self
+            .ctx
+            .cache
+            .role(guild_id, role_id)
+            .await
+            .expect("Unknown mock role");
+        let mut event: GuildRoleUpdateEvent = serde_json::from_value(json!({
+            "guild_id": guild_id.to_string(),
+            "role": role_json(
+                guild_id,
+                role_id,
+                &role.name,
+                role.position,
+                role.permissions | permissions
+            ),
+        }))
+        .expect("Invalid mock role");
+        self.ctx.cache.update(&mut event).await;
+    }
+
+    /// The storage as it was last saved.
+    pub fn saved_storage(&self) -> Storage {
+        Storage::load(&self.storage_path).expect("Failed to load the test storage")
+    }
+
+    /// Every request received so far.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Bodies of the messages sent so far.
+    pub fn sent_messages(&self) -> Vec<Value> {
+        self.requests()
+            .into_iter()
+            .filter(|r| r.method == "POST" && r.path.ends_with("/messages"))
+            .map(|r| r.body)
+            .collect()
+    }
+
+    /// Contents of the messages sent so far.
+    pub fn sent_contents(&self) -> Vec<String> {
+        self.sent_messages()
+            .iter()
+            .filter_map(|m| m["content"].as_str().map(String::from))
+            .collect()
+    }
+
+    /// Embeds of the messages sent so far.
+    pub fn sent_embeds(&self) -> Vec<Value> {
+        self.sent_messages()
+            .into_iter()
+            .filter_map(|m| match m.get("embeds").and_then(|e| e.get(0)) {
+                Some(e) => Some(e.clone()),
+                None => m.get("embed").cloned(),
+            })
+            .collect()
+    }
+
+    /// Emojis of the reactions added so far.
+    pub fn reactions(&self) -> Vec<String> {
+        self.requests()
+            .into_iter()
+            .filter(|r| r.method == "PUT" && r.path.contains("/reactions/"))
+            .filter_map(|r| {
+                r.path
+                    .split("/reactions/")
+                    .nth(1)
+                    .and_then(|emoji| emoji.split('/').next())
+                    .map(percent_decode)
+            })
+            .collect()
+    }
+
+    /// Bodies of the member edits made so far.
+    pub fn member_edits(&self) -> Vec<Value> {
+        self.requests()
+            .into_iter()
+            .filter(|r| r.method == "PATCH" && r.path.contains("/members/"))
+            .map(|r| r.body)
+            .collect()
+    }
+}
+
+impl Drop for MockDiscord {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.storage_path);
+    }
+}
+
+/// Hands the context of the ready event to [`MockDiscord::start`], and every other event
+/// to the OxiBot handler.
+struct MockHandler {
+    ready: StdMutex<Option<oneshot::Sender<Context>>>,
+}
+
+#[async_trait]
+impl EventHandler for MockHandler {
+    async fn ready(&self, ctx: Context, _ready: Ready) {
+        if let Some(ready) = self.ready.lock().unwrap().take() {
+            let _ = ready.send(ctx);
+        }
+    }
+
+    async fn message(&self, ctx: Context, msg: Message) {
+        OxiHandler.message(ctx, msg).await;
+    }
+
+    async fn message_update(
+        &self, ctx: Context, old: Option<Message>, new: Option<Message>, event: MessageUpdateEvent,
+    ) {
+        OxiHandler.message_update(ctx, old, new, event).await;
+    }
+
+    async fn message_delete(
+        &self, ctx: Context, channel_id: ChannelId, message_id: MessageId,
+        guild_id: Option<GuildId>,
+    ) {
+        OxiHandler
+            .message_delete(ctx, channel_id, message_id, guild_id)
+            .await;
+    }
+
+    async fn guild_member_addition(&self, ctx: Context, guild_id: GuildId, member: Member) {
+        OxiHandler
+            .guild_member_addition(ctx, guild_id, member)
+            .await;
+    }
+
+    async fn guild_member_removal(
+        &self, ctx: Context, guild_id: GuildId, user: User, member: Option<Member>,
+    ) {
+        OxiHandler
+            .guild_member_removal(ctx, guild_id, user, member)
+            .await;
+    }
+
+    async fn guild_member_update(&self, ctx: Context, old: Option<Member>, new: Member) {
+        OxiHandler.guild_member_update(ctx, old, new).await;
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        OxiHandler.interaction_create(ctx, interaction).await;
+    }
+}
+
+/// Tell [`MockDiscord::send`] how the framework handled a message.
+async fn report(ctx: &Context, outcome: Result<(), String>) {
+    if let Some(outcomes) = ctx.data.read().await.get::<Outcomes>() {
+        let _ = outcomes.unbounded_send(outcome);
+    }
+}
+
+#[hook]
+async fn before(ctx: &Context, msg: &Message, command_name: &str) -> bool {
+    let run = crate::before(ctx, msg, command_name).await;
+    if !run {
+        report(
+            ctx,
+            Err(format!("'{}' refused by the before hook", command_name)),
+        )
+        .await;
+    }
+
+    run
+}
+
+#[hook]
+async fn after(ctx: &Context, msg: &Message, command_name: &str, command_result: CommandResult) {
+    let outcome = command_result
+        .as_ref()
+        .map_err(ToString::to_string)
+        .map(|_| ());
+    crate::after(ctx, msg, command_name, command_result).await;
+    report(ctx, outcome).await;
+}
+
+#[hook]
+async fn dispatch_error(ctx: &Context, _msg: &Message, error: DispatchError) {
+    report(ctx, Err(format!("dispatch error: {:?}", error))).await;
+}
+
+#[hook]
+async fn unknown_command(ctx: &Context, msg: &Message, unknown_command_name: &str) {
+    crate::unknown_command(ctx, msg, unknown_command_name).await;
+    report(ctx, Ok(())).await;
+}
+
+/// Build a message as if the gateway sent it.
+pub fn message(content: &str, author_id: u64, guild_id: Option<u64>, channel_id: u64) -> Message {
+    let mut msg = json!({
+        "id": "100",
+        "channel_id": channel_id.to_string(),
+        "author": user_json(author_id),
+        "content": content,
+        "timestamp": "2021-01-01T00:00:00+00:00",
+        "edited_timestamp": null,
+        "tts": false,
+        "mention_everyone": false,
+        "mentions": [],
+        "mention_roles": [],
+        "mention_channels": [],
+        "attachments": [],
+        "embeds": [],
+        "reactions": [],
+        "pinned": false,
+        "type": 0,
+        "flags": 0,
+    });
+
+    if let Some(guild_id) = guild_id {
+        msg["guild_id"] = json!(guild_id.to_string());
+    }
+
+    serde_json::from_value(msg).expect("Invalid mock message")
+}
+
+/// Build a member as if the gateway sent it.
+pub fn member(guild_id: u64, user_id: u64) -> Member {
+    let member = member_json(&guild_id.to_string(), &user_id.to_string(), &json!([]));
+    serde_json::from_value(member).expect("Invalid mock member")
+}
+
+fn temp_path() -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    std::env::temp_dir().join(format!(
+        "oxibot-test-{}-{}.json",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    ))
+}
+
+async fn serve(listener: TcpListener, requests: Arc<StdMutex<Vec<Request>>>, gateway_url: String) {
+    while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(handle(stream, Arc::clone(&requests), gateway_url.clone()));
+    }
+}
+
+/// Answer the requests of a keep-alive connection until it is closed.
+async fn handle(stream: TcpStream, requests: Arc<StdMutex<Vec<Request>>>, gateway_url: String) {
+    let mut stream = BufReader::new(stream);
+
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+            return;
+        }
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let target = parts.next().unwrap_or_default().to_string();
+
+        let mut len = 0;
+        let mut reason = None;
+        loop {
+            let mut header = String::new();
+            if stream.read_line(&mut header).await.unwrap_or(0) == 0 {
+                return;
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            let (name, value) = match header.find(':') {
+                Some(i) => (header[..i].to_ascii_lowercase(), header[i + 1..].trim()),
+                None => continue,
+            };
+            match name.as_str() {
+                "content-length" => len = value.parse().unwrap_or(0),
+                "x-audit-log-reason" => reason = Some(value.to_string()),
+                _ => {},
+            }
+        }
+
+        let mut body = vec![0; len];
+        if stream.read_exact(&mut body).await.is_err() {
+            return;
+        }
+
+        let request = Request {
+            method,
+            path: api_path(&target),
+            body: serde_json::from_slice(&body).unwrap_or(Value::Null),
+            reason,
+        };
+        let (status, response) = {
+            let mut requests = requests.lock().unwrap();
+            let answer = respond(&request, &requests, &gateway_url);
+            requests.push(request);
+            answer
+        };
+
+        let response = response.map(|v| v.to_string()).unwrap_or_default();
+        let head = format!(
+            "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n",
+            status,
+            response.len()
+        );
+
+        let out = stream.get_mut();
+        if out.write_all(head.as_bytes()).await.is_err()
+            || out.write_all(response.as_bytes()).await.is_err()
+        {
+            return;
+        }
+    }
+}
+
+/// Accept the connections of the client to the gateway, one after the other.
+async fn serve_gateway(
+    listener: TcpListener, mut events: mpsc::UnboundedReceiver<(String, Value)>,
+    received: Arc<StdMutex<Vec<Value>>>,
+) {
+    while let Ok((stream, _)) = listener.accept().await {
+        if let Ok(socket) = accept_async(stream).await {
+            gateway_session(socket, &mut events, &received).await;
+        }
+    }
+}
+
+/// Run one gateway session: the hello, the ready event after the identify, the heartbeat
+/// acks, then every event sent by the test.
+async fn gateway_session<S>(
+    socket: S, events: &mut mpsc::UnboundedReceiver<(String, Value)>,
+    received: &StdMutex<Vec<Value>>,
+) where
+    S: futures::Stream<Item = Result<WsMessage, async_tungstenite::tungstenite::Error>>
+        + futures::Sink<WsMessage>
+        + Unpin,
+{
+    let (mut sink, mut stream) = socket.split();
+    let mut seq = 0;
+
+    let hello = json!({"op": 10, "d": {"heartbeat_interval": 45000}});
+    if sink.send(WsMessage::Text(hello.to_string())).await.is_err() {
+        return;
+    }
+
+    loop {
+        let payload = tokio::select! {
+            message = stream.next() => {
+                let payload = match message {
+                    Some(Ok(WsMessage::Text(text))) => {
+                        serde_json::from_str::<Value>(&text).unwrap_or(Value::Null)
+                    },
+                    Some(Ok(_)) => continue,
+                    _ => return,
+                };
+                match payload["op"].as_u64() {
+                    Some(1) => json!({"op": 11}),
+                    Some(2) => {
+                        seq += 1;
+                        json!({"op": 0, "s": seq, "t": "READY", "d": ready_json()})
+                    },
+                    _ => {
+                        received.lock().unwrap().push(payload);
+                        continue;
+                    },
+                }
+            },
+            event = events.next() => match event {
+                Some((kind, data)) => {
+                    seq += 1;
+                    json!({"op": 0, "s": seq, "t": kind, "d": data})
+                },
+                None => return,
+            },
+        };
+
+        if sink
+            .send(WsMessage::Text(payload.to_string()))
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+/// Strip the `/api/vN` prefix and the query from a request target.
+fn api_path(target: &str) -> String {
+    let path = target.split('?').next().unwrap_or_default();
+    let mut segments = path.split('/').filter(|s| !s.is_empty()).peekable();
+
+    if segments.peek() == Some(&"api") {
+        segments.next();
+        if segments.peek().map_or(false, |s| s.starts_with('v')) {
+            segments.next();
+        }
+    }
+
+    format!("/{}", segments.collect::<Vec<_>>().join("/"))
+}
+
+/// The canned answer of the fake API to a request, given the requests before it.
+fn respond(
+    request: &Request, before: &[Request], gateway_url: &str,
+) -> (&'static str, Option<Value>) {
+    let segments = request.path.split('/').skip(1).collect::<Vec<_>>();
+
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["gateway"]) => ("200 OK", Some(json!({ "url": gateway_url }))),
+        ("GET", ["gateway", "bot"]) => {
+            let gateway = json!({
+                "url": gateway_url,
+                "shards": 1,
+                "session_start_limit": {"total": 1000, "remaining": 1000, "reset_after": 0},
+            });
+            ("200 OK", Some(gateway))
+        },
+        ("POST", ["channels", channel_id, "messages"]) => {
+            let mut msg = message_json(channel_id, &request.body);
+            if let Some(guild_id) = request.body.get("guild_id") {
+                msg["guild_id"] = guild_id.clone();
+            }
+            ("200 OK", Some(msg))
+        },
+        ("PATCH", ["channels", channel_id, "messages", _]) => {
+            ("200 OK", Some(message_json(channel_id, &request.body)))
+        },
+        ("GET", ["guilds", guild_id, "members", user_id]) => {
+            // A member keeps the roles of its last edit
+            let roles = before
+                .iter()
+                .rev()
+                .find(|r| r.method == "PATCH" && r.path == request.path)
+                .and_then(|r| r.body.get("roles").cloned())
+                .unwrap_or_else(|| json!([]));
+            ("200 OK", Some(member_json(guild_id, user_id, &roles)))
+        },
+        ("PATCH", ["guilds", guild_id, "members", user_id]) => {
+            let roles = request
+                .body
+                .get("roles")
+                .cloned()
+                .unwrap_or_else(|| json!([]));
+            ("200 OK", Some(member_json(guild_id, user_id, &roles)))
+        },
+        ("POST", ["guilds", guild_id, "roles"]) => {
+            let role = json!({
+                "id": "900",
+                "guild_id": guild_id,
+                "name": request.body["name"].as_str().unwrap_or_default(),
+                "color": request.body["color"].as_u64().unwrap_or_default(),
+                "hoist": request.body["hoist"].as_bool().unwrap_or_default(),
+                "managed": false,
+                "mentionable": request.body["mentionable"].as_bool().unwrap_or_default(),
+                "permissions": "0",
+                "position": 1,
+            });
+            ("200 OK", Some(role))
+        },
+        ("POST", ["users", "@me", "channels"]) => {
+            let recipient = request.body["recipient_id"]
+                .as_str()
+                .and_then(|id| id.parse().ok())
+                .or_else(|| request.body["recipient_id"].as_u64())
+                .unwrap_or_default();
+            let channel = json!({
+                "id": DM_CHANNEL.to_string(),
+                "type": 1,
+                "last_message_id": null,
+                "recipients": [user_json(recipient)],
+            });
+            ("200 OK", Some(channel))
+        },
+        ("GET", ["channels", _, "messages"]) => ("200 OK", Some(json!([]))),
+        ("PUT", ["channels", _, "messages", _, "reactions", ..])
+        | ("DELETE", ["channels", _, "messages", _, "reactions", ..])
+        | ("DELETE", ["channels", _, "messages", _])
+        | ("POST", ["channels", _, "messages", "bulk-delete"])
+        | ("PUT", ["guilds", _, "members", _, "roles", _])
+        | ("DELETE", ["guilds", _, "members", _, "roles", _])
+        | ("DELETE", ["guilds", _, "members", _])
+        | ("PUT", ["guilds", _, "bans", _])
+        | ("DELETE", ["guilds", _, "bans", _]) => ("204 No Content", None),
+        _ => (
+            "404 Not Found",
+            Some(json!({ "code": 10000, "message": "Unknown route in the mock API" })),
+        ),
+    }
+}
+
+/// The message `200` sent by the bot in `channel_id`, with the content of `body`.
+fn message_json(channel_id: &str, body: &Value) -> Value {
+    json!({
+        "id": "200",
+        "channel_id": channel_id,
+        "author": user_json(BOT_ID),
+        "content": body["content"].as_str().unwrap_or_default(),
+        "timestamp": "2021-01-01T00:00:00+00:00",
+        "edited_timestamp": null,
+        "tts": false,
+        "mention_everyone": false,
+        "mentions": [],
+        "mention_roles": [],
+        "attachments": [],
+        "embeds": [],
+        "pinned": false,
+        "type": 0,
+    })
+}
+
+fn ready_json() -> Value {
+    let mut user = user_json(BOT_ID);
+    user["mfa_enabled"] = json!(false);
+
+    json!({
+        "v": 8,
+        "user": user,
+        "guilds": [],
+        "session_id": "mock-session",
+        "shard": [0, 1],
+        "application": {"id": BOT_ID.to_string(), "flags": 0},
+    })
+}
+
+fn user_json(id: u64) -> Value {
+    json!({
+        "id": id.to_string(),
+        "username": format!("user{}", id),
+        "discriminator": "0001",
+        "avatar": null,
+        "bot": id == BOT_ID,
+        "public_flags": 0,
+    })
+}
+
+fn member_json(guild_id: &str, user_id: &str, roles: &Value) -> Value {
+    json!({
+        "guild_id": guild_id,
+        "user": user_json(user_id.parse().unwrap_or_default()),
+        "roles": roles,
+        "nick": null,
+        "joined_at": "2021-01-01T00:00:00+00:00",
+        "deaf": false,
+        "mute": false,
+        "pending": false,
+        "premium_since": null,
+    })
+}
+
+fn role_json(
+    guild_id: u64, role_id: u64, name: &str, position: i64, permissions: Permissions,
+) -> Value {
+    json!({
+        "id": role_id.to_string(),
+        "guild_id": guild_id.to_string(),
+        "name": name,
+        "color": 0,
+        "hoist": false,
+        "managed": false,
+        "mentionable": false,
+        "permissions": permissions.bits().to_string(),
+        "position": position,
+    })
+}
+
+fn guild_json(guild_id: u64, channel_id: u64, roles: &[(u64, &str)]) -> Value {
+    let mut roles = roles
+        .iter()
+        .zip(1..)
+        .map(|(&(id, name), position)| {
+            role_json(guild_id, id, name, position, Permissions::empty())
+        })
+        .collect::<Vec<_>>();
+    roles.push(role_json(
+        guild_id,
+        guild_id,
+        "@everyone",
+        0,
+        Permissions::READ_MESSAGES | Permissions::SEND_MESSAGES,
+    ));
+
+    json!({
+        "id": guild_id.to_string(),
+        "name": "Mock guild",
+        "nsfw": false,
+        "icon": null,
+        "splash": null,
+        "discovery_splash": null,
+        "owner_id": "2",
+        "region": "us-east",
+        "afk_channel_id": null,
+        "afk_timeout": 300,
+        "verification_level": 0,
+        "default_message_notifications": 0,
+        "explicit_content_filter": 0,
+        "roles": roles,
+        "emojis": [],
+        "features": [],
+        "mfa_level": 0,
+        "application_id": null,
+        "system_channel_id": null,
+        "system_channel_flags": 0,
+        "rules_channel_id": null,
+        "joined_at": "2021-01-01T00:00:00+00:00",
+        "large": false,
+        "member_count": 1,
+        "voice_states": [],
+        "members": [],
+        "channels": [{
+            "id": channel_id.to_string(),
+            "guild_id": guild_id.to_string(),
+            "type": 0,
+            "name": "general",
+            "position": 0,
+            "permission_overwrites": [],
+            "nsfw": false,
+            "topic": null,
+            "last_message_id": null,
+            "parent_id": null,
+            "rate_limit_per_user": 0,
+        }],
+        "threads": [],
+        "presences": [],
+        "stage_instances": [],
+        "stickers": [],
+        "description": null,
+        "premium_tier": 0,
+        "premium_subscription_count": 0,
+        "banner": null,
+        "vanity_url_code": null,
+        "preferred_locale": "en-US",
+        "public_updates_channel_id": null,
+        "nsfw_level": 0,
+    })
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        // An escape needs two hex digits, which may end the string
+        if bytes[i] == b'%' && i + 3 <= bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+            if let Ok(b) = u8::from_str_radix(hex, 16) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use serenity::model::gateway::Activity;
+    use tokio::time::sleep;
+
+    use super::*;
+
+    #[test]
+    fn percent_decode_reads_every_escape() {
+        assert_eq!(percent_decode("too%20spammy%21"), "too spammy!");
+        assert_eq!(percent_decode("%41"), "A");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%4"), "%4");
+        assert_eq!(percent_decode("%zz%é"), "%zz%é");
+    }
+
+    #[tokio::test]
+    async fn presence_updates_reach_the_gateway() {
+        let mock = MockDiscord::start().await;
+
+        mock.ctx.set_activity(Activity::playing("tests")).await;
+
+        let start = Instant::now();
+        while mock.gateway_messages().is_empty() && start.elapsed() < DISPATCH_TIMEOUT {
+            sleep(Duration::from_millis(10)).await;
+        }
+        let messages = mock.gateway_messages();
+        assert_eq!(messages[0]["op"], 3);
+        assert_eq!(messages[0]["d"]["game"]["name"], "tests");
+    }
+}
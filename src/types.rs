@@ -1,26 +1,110 @@
-use std::sync::Arc;
+use std::sync::{atomic::AtomicBool, Arc};
 
 use serenity::{
-    async_trait, client::bridge::gateway::ShardManager, model::gateway::Ready, prelude::*,
+    async_trait,
+    client::bridge::gateway::ShardManager,
+    model::{
+        channel::Message,
+        event::MessageUpdateEvent,
+        gateway::Ready,
+        guild::Member,
+        id::{ChannelId, GuildId, MessageId},
+        interactions::Interaction,
+        user::User,
+    },
+    prelude::*,
 };
 
 use time::Instant;
 
+use crate::{config::Config, eventlog::MessageCache, module::ModuleRegistry, storage::Storage};
+
 /// OxiBot event handler
 pub struct OxiHandler;
 
 #[async_trait]
 impl EventHandler for OxiHandler {
     #[inline]
-    async fn ready(&self, _ctx: Context, ready: Ready) {
+    async fn ready(&self, ctx: Context, ready: Ready) {
         // Reset every time it reconnects
         // SAFETY: safe because it's the only other place where we mutate the `static mut`
         unsafe { *crate::UPTIME = Instant::now() };
 
-        println!("{} is connected!", ready.user.name)
+        println!("{} is connected!", ready.user.name);
+
+        crate::scheduler::start(&ctx).await;
+
+        for module in modules(&ctx).await.iter() {
+            module.ready(&ctx, &ready).await;
+        }
+    }
+
+    async fn message(&self, ctx: Context, msg: Message) {
+        for module in modules(&ctx).await.iter() {
+            module.message(&ctx, &msg).await;
+        }
+    }
+
+    async fn message_update(
+        &self, ctx: Context, old: Option<Message>, new: Option<Message>, event: MessageUpdateEvent,
+    ) {
+        for module in modules(&ctx).await.iter() {
+            module
+                .message_update(&ctx, old.as_ref(), new.as_ref(), &event)
+                .await;
+        }
+    }
+
+    async fn message_delete(
+        &self, ctx: Context, channel_id: ChannelId, message_id: MessageId,
+        guild_id: Option<GuildId>,
+    ) {
+        for module in modules(&ctx).await.iter() {
+            module
+                .message_delete(&ctx, channel_id, message_id, guild_id)
+                .await;
+        }
+    }
+
+    async fn guild_member_addition(&self, ctx: Context, guild_id: GuildId, member: Member) {
+        for module in modules(&ctx).await.iter() {
+            module.guild_member_addition(&ctx, guild_id, &member).await;
+        }
+    }
+
+    async fn guild_member_removal(
+        &self, ctx: Context, guild_id: GuildId, user: User, member: Option<Member>,
+    ) {
+        for module in modules(&ctx).await.iter() {
+            module
+                .guild_member_removal(&ctx, guild_id, &user, member.as_ref())
+                .await;
+        }
+    }
+
+    async fn guild_member_update(&self, ctx: Context, old: Option<Member>, new: Member) {
+        for module in modules(&ctx).await.iter() {
+            module.guild_member_update(&ctx, old.as_ref(), &new).await;
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        for module in modules(&ctx).await.iter() {
+            module.interaction_create(&ctx, &interaction).await;
+        }
     }
 }
 
+/// The loaded modules, cloned so no lock is held while they handle an event.
+async fn modules(ctx: &Context) -> ModuleRegistry {
+    ctx.data
+        .read()
+        .await
+        .get::<Modules>()
+        .cloned()
+        .unwrap_or_default()
+}
+
 // A container type is created for inserting into the Client's `data`, which
 // allows for data to be accessible across all events and framework commands, or
 // anywhere else that has a copy of the `data` Arc.
@@ -36,3 +120,60 @@ pub struct CommandCounter;
 impl TypeMapKey for CommandCounter {
     type Value = std::collections::HashMap<String, u64>;
 }
+
+// The bot configuration, replaced as a whole when it is reloaded
+pub struct ConfigContainer;
+
+impl TypeMapKey for ConfigContainer {
+    type Value = Arc<RwLock<Config>>;
+}
+
+// Set by the `restart` command so `main` re-executes the bot after shutdown
+pub struct RestartFlag;
+
+impl TypeMapKey for RestartFlag {
+    type Value = Arc<AtomicBool>;
+}
+
+// The persistent storage
+pub struct StorageContainer;
+
+impl TypeMapKey for StorageContainer {
+    type Value = Arc<RwLock<Storage>>;
+}
+
+// Maps every command name and alias to the names of its parent commands and groups, in
+// lowercase
+pub struct CommandGroups;
+
+impl TypeMapKey for CommandGroups {
+    type Value = std::collections::HashMap<String, Vec<String>>;
+}
+
+// The application owner or the accepted members of its team
+pub struct PrimaryOwners;
+
+impl TypeMapKey for PrimaryOwners {
+    type Value = std::collections::HashSet<serenity::model::id::UserId>;
+}
+
+// The modules loaded at startup
+pub struct Modules;
+
+impl TypeMapKey for Modules {
+    type Value = ModuleRegistry;
+}
+
+// The last messages sent in guilds, kept by the event log
+pub struct RecentMessages;
+
+impl TypeMapKey for RecentMessages {
+    type Value = Arc<RwLock<MessageCache>>;
+}
+
+// The loop running the scheduled tasks, started once on the first ready event
+pub struct SchedulerHandle;
+
+impl TypeMapKey for SchedulerHandle {
+    type Value = tokio::task::JoinHandle<()>;
+}
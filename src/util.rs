@@ -1,15 +1,35 @@
 use std::process::Command;
 
-use crate::types::ShardManagerContainer;
+use crate::{module::Module, reply::Reply, types::ShardManagerContainer};
 
 use serenity::{
     client::bridge::gateway::ShardId,
-    framework::standard::{macros::command, CommandResult},
+    framework::standard::{
+        macros::{command, group},
+        CommandGroup, CommandResult,
+    },
     model::channel::Message,
     prelude::*,
     utils::Colour as Color,
 };
 
+#[group]
+#[commands(latency, uname, uptime)]
+struct Util;
+
+/// Latency, uptime and system information commands.
+pub struct UtilModule;
+
+impl Module for UtilModule {
+    fn name(&self) -> &'static str {
+        "util"
+    }
+
+    fn groups(&self) -> Vec<&'static CommandGroup> {
+        vec![&UTIL_GROUP]
+    }
+}
+
 /// Calculates the shard latency.
 #[command]
 async fn latency(ctx: &Context, msg: &Message) -> CommandResult {
@@ -20,7 +40,9 @@ async fn latency(ctx: &Context, msg: &Message) -> CommandResult {
     let shard_manager = match data.get::<ShardManagerContainer>() {
         Some(v) => v,
         None => {
-            let _ = msg.reply(&ctx, "There was a problem getting the shard manager");
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the shard manager")
+                .await?;
 
             return Ok(());
         },
@@ -37,11 +59,7 @@ async fn latency(ctx: &Context, msg: &Message) -> CommandResult {
         None => {
             msg.channel_id
                 .send_message(&ctx.http, |m| {
-                    m.embed(|e| {
-                        e.title(" ")
-                            .color(Color::RED)
-                            .description(&"No shard found")
-                    })
+                    m.embed(|e| e.title(" ").color(Color::RED).description("No shard found"))
                 })
                 .await?;
 
@@ -59,7 +77,7 @@ async fn latency(ctx: &Context, msg: &Message) -> CommandResult {
             m.embed(|e| {
                 e.title(" ")
                     .color(Color::TEAL)
-                    .description(&format!("The shard latency is {}", latency))
+                    .description(format!("The shard latency is {}", latency))
             })
         })
         .await?;
@@ -72,40 +90,79 @@ async fn latency(ctx: &Context, msg: &Message) -> CommandResult {
 async fn uptime(ctx: &Context, msg: &Message) -> CommandResult {
     // SAFETY: safe because we are just borrowing from a `static mut`
     let time = unsafe { crate::UPTIME.elapsed().whole_seconds() };
-    let up_days = time / 86400;
-    let up_hours = (time - (up_days * 86400)) / 3600;
-    let up_min = (time - (up_days * 86400) - (up_hours * 3600)) / 60;
-    let up_sec = time - ((up_days * 86400) + (up_hours * 3600) + (up_min * 60));
-
-    msg.channel_id
-        .send_message(&ctx.http, |m| {
-            m.embed(|e| {
-                e.title("UPTIME").color(Color::RED).description(format!(
-                    "Up for {} days {} hours {} minutes {} seconds",
-                    up_days, up_hours, up_min, up_sec,
-                ))
-            })
-        })
-        .await?;
 
-    Ok(())
+    uptime_reply(time).send(ctx, msg).await
 }
 
 /// Shows the kernel the bot runs on!
 #[command]
 async fn uname(ctx: &Context, msg: &Message) -> CommandResult {
-    let uname = Command::new("uname").arg("-a").output();
-    let mut str = String::new();
-    match uname {
-        Ok(out) => str.push_str(&out.stdout.iter().map(|&c| c as char).collect::<String>()),
-        Err(why) => println!("Error calling uname: {:?}", why),
+    let uname = match Command::new("uname").arg("-a").output() {
+        Ok(out) => out.stdout,
+        Err(why) => {
+            println!("Error calling uname: {:?}", why);
+            Vec::new()
+        },
     };
 
-    msg.channel_id
-        .send_message(&ctx.http, |m| {
-            m.embed(|e| e.title(" ").color(Color::RED).description(&str))
-        })
-        .await?;
+    uname_reply(&uname).send(ctx, msg).await
+}
 
-    Ok(())
+/// Reply with the uptime, given in seconds.
+fn uptime_reply(time: i64) -> Reply {
+    let up_days = time / 86400;
+    let up_hours = (time - (up_days * 86400)) / 3600;
+    let up_min = (time - (up_days * 86400) - (up_hours * 3600)) / 60;
+    let up_sec = time - ((up_days * 86400) + (up_hours * 3600) + (up_min * 60));
+
+    Reply::embed(
+        "UPTIME",
+        Color::RED,
+        format!(
+            "Up for {} days {} hours {} minutes {} seconds",
+            up_days, up_hours, up_min, up_sec,
+        ),
+    )
+}
+
+/// Reply with the output of `uname`.
+fn uname_reply(output: &[u8]) -> Reply {
+    Reply::embed(
+        " ",
+        Color::RED,
+        output.iter().map(|&c| c as char).collect::<String>(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{message, MockDiscord};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn uptime_sends_an_embed() {
+        let mock = MockDiscord::start().await;
+        let msg = message("!uptime", 10, None, 20);
+
+        mock.send(&msg).await.unwrap();
+
+        let embeds = mock.sent_embeds();
+        assert_eq!(embeds.len(), 1);
+        assert_eq!(embeds[0]["title"], "UPTIME");
+        assert!(embeds[0]["description"]
+            .as_str()
+            .unwrap()
+            .starts_with("Up for 0 days"));
+    }
+
+    #[test]
+    fn uptime_is_split_in_units() {
+        let reply = uptime_reply(2 * 86400 + 3 * 3600 + 4 * 60 + 5);
+
+        assert_eq!(
+            reply.embed.unwrap().description,
+            "Up for 2 days 3 hours 4 minutes 5 seconds"
+        );
+    }
 }
@@ -0,0 +1,403 @@
+use serenity::{
+    async_trait,
+    client::bridge::gateway::GatewayIntents,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{
+        channel::Message,
+        guild::Member,
+        id::{ChannelId, GuildId},
+        user::User,
+    },
+    prelude::*,
+    utils::Colour as Color,
+};
+
+use crate::{module::Module, reply::Reply, storage::Greeting, types::StorageContainer};
+
+#[group]
+#[commands(welcome, goodbye, rules)]
+struct Welcome;
+
+/// Welcome and goodbye messages, and the rules sent to new members.
+///
+/// Needs the privileged server members intent to be told about joins and leaves.
+pub struct WelcomeModule;
+
+#[async_trait]
+impl Module for WelcomeModule {
+    fn name(&self) -> &'static str {
+        "welcome"
+    }
+
+    fn groups(&self) -> Vec<&'static CommandGroup> {
+        vec![&WELCOME_GROUP]
+    }
+
+    fn intents(&self) -> GatewayIntents {
+        GatewayIntents::GUILD_MEMBERS
+    }
+
+    async fn guild_member_addition(&self, ctx: &Context, guild_id: GuildId, member: &Member) {
+        let (welcome, rules) = match ctx.data.read().await.get::<StorageContainer>() {
+            Some(storage) => match storage.read().await.guild(guild_id) {
+                Some(guild) => (guild.welcome.clone(), guild.rules.clone()),
+                None => return,
+            },
+            None => return,
+        };
+        let (guild_name, member_count) = guild_info(ctx, guild_id).await;
+
+        if let Some(welcome) = welcome {
+            let text = render(&welcome.template, &member.user, &guild_name, member_count);
+            greet(ctx, &welcome, "WELCOME", text).await;
+        }
+
+        if let Some(rules) = rules.filter(|_| !member.user.bot) {
+            let reply = Reply::embed(
+                format!("RULES OF {}", guild_name.to_uppercase()),
+                Color::BLUE,
+                rules,
+            );
+            let result = match member.user.create_dm_channel(ctx).await {
+                Ok(channel) => reply.send_to(ctx, channel.id).await,
+                Err(why) => Err(why.into()),
+            };
+            if let Err(why) = result {
+                eprintln!("Failed to send the rules to {}: {}", member.user.tag(), why);
+            }
+        }
+    }
+
+    async fn guild_member_removal(
+        &self, ctx: &Context, guild_id: GuildId, user: &User, _member: Option<&Member>,
+    ) {
+        let goodbye = match ctx.data.read().await.get::<StorageContainer>() {
+            Some(storage) => storage
+                .read()
+                .await
+                .guild(guild_id)
+                .and_then(|g| g.goodbye.clone()),
+            None => None,
+        };
+
+        if let Some(goodbye) = goodbye {
+            let (guild_name, member_count) = guild_info(ctx, guild_id).await;
+            let text = render(&goodbye.template, user, &guild_name, member_count);
+            greet(ctx, &goodbye, "GOODBYE", text).await;
+        }
+    }
+}
+
+/// Set the message posted when a member joins.
+///
+/// The message can use `{user}`, `{name}`, `{guild}` and `{member_count}`, and is posted
+/// in an embed if it starts with `embed`. Without arguments it shows the current message,
+/// and `off` stops posting it.
+#[command]
+#[only_in(guild)]
+#[required_permissions("MANAGE_GUILD")]
+#[usage = "welcome [#CHANNEL [embed] <MESSAGE ...> | off]"]
+#[example = "welcome #general Welcome to {guild}, {user}!"]
+async fn welcome(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    set_greeting(ctx, msg, args, true).await
+}
+
+/// Set the message posted when a member leaves.
+///
+/// The message can use `{user}`, `{name}`, `{guild}` and `{member_count}`, and is posted
+/// in an embed if it starts with `embed`. Without arguments it shows the current message,
+/// and `off` stops posting it.
+#[command]
+#[only_in(guild)]
+#[required_permissions("MANAGE_GUILD")]
+#[usage = "goodbye [#CHANNEL [embed] <MESSAGE ...> | off]"]
+#[example = "goodbye #general {name} left, we are now {member_count}."]
+async fn goodbye(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    set_greeting(ctx, msg, args, false).await
+}
+
+/// Set the rules sent in a direct message to every member who joins.
+///
+/// Without arguments it shows the current rules, and `off` stops sending them.
+#[command]
+#[only_in(guild)]
+#[required_permissions("MANAGE_GUILD")]
+#[usage = "rules [<RULES ...> | off]"]
+async fn rules(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let storage = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(s) => s.clone(),
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    let text = args.rest().trim();
+    if text.is_empty() {
+        let rules = storage
+            .read()
+            .await
+            .guild(guild_id)
+            .and_then(|g| g.rules.clone());
+        let text = rules.unwrap_or_else(|| "No rules are sent to new members.".to_string());
+        return Reply::embed("RULES", Color::BLUE, text)
+            .send(ctx, msg)
+            .await;
+    }
+
+    let rules = if text == "off" {
+        None
+    } else {
+        Some(text.to_string())
+    };
+
+    {
+        let mut storage = storage.write().await;
+        storage.guild_mut(guild_id).rules = rules.clone();
+        storage.save()?;
+    }
+
+    let text = match rules {
+        Some(_) => "The rules are now sent to new members.",
+        None => "The rules are no longer sent to new members.",
+    };
+    Reply::embed("RULES", Color::TEAL, text)
+        .send(ctx, msg)
+        .await
+}
+
+async fn set_greeting(ctx: &Context, msg: &Message, args: Args, welcome: bool) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    let title = if welcome { "WELCOME" } else { "GOODBYE" };
+
+    let storage = match ctx.data.read().await.get::<StorageContainer>() {
+        Some(s) => s.clone(),
+        None => {
+            let _ = msg
+                .reply(&ctx, "There was a problem getting the storage")
+                .await?;
+
+            return Ok(());
+        },
+    };
+
+    if args.is_empty() {
+        let greeting = storage.read().await.guild(guild_id).and_then(|g| {
+            if welcome {
+                g.welcome.clone()
+            } else {
+                g.goodbye.clone()
+            }
+        });
+        let text = match greeting {
+            Some(g) => format!(
+                "Posted in <#{}>{}:\n{}",
+                g.channel,
+                if g.embed { " in an embed" } else { "" },
+                g.template
+            ),
+            None => "No message is posted.".to_string(),
+        };
+        return Reply::embed(title, Color::BLUE, text).send(ctx, msg).await;
+    }
+
+    let greeting = match parse_greeting(args.rest()) {
+        Ok(greeting) => greeting,
+        Err(why) => return Reply::embed(" ", Color::RED, why).send(ctx, msg).await,
+    };
+
+    {
+        let mut storage = storage.write().await;
+        let guild = storage.guild_mut(guild_id);
+        if welcome {
+            guild.welcome = greeting.clone();
+        } else {
+            guild.goodbye = greeting.clone();
+        }
+        storage.save()?;
+    }
+
+    let text = match greeting {
+        Some(g) => format!("The message is now posted in <#{}>.", g.channel),
+        None => "The message is no longer posted.".to_string(),
+    };
+    Reply::embed(title, Color::TEAL, text).send(ctx, msg).await
+}
+
+/// Post a greeting in its channel.
+async fn greet(ctx: &Context, greeting: &Greeting, title: &str, text: String) {
+    let reply = if greeting.embed {
+        Reply::embed(title, Color::TEAL, text)
+    } else {
+        Reply::text(text)
+    };
+
+    if let Err(why) = reply.send_to(ctx, ChannelId(greeting.channel)).await {
+        eprintln!("Failed to post a greeting in {}: {}", greeting.channel, why);
+    }
+}
+
+/// Name and member count of a guild, if it is cached.
+async fn guild_info(ctx: &Context, guild_id: GuildId) -> (String, Option<u64>) {
+    match guild_id.to_guild_cached(&ctx.cache).await {
+        Some(guild) => (guild.name, Some(guild.member_count)),
+        None => ("the server".to_string(), None),
+    }
+}
+
+/// Parse the arguments of `welcome` and `goodbye`: `off`, or a channel, an optional
+/// `embed` and the template.
+fn parse_greeting(args: &str) -> Result<Option<Greeting>, String> {
+    let args = args.trim();
+    if args == "off" {
+        return Ok(None);
+    }
+
+    let mut parts = args.splitn(2, char::is_whitespace);
+    let channel = parts.next().unwrap_or_default();
+    let channel = match channel.parse::<ChannelId>() {
+        Ok(id) => id,
+        Err(_) => return Err(format!("Invalid channel: `{}`", channel)),
+    };
+
+    let mut template = parts.next().unwrap_or_default().trim_start();
+    let mut embed = false;
+    if template == "embed" || template.starts_with("embed ") || template.starts_with("embed\n") {
+        embed = true;
+        template = template["embed".len()..].trim_start();
+    }
+
+    if template.is_empty() {
+        return Err("Missing the message.".to_string());
+    }
+
+    Ok(Some(Greeting {
+        channel: channel.0,
+        template: template.to_string(),
+        embed,
+    }))
+}
+
+/// Fill the placeholders of a greeting template.
+///
+/// - `{user}`: mention of the member
+/// - `{name}`: name and discriminator of the member
+/// - `{guild}`: name of the guild
+/// - `{member_count}`: number of members of the guild, or `?` if it is unknown
+pub fn render(template: &str, user: &User, guild: &str, member_count: Option<u64>) -> String {
+    let member_count = member_count.map_or_else(|| "?".to_string(), |c| c.to_string());
+
+    template
+        .replace("{user}", &format!("<@{}>", user.id))
+        .replace("{name}", &user.tag())
+        .replace("{guild}", guild)
+        .replace("{member_count}", &member_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{self, MockDiscord, DM_CHANNEL};
+
+    use super::*;
+
+    const GUILD: u64 = 30;
+
+    #[tokio::test]
+    async fn joins_are_welcomed_and_get_the_rules() {
+        let mock = MockDiscord::start().await;
+        mock.add_guild(GUILD, 20, &[]).await;
+        {
+            let data = mock.ctx.data.read().await;
+            let mut storage = data.get::<StorageContainer>().unwrap().write().await;
+            let guild = storage.guild_mut(GuildId(GUILD));
+            guild.welcome = Some(Greeting {
+                channel:  99,
+                template: "Hi {user}, welcome to {guild}! You are member #{member_count}."
+                    .to_string(),
+                embed:    false,
+            });
+            guild.rules = Some("Be nice.".to_string());
+        }
+
+        WelcomeModule
+            .guild_member_addition(&mock.ctx, GuildId(GUILD), &testing::member(GUILD, 50))
+            .await;
+
+        assert_eq!(mock.sent_contents(), vec!["Hi <@50>, welcome to Mock \
+                                               guild! You are member #1."
+            .to_string()]);
+        let embeds = mock.sent_embeds();
+        assert_eq!(embeds[0]["title"], "RULES OF MOCK GUILD");
+        assert_eq!(embeds[0]["description"], "Be nice.");
+        assert!(mock
+            .requests()
+            .iter()
+            .any(|r| r.path == format!("/channels/{}/messages", DM_CHANNEL)));
+    }
+
+    #[tokio::test]
+    async fn nothing_is_posted_without_a_goodbye() {
+        let mock = MockDiscord::start().await;
+        let member = testing::member(GUILD, 50);
+
+        WelcomeModule
+            .guild_member_removal(&mock.ctx, GuildId(GUILD), &member.user, None)
+            .await;
+
+        assert!(mock.requests().is_empty());
+    }
+
+    #[test]
+    fn templates_fill_the_placeholders() {
+        let user = testing::member(GUILD, 50).user;
+
+        assert_eq!(
+            render(
+                "{user} ({name}) left {guild}, {member_count} left",
+                &user,
+                "Rust",
+                None
+            ),
+            "<@50> (user50#0001) left Rust, ? left"
+        );
+    }
+
+    #[test]
+    fn greetings_are_parsed() {
+        assert_eq!(parse_greeting("off"), Ok(None));
+        assert_eq!(
+            parse_greeting("<#99> embed Hello {user}"),
+            Ok(Some(Greeting {
+                channel:  99,
+                template: "Hello {user}".to_string(),
+                embed:    true,
+            }))
+        );
+        assert_eq!(
+            parse_greeting("99 embedded {user}").map(|g| g.map(|g| g.embed)),
+            Ok(Some(false))
+        );
+        assert_eq!(
+            parse_greeting("general Hi"),
+            Err("Invalid channel: `general`".to_string())
+        );
+        assert_eq!(
+            parse_greeting("<#99> embed"),
+            Err("Missing the message.".to_string())
+        );
+    }
+}